@@ -0,0 +1,76 @@
+//! Render a manifest on several daemons, writing each export to a folder:
+//!
+//!     cargo run --example coordinator --features coordinator -- \
+//!         manifest.json out/ 10.0.0.5:8080 10.0.0.6:8080 /tmp/wry-bokeh-render.sock
+//!
+//! The manifest is a JSON array of `{"name": "fig1", "request": {...}}`, each
+//! request the body `examples/http_server.rs` takes. A daemon is a
+//! `RenderService` at `host:port`, sent two jobs at once, or a `serve_ipc`
+//! socket path, sent one. Exits with 1 if any job failed.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use wry_bokeh_helper::coordinator::{Coordinator, Daemon, Job};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [manifest, out, daemons @ ..] = args.as_slice() else {
+        println!("coordinator: usage: coordinator MANIFEST OUT_DIR DAEMON...");
+        return ExitCode::FAILURE;
+    };
+    let jobs: Vec<Job> = match std::fs::read_to_string(manifest)
+        .map_err(|e| e.to_string())
+        .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+    {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            println!("coordinator: reading {} failed: {}", manifest, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let coordinator = daemons
+        .iter()
+        .fold(Coordinator::new(), |coordinator, daemon| {
+            match Path::new(daemon).exists() || !daemon.contains(':') {
+                true => coordinator.daemon(Daemon::Ipc(PathBuf::from(daemon)), 1),
+                false => coordinator.daemon(Daemon::Http(daemon.clone()), 2),
+            }
+        });
+    let out = Path::new(out);
+    if let Err(e) = std::fs::create_dir_all(out) {
+        println!("coordinator: creating {} failed: {}", out.display(), e);
+        return ExitCode::FAILURE;
+    }
+    let mut failed = 0;
+    for report in coordinator.run(jobs).await {
+        let daemon = report.daemon.as_deref().unwrap_or("no daemon");
+        match report.result {
+            Ok(export) => {
+                let extension = match export.mime_type.as_str() {
+                    "application/pdf" => "pdf",
+                    "image/jpeg" => "jpg",
+                    "image/webp" => "webp",
+                    _ => "png",
+                };
+                let path = out.join(format!("{}.{}", report.name, extension));
+                match std::fs::write(&path, &export.bytes) {
+                    Ok(()) => println!("{}: {} ({})", report.name, path.display(), daemon),
+                    Err(e) => {
+                        failed += 1;
+                        println!("{}: writing {} failed: {}", report.name, path.display(), e);
+                    }
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("{}: {} after {} attempts", report.name, e, report.attempts);
+            }
+        }
+    }
+    match failed {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
@@ -0,0 +1,34 @@
+//! Run the Bokeh export service on a port, with a pool sized to the host:
+//!
+//!     cargo run --example http_server --features http-server -- 0.0.0.0:8080
+//!     curl --data '{"document": ..., "dpi": 192}' http://localhost:8080/render > plot.png
+
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use tokio::net::TcpListener;
+use wry_bokeh_helper::http_server::RenderService;
+use wry_bokeh_helper::prelude::*;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let listener = match TcpListener::bind(&address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("http_server: binding {} failed: {}", address, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let pool = Arc::new(RendererPool::with_auto_size(None, RenderOptions::default()));
+    println!("http_server: {} renders at once on {}", pool.size(), address);
+    match RenderService::new(pool).serve(listener).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            println!("http_server: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! Render a built-in fixture to PNG and PDF and check the exports, to verify
+//! that an installed package works on the target machine. Exits non-zero on
+//! failure. An optional argument is a local bundle folder to render with
+//! instead of the CDN:
+//!
+//!     cargo run --example smoke -- /path/to/bokeh/dist
+
+use std::process::ExitCode;
+
+use wry_bokeh_helper::diagnostics::smoke_test;
+use wry_bokeh_helper::resources::BokehResource;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let resource = match std::env::args().nth(1).map(BokehResource::local).transpose() {
+        Ok(resource) => resource,
+        Err(e) => {
+            println!("smoke: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match smoke_test(resource).await {
+        Ok(()) => {
+            println!("smoke: ok");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            println!("smoke: FAILED: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
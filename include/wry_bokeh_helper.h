@@ -0,0 +1,39 @@
+/* C API of wry_bokeh_helper, built with the `ffi` feature. See src/ffi.rs. */
+
+#ifndef WRY_BOKEH_HELPER_H
+#define WRY_BOKEH_HELPER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct WbhRenderer WbhRenderer;
+
+typedef struct WbhRenderOptions {
+    double dpi;
+} WbhRenderOptions;
+
+/* Why the last failing call on this thread failed, or NULL. */
+const char *wbh_last_error(void);
+
+/* A renderer loading Bokeh from `resource_dir`, or the CDN if NULL; NULL on failure. */
+WbhRenderer *wbh_renderer_new(const char *resource_dir);
+void wbh_renderer_free(WbhRenderer *renderer);
+
+/* 0 on success with the export in `*out_buf` and `*out_len`, -1 on failure.
+ * `options` may be NULL for 300 DPI. */
+int wbh_render_png(const WbhRenderer *renderer, const uint8_t *json, size_t len,
+                   const WbhRenderOptions *options, uint8_t **out_buf, size_t *out_len);
+/* `format` is png, jpeg, webp or pdf, or their MIME type. */
+int wbh_render(const WbhRenderer *renderer, const uint8_t *json, size_t len, const char *format,
+               const WbhRenderOptions *options, uint8_t **out_buf, size_t *out_len);
+void wbh_buffer_free(uint8_t *buf, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,214 @@
+//! Reads the files of a `BokehArchiveResource`: zip archives, stored or
+//! deflated, and gzipped tarballs. Files are read straight out of the archive
+//! on every request, so nothing is unpacked where it could be modified.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+
+const END_OF_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const DIRECTORY_ENTRY_SIGNATURE: u32 = 0x0201_4b50;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+
+/// A file listed in the central directory of a zip archive.
+struct ZipEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    header_offset: u64,
+}
+
+/// The files in the archive at `path`, folders excluded.
+pub(crate) fn file_names(path: &Path) -> io::Result<Vec<String>> {
+    if is_tarball(path) {
+        let mut names = Vec::new();
+        walk_tarball(path, |name, _| {
+            names.push(name.to_string());
+            Ok(false)
+        })?;
+        return Ok(names);
+    }
+    let mut file = File::open(path)?;
+    Ok(zip_entries(&mut file)?
+        .into_iter()
+        .map(|entry| entry.name)
+        .filter(|name| !name.ends_with('/'))
+        .collect())
+}
+
+/// The content of the file `name` in the archive at `path`.
+pub(crate) fn read_file(path: &Path, name: &str) -> io::Result<Vec<u8>> {
+    let not_found = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not in {}", name, path.display()),
+        )
+    };
+    if is_tarball(path) {
+        let mut found = None;
+        walk_tarball(path, |entry, content| {
+            if entry != name {
+                return Ok(false);
+            }
+            let mut bytes = Vec::new();
+            content.read_to_end(&mut bytes)?;
+            found = Some(bytes);
+            Ok(true)
+        })?;
+        return found.ok_or_else(not_found);
+    }
+    let mut file = File::open(path)?;
+    let entry = zip_entries(&mut file)?
+        .into_iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(not_found)?;
+    read_zip_entry(&mut file, &entry)
+}
+
+fn is_tarball(path: &Path) -> bool {
+    let name = path.to_string_lossy().to_ascii_lowercase();
+    name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+fn zip_entries(file: &mut File) -> io::Result<Vec<ZipEntry>> {
+    // The end of central directory record is the last 22 bytes, followed by a
+    // comment of up to 64 KiB.
+    let len = file.seek(SeekFrom::End(0))?;
+    let tail_len = len.min(22 + u64::from(u16::MAX));
+    if tail_len < 22 {
+        return Err(invalid("not a zip archive".to_string()));
+    }
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = vec![0; tail_len as usize];
+    file.read_exact(&mut tail)?;
+    let end = (0..=tail.len() - 22)
+        .rev()
+        .find(|&at| u32_at(&tail, at) == END_OF_DIRECTORY_SIGNATURE)
+        .ok_or_else(|| invalid("not a zip archive".to_string()))?;
+    let count = u16_at(&tail, end + 10);
+    let directory_size = u32_at(&tail, end + 12);
+    let directory_offset = u32_at(&tail, end + 16);
+    if count == u16::MAX || directory_offset == u32::MAX {
+        return Err(invalid("zip64 archives are not supported".to_string()));
+    }
+
+    file.seek(SeekFrom::Start(u64::from(directory_offset)))?;
+    let mut directory = vec![0; directory_size as usize];
+    file.read_exact(&mut directory)?;
+    let mut entries = Vec::with_capacity(usize::from(count));
+    let mut at = 0;
+    for _ in 0..count {
+        if directory.len() < at + 46 || u32_at(&directory, at) != DIRECTORY_ENTRY_SIGNATURE {
+            return Err(invalid("corrupt zip central directory".to_string()));
+        }
+        let name_len = usize::from(u16_at(&directory, at + 28));
+        let extra_len = usize::from(u16_at(&directory, at + 30));
+        let comment_len = usize::from(u16_at(&directory, at + 32));
+        let name = directory
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(|| invalid("corrupt zip central directory".to_string()))?;
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(&directory, at + 10),
+            crc: u32_at(&directory, at + 16),
+            compressed_size: u64::from(u32_at(&directory, at + 20)),
+            size: u64::from(u32_at(&directory, at + 24)),
+            header_offset: u64::from(u32_at(&directory, at + 42)),
+        });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+fn read_zip_entry(file: &mut File, entry: &ZipEntry) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(entry.header_offset))?;
+    let mut header = [0; 30];
+    file.read_exact(&mut header)?;
+    if u32_at(&header, 0) != LOCAL_HEADER_SIGNATURE {
+        return Err(invalid(format!("corrupt zip entry {}", entry.name)));
+    }
+    let skip = i64::from(u16_at(&header, 26)) + i64::from(u16_at(&header, 28));
+    file.seek(SeekFrom::Current(skip))?;
+
+    let data = file.take(entry.compressed_size);
+    let mut content = Vec::with_capacity(entry.size as usize);
+    match entry.method {
+        0 => BufReader::new(data).read_to_end(&mut content)?,
+        8 => DeflateDecoder::new(data).read_to_end(&mut content)?,
+        method => {
+            return Err(invalid(format!(
+                "{} uses unsupported compression method {}",
+                entry.name, method
+            )))
+        }
+    };
+    if crc32fast::hash(&content) != entry.crc {
+        return Err(invalid(format!("{} fails its checksum", entry.name)));
+    }
+    Ok(content)
+}
+
+/// Hand the name and content of each regular file in the gzipped tarball at
+/// `path` to `visit`, until it returns true.
+fn walk_tarball(
+    path: &Path,
+    mut visit: impl FnMut(&str, &mut dyn Read) -> io::Result<bool>,
+) -> io::Result<()> {
+    let mut reader = GzDecoder::new(BufReader::new(File::open(path)?));
+    let mut header = [0; 512];
+    loop {
+        match reader.read_exact(&mut header) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+        // Two zero blocks end the archive.
+        if header.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+        let size = tar_octal(&header[124..136])?;
+        let mut name = tar_string(&header[0..100]);
+        // ustar keeps the folders of long names in a prefix.
+        if &header[257..262] == b"ustar" {
+            let prefix = tar_string(&header[345..500]);
+            if !prefix.is_empty() {
+                name = format!("{}/{}", prefix, name);
+            }
+        }
+        let name = name.trim_start_matches("./");
+
+        let mut content = (&mut reader).take(size);
+        if matches!(header[156], b'0' | 0) && visit(name, &mut content)? {
+            return Ok(());
+        }
+        io::copy(&mut content, &mut io::sink())?;
+        let padding = (512 - size % 512) % 512;
+        io::copy(&mut (&mut reader).take(padding), &mut io::sink())?;
+    }
+}
+
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn tar_octal(field: &[u8]) -> io::Result<u64> {
+    let digits = tar_string(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    u64::from_str_radix(digits, 8).map_err(|_| invalid("corrupt tar header".to_string()))
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,314 @@
+//! Render a Bokeh document to an image file from the command line, for
+//! pipelines in Python, R or the shell that don't link the crate:
+//!
+//!     python plot.py | wry-bokeh-render --dpi 192 --out plot.png
+//!     wry-bokeh-render plot.json --format pdf --resource-dir bokeh/dist --out plot.pdf
+//!
+//! Exits with 1 if the render fails or times out, and 2 on bad arguments.
+//!
+//! With `--serve-stdio` it keeps one renderer warm and answers newline-delimited
+//! JSON-RPC 2.0 requests on stdin with one line each on stdout, until stdin
+//! closes or a `shutdown` request:
+//!
+//!     {"jsonrpc": "2.0", "id": 1, "method": "warm_up"}
+//!     {"jsonrpc": "2.0", "id": 2, "method": "render",
+//!      "params": {"document": {...}, "dpi": 192, "format": "pdf",
+//!                 "options": {"page_size": "a4"}}}
+//!     {"jsonrpc": "2.0", "id": 3, "method": "shutdown"}
+//!
+//! A render answers `{"data": <base64>, "mime_type", "width", "height"}`.
+//! The params are a `wire::WireRequest`, with `dpi` defaulting to `--dpi`.
+//! `--timeout` applies to each render.
+
+use std::io::{BufRead, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use wry_bokeh_helper::prelude::*;
+use wry_bokeh_helper::wire::WireRequest;
+
+const USAGE: &str = "\
+Usage: wry-bokeh-render [OPTIONS] [FILE]
+
+Renders the document JSON in FILE, or read from stdin without FILE or with `-`.
+
+Options:
+  --dpi <DPI>           Resolution of the export [default: 96]
+  --format <FORMAT>     png, jpeg, webp or pdf [default: from --out, else png]
+  --resource-dir <DIR>  Load Bokeh from a local bundle folder instead of the CDN
+  --timeout <SECONDS>   Fail if the render takes longer
+  --out <PATH>          Write the export here instead of to stdout
+  --serve-stdio         Answer JSON-RPC render requests on stdin and stdout
+  -h, --help            Print this help";
+
+#[derive(Default)]
+struct Args {
+    input: Option<PathBuf>,
+    dpi: Option<f64>,
+    format: Option<String>,
+    resource_dir: Option<PathBuf>,
+    timeout: Option<Duration>,
+    out: Option<PathBuf>,
+    serve_stdio: bool,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut parsed = Args::default();
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        // `--flag=value` as well as `--flag value`.
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| match inline {
+            Some(value) => Ok(value.to_string()),
+            None => args.next().ok_or_else(|| format!("{} needs a value", name)),
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--dpi" => {
+                let dpi = value("--dpi")?;
+                parsed.dpi = Some(dpi.parse().map_err(|_| format!("invalid --dpi {}", dpi))?);
+            }
+            "--format" => parsed.format = Some(value("--format")?),
+            "--resource-dir" => parsed.resource_dir = Some(value("--resource-dir")?.into()),
+            "--timeout" => {
+                let timeout = value("--timeout")?;
+                let seconds = timeout
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+                    .ok_or_else(|| format!("invalid --timeout {}", timeout))?;
+                parsed.timeout = Some(Duration::from_secs_f64(seconds));
+            }
+            "--out" => parsed.out = Some(value("--out")?.into()),
+            "--serve-stdio" => parsed.serve_stdio = true,
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            _ if parsed.input.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => parsed.input = Some(arg.into()),
+        }
+    }
+    Ok(Some(parsed))
+}
+
+/// The format named by `--format`, or by the extension of `--out`.
+fn render_format(args: &Args) -> Result<RenderFormat, String> {
+    let name = match (&args.format, &args.out) {
+        (Some(format), _) => format.clone(),
+        (None, Some(out)) => out
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("png")
+            .to_string(),
+        (None, None) => "png".to_string(),
+    };
+    format_named(&name)
+}
+
+fn format_named(name: &str) -> Result<RenderFormat, String> {
+    RenderFormat::from_name(name).ok_or_else(|| format!("unsupported format {}", name))
+}
+
+fn read_input(input: Option<&PathBuf>) -> std::io::Result<String> {
+    match input {
+        Some(path) if path.as_os_str() != "-" => std::fs::read_to_string(path),
+        _ => {
+            let mut json_data = String::new();
+            std::io::stdin().read_to_string(&mut json_data)?;
+            Ok(json_data)
+        }
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("wry-bokeh-render: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let format = match render_format(&args) {
+        Ok(format) => format,
+        Err(e) => {
+            eprintln!("wry-bokeh-render: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let resource = match args.resource_dir.as_ref().map(BokehResource::local).transpose() {
+        Ok(resource) => resource,
+        Err(e) => {
+            eprintln!("wry-bokeh-render: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    if args.serve_stdio {
+        return serve_stdio(resource, args.dpi.unwrap_or(96.0), args.timeout);
+    }
+    let json_data = match read_input(args.input.as_ref()) {
+        Ok(json_data) => json_data,
+        Err(e) => {
+            eprintln!("wry-bokeh-render: reading the document failed: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // The render blocks this thread in the webview's event loop, out of reach
+    // of a tokio timer.
+    if let Some(timeout) = args.timeout {
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            eprintln!(
+                "wry-bokeh-render: the render timed out after {} s",
+                timeout.as_secs_f64()
+            );
+            std::process::exit(1);
+        });
+    }
+
+    let dpi = args.dpi.unwrap_or(96.0);
+    let options = RenderOptions::default();
+    let output =
+        match render_bokeh_in_webview(json_data.as_str(), dpi, format, resource, options).await {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("wry-bokeh-render: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+    let written = match &args.out {
+        Some(out) => std::fs::write(out, &output.bytes),
+        None => std::io::stdout().write_all(&output.bytes),
+    };
+    match written {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("wry-bokeh-render: writing the export failed: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// `--serve-stdio`: one renderer kept warm for every request on stdin. The
+/// renderer runs its event loop on this thread, as macOS requires.
+fn serve_stdio(resource: Option<BokehResource>, dpi: f64, timeout: Option<Duration>) -> ExitCode {
+    let served = MainThreadRenderer::new(resource, RenderOptions::default()).run(move |renderer| {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let mut stdout = std::io::stdout();
+        for line in std::io::stdin().lock().lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let rpc = handle_rpc(&renderer, &line, dpi, timeout);
+            let (response, shutdown) = runtime.block_on(rpc);
+            if let Some(response) = response {
+                writeln!(stdout, "{}", response)?;
+                stdout.flush()?;
+            }
+            if shutdown {
+                break;
+            }
+        }
+        Ok::<_, std::io::Error>(())
+    });
+    match served {
+        Ok(Ok(())) => ExitCode::SUCCESS,
+        Ok(Err(e)) => {
+            eprintln!("wry-bokeh-render: {}", e);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("wry-bokeh-render: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Answer one JSON-RPC request: the response, `None` for a notification, and
+/// whether it asked to shut down.
+async fn handle_rpc(
+    renderer: &BokehRenderer,
+    line: &str,
+    dpi: f64,
+    timeout: Option<Duration>,
+) -> (Option<Value>, bool) {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = rpc_error(Value::Null, -32700, format!("Parse error: {}", e));
+            return (Some(error), false);
+        }
+    };
+    let id = request.get("id").cloned();
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    let (result, shutdown) = match request.get("method").and_then(Value::as_str) {
+        Some("render") => (rpc_render(renderer, &params, dpi, timeout).await, false),
+        Some("warm_up") => (
+            renderer
+                .warm_up()
+                .await
+                .map(|()| Value::Null)
+                .map_err(|e| (-32000, e.to_string())),
+            false,
+        ),
+        Some("shutdown") => (Ok(Value::Null), true),
+        Some(method) => (Err((-32601, format!("Method not found: {}", method))), false),
+        None => (Err((-32600, "Invalid Request: no method".to_string())), false),
+    };
+    let response = id.map(|id| match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => rpc_error(id, code, message),
+    });
+    (response, shutdown)
+}
+
+async fn rpc_render(
+    renderer: &BokehRenderer,
+    params: &Value,
+    dpi: f64,
+    timeout: Option<Duration>,
+) -> Result<Value, (i64, String)> {
+    let invalid = |message: String| (-32602, format!("Invalid params: {}", message));
+    let request = WireRequest::deserialize(params)
+        .map_err(|e| invalid(e.to_string()))?
+        .into_request(dpi)
+        .map_err(|e| invalid(e.to_string()))?;
+    let render = renderer.render(&request.json_data, request.dpi, request.format);
+    let output = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, render).await {
+            Ok(output) => output,
+            Err(_) => {
+                // The page is still busy with the document; start the next
+                // request on a fresh webview instead of behind it.
+                renderer.recycle();
+                let message = format!("The render timed out after {} s", timeout.as_secs_f64());
+                return Err((-32000, message));
+            }
+        },
+        None => render.await,
+    }
+    .map_err(|e| (-32000, e.to_string()))?;
+    Ok(json!({
+        "data": base64::engine::general_purpose::STANDARD.encode(&output.bytes),
+        "mime_type": output.format.mime_type(),
+        "width": output.width,
+        "height": output.height,
+    }))
+}
+
+fn rpc_error(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
@@ -1,75 +1,362 @@
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+use base64::Engine;
 use tao::{
+    dpi::LogicalSize,
     event::{Event, StartCause, WindowEvent},
-    event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy},
+    event_loop::{ControlFlow, EventLoopBuilder},
     platform::run_return::EventLoopExtRunReturn,
     window::WindowBuilder,
 };
-use tokio::sync::broadcast::Sender;
-use wry::{
-    http::{self, Request},
-    WebViewBuilder,
-};
+use tokio::sync::{mpsc::unbounded_channel, oneshot};
+use wry::{PageLoadEvent, WebView, WebViewBuilder};
 
 #[cfg(target_os = "windows")]
-use wry::WebContext;
+use wry::{WebContext, WebViewBuilderExtWindows};
+
+use crate::diagnostics::DocumentStats;
+use crate::document::embeddable_json;
+use crate::fonts;
+use crate::offline::{check_offline_document, check_offline_resources, OFFLINE_CSP};
+use crate::pdf::{self, Margins, ReportLayout};
+use crate::pool::acquire_webview_permit;
+use crate::postprocess::encode_multipage_tiff;
+use crate::process::watch_web_process;
+use crate::protocol::{
+    custom_protocol_handler, image_output, ipc_handler, CaptureSlot, EventSink, ResourceFailures,
+};
+use crate::proxy;
+use crate::resources::{cdn_bundle_url, LOCAL_BUNDLES};
+use crate::runtime::webview_error;
+use crate::tiles::Stitcher;
+use crate::version::MIN_SUPPORTED_VERSION;
+
+// The types below moved to their own modules; `bokeh_helpers::` paths keep working.
+pub use crate::options::{
+    document_viewport, Dpi, RenderFormat, RenderInput, RenderOptions, RenderRequest,
+    SettleStrategy, WaitUntil, DEFAULT_BOKEH_LOAD_TIMEOUT, DEFAULT_CONTENT_SECURITY_POLICY,
+    DEFAULT_MATHJAX_TIMEOUT,
+};
+pub use crate::output::{
+    BokehRenderError, ConsoleLevel, ConsoleMessage, RenderOutput, RenderTimings, ResourceFailure,
+};
+pub use crate::protocol::{ExportedImage, UserEvent};
+pub use crate::resources::{
+    BokehArchiveResource, BokehAutoCacheResource, BokehCDNResource, BokehLocalResource,
+    BokehResource, BokehResourceError, CssSource, Resource, ResourceProvider, ScriptSource,
+};
+pub use crate::version::BokehVersion;
+
+/// Page-side render/export functions, evaluated in the page's `<head>`.
+const RENDER_SCRIPT: &str = include_str!("render_bokeh.js");
+
+/// Forwards every `console` call and uncaught error of the page over IPC, ahead
+/// of all other scripts so Bokeh's own warnings are included. Calls made while
+/// a `RenderOptions` hook runs are attributed to it (see `runHook`).
+const CONSOLE_SCRIPT: &str = "
+    const runningHooks = [];
+
+    function forwardConsole(level, message, source) {
+        window.ipc.postMessage(JSON.stringify({ type: 'console', level, message, source }));
+    }
+
+    function formatConsoleValue(value) {
+        if (typeof value === 'string') {
+            return value;
+        }
+        if (value instanceof Error) {
+            return value.stack ?? String(value);
+        }
+        try {
+            return JSON.stringify(value) ?? String(value);
+        } catch (e) {
+            return String(value);
+        }
+    }
+
+    for (const level of ['debug', 'log', 'info', 'warn', 'error']) {
+        const original = console[level];
+        console[level] = (...values) => {
+            original.apply(console, values);
+            const source = runningHooks.length > 0 ? runningHooks[runningHooks.length - 1] : 'page';
+            forwardConsole(level, values.map(formatConsoleValue).join(' '), source);
+        };
+    }
+
+    window.addEventListener('error', (event) => {
+        const location = event.filename ? ` (${event.filename}:${event.lineno}:${event.colno})` : '';
+        forwardConsole('error', `${event.message}${location}`, 'window.onerror');
+    });
+    window.addEventListener('unhandledrejection', (event) => {
+        forwardConsole('error', formatConsoleValue(event.reason), 'unhandledrejection');
+    });
+";
+
+/// Defines the `onerror` handler of the bundle `<script>` and stylesheet `<link>`
+/// tags, and a watchdog for `RenderOptions::bokeh_load_timeout`. Without them a
+/// bundle that fails to load, fails its integrity check or never arrives leaves
+/// the render hanging.
+const BUNDLE_ERROR_SCRIPT: &str = "
+    function resourceLoadFailed(url, reason) {
+        window.ipc.postMessage(JSON.stringify({ type: 'resource_load_failed', url, reason }));
+    }
+    function bundleFailed(element) {
+        const reason = element.integrity
+            ? 'the request failed or the bundle does not match its integrity hash'
+            : 'the request failed';
+        resourceLoadFailed(element.src ?? element.href, reason);
+    }
+    // A bundle from an unreachable host can take minutes to fail.
+    if (pageOptions.bokeh_load_timeout > 0) {
+        setTimeout(() => {
+            if (document.readyState !== 'complete') {
+                resourceLoadFailed(
+                    null,
+                    `the scripts and stylesheets did not load within ${pageOptions.bokeh_load_timeout} ms`,
+                );
+            }
+        }, pageOptions.bokeh_load_timeout);
+    }
+";
+
+fn bokeh_cdn_as_script_html(cdn: &BokehCDNResource, options: &RenderOptions) -> String {
+    cdn.version
+        .bundles()
+        .iter()
+        .map(|bundle| {
+            let integrity = match cdn.integrity.get(*bundle) {
+                Some(hash) => format!(" integrity='{}' crossorigin='anonymous'", hash),
+                None => String::new(),
+            };
+            format!(
+                "<script type='text/javascript' src='{}'{} onerror='window.bundleFailed?.(this)'></script>",
+                proxy::page_url(options, &cdn_bundle_url(&cdn.version, bundle)),
+                integrity
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn bokeh_resource_as_script_html(
+    resource: Option<BokehResource>,
+    options: &RenderOptions,
+) -> String {
+    match resource {
+        Some(BokehResource::CDN(cdn)) => bokeh_cdn_as_script_html(&cdn, options),
+        // Served over the custom protocol, see `custom_protocol_handler`.
+        Some(resource) => resource
+            .bundles()
+            .iter()
+            .map(|bundle| {
+                format!(
+                    "<script type='text/javascript' src='/bokeh-resource-dir/{}' onerror='window.bundleFailed?.(this)'></script>",
+                    bundle
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => bokeh_cdn_as_script_html(
+            &BokehCDNResource {
+                version: BokehVersion::DEFAULT,
+                integrity: HashMap::new(),
+            },
+            options,
+        ),
+    }
+}
 
-pub enum UserEvent {
-    PayloadReceived(String),
+/// The page's Content-Security-Policy, which comes first in the head so that it
+/// covers every resource.
+fn content_security_policy_as_meta_html(options: &RenderOptions) -> String {
+    let policy = match options.offline {
+        true => Some(OFFLINE_CSP),
+        false => options.content_security_policy.as_deref(),
+    };
+    match policy {
+        Some(policy) => format!(
+            "<meta http-equiv='Content-Security-Policy' content=\"{}\">",
+            policy.replace('&', "&amp;").replace('"', "&quot;")
+        ),
+        None => String::new(),
+    }
 }
 
-#[derive(Clone)]
-pub struct BokehCDNResource {
-    pub version: String,
+fn extra_css_as_link_html(options: &RenderOptions) -> String {
+    (0..options.extra_css.len())
+        .map(|index| {
+            format!(
+                "<link rel='stylesheet' href='/extra-css/{}.css' onerror='window.bundleFailed?.(this)'>",
+                index
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
-#[derive(Clone)]
-pub struct BokehLocalResource {
-    pub folder_uri: String,
+fn extra_scripts_as_script_html(options: &RenderOptions) -> String {
+    (0..options.extra_scripts.len())
+        .map(|index| {
+            format!(
+                "<script type='text/javascript' src='/extra-scripts/{}.js' onerror='window.bundleFailed?.(this)'></script>",
+                index
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
-#[derive(Clone)]
-pub enum BokehResource {
-    CDN(BokehCDNResource),
-    Local(BokehLocalResource),
+/// Like `bokeh_resource_as_script_html`, but bundles served over the custom
+/// protocol are fetched and inlined so the resulting page doesn't depend on it.
+fn bokeh_resource_as_inline_script_html(
+    resource: Option<BokehResource>,
+) -> std::io::Result<String> {
+    match resource.as_ref().and_then(BokehResource::provider) {
+        Some(provider) => {
+            let mut html = String::new();
+            for bundle in resource.as_ref().map_or(&LOCAL_BUNDLES[..], BokehResource::bundles) {
+                let content = provider.fetch(bundle)?.content;
+                html.push_str(&format!(
+                    "<script type='text/javascript'>\n{}\n</script>\n",
+                    escape_script_content(&String::from_utf8_lossy(&content))
+                ));
+            }
+            Ok(html)
+        }
+        // Exported pages load the bundles themselves, without the proxy.
+        None => Ok(bokeh_resource_as_script_html(
+            resource,
+            &RenderOptions::default(),
+        )),
+    }
 }
 
-fn ipc_handler(payload: &Request<String>, event_loop_proxy: &EventLoopProxy<UserEvent>) {
-    let _ = event_loop_proxy.send_event(UserEvent::PayloadReceived(payload.body().clone()));
+/// Prevent an inlined script from terminating its enclosing `<script>` tag
+/// early. End tags are matched regardless of case, like the HTML parser does.
+fn escape_script_content(content: &str) -> String {
+    let mut escaped = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("</") {
+        let (head, tail) = rest.split_at(start);
+        escaped.push_str(head);
+        let end_tag = tail
+            .get(2..8)
+            .is_some_and(|name| name.eq_ignore_ascii_case("script"));
+        escaped.push_str(if end_tag { "<\\/" } else { "</" });
+        rest = &tail[2..];
+    }
+    escaped.push_str(rest);
+    escaped
 }
 
-fn bokeh_cdn_as_script_html(version: &str) -> String {
-    format!(
-        "
-        <script type='text/javascript' src='https://cdn.bokeh.org/bokeh/release/bokeh-{}.min.js'></script>
-        <script type='text/javascript' src='https://cdn.bokeh.org/bokeh/release/bokeh-api-{}.min.js'></script>
-        <script type='text/javascript' src='https://cdn.bokeh.org/bokeh/release/bokeh-mathjax-{}.min.js'></script>
-        ",
-        version, version, version
-    )
+/// JSON inlined in a `<script>` block, with every `<` escaped so that neither
+/// an end tag nor a `<!--` in its strings can change how the block is parsed.
+fn escape_json_script_content(json_data: &str) -> String {
+    json_data.replace('<', "\\u003c")
 }
 
-fn bokeh_resource_as_script_html(resource: Option<BokehResource>) -> String {
-    match resource {
-        Some(BokehResource::CDN(BokehCDNResource { version })) => {
-            bokeh_cdn_as_script_html(&version)
-        }
-        Some(BokehResource::Local(_)) => format!(
-            "
-            <script type='text/javascript' src='/bokeh-resource-dir/bokeh.min.js'></script>
-            <script type='text/javascript' src='/bokeh-resource-dir/bokeh-mathjax.min.js'></script>
-            <script type='text/javascript' src='/bokeh-resource-dir/bokeh-api.min.js'></script>
-            "
-        ),
-        None => bokeh_cdn_as_script_html("3.5.2"),
+fn reduced_motion_as_head_html() -> String {
+    "
+    <style>
+        *, *::before, *::after {
+            animation: none !important;
+            transition: none !important;
+            scroll-behavior: auto !important;
+        }
+    </style>
+    <script type='text/javascript'>
+        (() => {
+            const matchMedia = window.matchMedia.bind(window);
+            window.matchMedia = (query) => {
+                if (/prefers-reduced-motion\\s*:\\s*reduce/.test(query)) {
+                    return {
+                        matches: true,
+                        media: query,
+                        onchange: null,
+                        addListener() {},
+                        removeListener() {},
+                        addEventListener() {},
+                        removeEventListener() {},
+                        dispatchEvent() { return false; },
+                    };
+                }
+                return matchMedia(query);
+            };
+        })();
+    </script>
+    "
+    .to_string()
+}
+
+/// Options the page script reads at export time, as its `pageOptions`.
+fn page_options(options: &RenderOptions) -> serde_json::Value {
+    serde_json::json!({
+        "data_images": options.data_image_format.is_some(),
+        "random_seed": options.random_seed,
+        "fonts": fonts::preloaded_fonts(options),
+        "gmap_api_key": options.gmap_api_key,
+        "mathjax_timeout": options
+            .mathjax_timeout
+            .unwrap_or(DEFAULT_MATHJAX_TIMEOUT)
+            .as_millis() as u64,
+        "bokeh_load_timeout": options
+            .bokeh_load_timeout
+            .unwrap_or(DEFAULT_BOKEH_LOAD_TIMEOUT)
+            .as_millis() as u64,
+        "ajax_polls": options.ajax_polls.unwrap_or(1),
+        "wait_until": options.wait_until.as_ref().map(WaitUntil::to_page_option),
+        "settle": options.settle.to_page_option(options.settle_delay),
+        "before_render_js": options.before_render_js,
+        "after_render_js": options.after_render_js,
+        "output_size": options.output_size,
+        "physical_size": options.physical_size,
+        "presentation_scale": options.presentation_scale,
+        "native_resolution": options.native_resolution,
+        "min_bokeh_version": [MIN_SUPPORTED_VERSION.major, MIN_SUPPORTED_VERSION.minor],
+        "binary_transfer": options.binary_transfer,
+        "tile_size": options.tiled.map(|tiled| tiled.tile_size),
+        "preview_dpi": options.preview_sender.as_ref().and(options.preview_dpi),
+    })
+}
+
+/// Markup injected into `<head>` ahead of the Bokeh bundles so it applies before BokehJS runs.
+fn render_options_as_head_html(options: &RenderOptions) -> String {
+    let mut html = format!(
+        "<script type='text/javascript'>const pageOptions = {};</script>",
+        escape_json_script_content(&page_options(options).to_string())
+    );
+    html.push_str(&fonts::fonts_as_head_html(options));
+    html.push_str(&proxy::proxy_as_head_html(options));
+    if options.reduced_motion {
+        html.push_str(&reduced_motion_as_head_html());
     }
+    html
+}
+
+pub(crate) fn build_bokeh_render_html(
+    resource: Option<BokehResource>,
+    options: &RenderOptions,
+) -> String {
+    render_page_html(&bokeh_resource_as_script_html(resource, options), "", options)
+}
+
+/// The render page for charts of a `ChartEngine`, whose libraries come first
+/// in `RenderOptions::extra_scripts`, with `engine_script` defining
+/// `renderChart`.
+pub(crate) fn build_chart_render_html(engine_script: &str, options: &RenderOptions) -> String {
+    render_page_html("", engine_script, options)
 }
 
-fn build_bokeh_render_html(resource: Option<BokehResource>) -> String {
+/// The render page, loading a chart library with `library_html` and defining
+/// the page script and `engine_script` after all other scripts.
+fn render_page_html(library_html: &str, engine_script: &str, options: &RenderOptions) -> String {
     format!(
         "
         <html>
             <head>
+            {}
             <style>
                 html, body {{
                     box-sizing: border-box;
@@ -78,32 +365,19 @@ fn build_bokeh_render_html(resource: Option<BokehResource>) -> String {
                     margin: 0;
                     padding: 0;
                 }}
+                #root {{
+                    height: 100%;
+                }}
             </style>
+            <script type='text/javascript'>{}</script>
+            {}
+            <script type='text/javascript'>{}</script>
+            {}
+            {}
             {}
             <script type='text/javascript'>
-                function renderBokeh(json, dpi, typ) {{
-                    const data = JSON.parse(json);
-                    const rootId = data['root_id'];
-                    if (window.Bokeh === undefined) {{
-                        throw new Error('Bokeh is not loaded');
-                    }}
-                    let devicePixelRatioBase = window.devicePixelRatio;
-                    window.devicePixelRatio = devicePixelRatioBase * dpi / 96;
-                    const container = document.getElementById('root');
-                    window.Bokeh.embed.embed_item(data, container).then((viewManager) => {{
-                        const view = viewManager.get_by_id(rootId);
-                        const canvas = view.export().canvas;
-                        const ctx = canvas.getContext('2d');
-                        ctx.globalCompositeOperation = 'destination-over';
-                        ctx.fillStyle = '#ffffff';
-                        ctx.fillRect(0, 0, canvas.width, canvas.height);
-                        container.style.width = canvas.width + 'px';
-                        container.style.height = canvas.height + 'px';
-                        const dataURL = canvas.toDataURL(typ, 1.0);
-                        window.devicePixelRatio = devicePixelRatioBase;
-                        window.ipc.postMessage(dataURL);
-                    }});
-                }}
+            {}
+            {}
             </script>
             </head>
             <body>
@@ -111,137 +385,973 @@ fn build_bokeh_render_html(resource: Option<BokehResource>) -> String {
             </body>
         </html>
         ",
-        bokeh_resource_as_script_html(resource)
+        content_security_policy_as_meta_html(options),
+        CONSOLE_SCRIPT,
+        render_options_as_head_html(options),
+        BUNDLE_ERROR_SCRIPT,
+        extra_css_as_link_html(options),
+        library_html,
+        extra_scripts_as_script_html(options),
+        RENDER_SCRIPT,
+        engine_script
     )
 }
 
-fn custom_protocol_handler(
-    request: Request<Vec<u8>>,
-    resource: &Option<BokehResource>,
-) -> Result<http::Response<Vec<u8>>, Box<dyn std::error::Error>> {
-    let uri = request.uri().path();
+/// Build a self-contained, interactive HTML page for a `json_item` payload,
+/// the equivalent of Python's `bokeh.embed.file_html`.
+pub fn export_bokeh_html(
+    json_data: &str,
+    resource: Option<BokehResource>,
+) -> std::io::Result<String> {
+    Ok(format!(
+        "
+        <!DOCTYPE html>
+        <html>
+            <head>
+            <meta charset='utf-8'>
+            <style>
+                html, body {{
+                    box-sizing: border-box;
+                    display: flow-root;
+                    height: 100%;
+                    margin: 0;
+                    padding: 0;
+                }}
+            </style>
+            {}
+            </head>
+            <body>
+            <div id='root'></div>
+            <script type='application/json' id='bokeh-json-item'>{}</script>
+            <script type='text/javascript'>
+                const item = JSON.parse(document.getElementById('bokeh-json-item').textContent);
+                window.Bokeh.embed.embed_item(item, 'root');
+            </script>
+            </body>
+        </html>
+        ",
+        bokeh_resource_as_inline_script_html(resource)?,
+        escape_json_script_content(json_data)
+    ))
+}
 
-    if uri == "/" {
-        return http::Response::builder()
-            .header(http::header::CONTENT_TYPE, "text/html")
-            .body(build_bokeh_render_html(resource.clone()).into_bytes())
-            .map_err(Into::into);
+/// CSS padding the page applies around the plot when printing. Only WKWebView's
+/// `createPDF` lacks printer margins, so everywhere else this is left to the printer.
+pub(crate) fn print_padding(margins: &Margins) -> String {
+    if cfg!(target_os = "macos") {
+        format!(
+            "{}mm {}mm {}mm {}mm",
+            margins.top, margins.right, margins.bottom, margins.left
+        )
+    } else {
+        "0".to_string()
     }
+}
+
+pub(crate) fn as_data_url(mime_type: &str, content: &[u8]) -> String {
+    format!(
+        "data:{};base64,{}",
+        mime_type,
+        base64::engine::general_purpose::STANDARD.encode(content)
+    )
+}
 
-    let path = PathBuf::from(uri);
+/// A PDF print in progress, started when the page reported itself printable.
+pub(crate) struct PendingPdf {
+    width: f64,
+    height: f64,
+    timings: RenderTimings,
+    started: Instant,
+}
 
-    if path.parent() == Some(&PathBuf::from("/bokeh-resource-dir")) {
-        match resource {
-            Some(BokehResource::Local(BokehLocalResource { folder_uri })) => {
-                let file_name = path.file_name().unwrap().to_str().unwrap();
-                let file_path = PathBuf::from(folder_uri).join(file_name);
-                let content = std::fs::read(file_path)?;
-                let mimetype = mime_guess::from_path(path)
-                    .first()
-                    .map(|mime| mime.to_string())
-                    .unwrap_or("text/plain".to_string());
+impl PendingPdf {
+    /// Print the page for a `RenderFormat::Pdf` render; other formats are left alone.
+    pub(crate) fn start(
+        webview: &WebView,
+        format: &RenderFormat,
+        width: f64,
+        height: f64,
+        timings: RenderTimings,
+        events: EventSink,
+    ) -> Option<Self> {
+        let RenderFormat::Pdf { page_size, margins } = format else {
+            return None;
+        };
+        let page_mm = page_size.to_mm((width, height), margins);
+        let started = Instant::now();
+        pdf::print_to_pdf(webview, page_mm, margins, events);
+        Some(PendingPdf {
+            width,
+            height,
+            timings,
+            started,
+        })
+    }
 
-                #[cfg(target_os = "windows")]
-                let cors = "https://wry.render-bokeh".to_string();
+    pub(crate) fn finish(
+        self,
+        result: Result<Vec<u8>, String>,
+        dpi: Dpi,
+        format: RenderFormat,
+    ) -> Result<RenderOutput, BokehRenderError> {
+        let bytes = result.map_err(BokehRenderError::Pdf)?;
+        Ok(RenderOutput {
+            bytes,
+            width: self.width.round() as u32,
+            height: self.height.round() as u32,
+            dpi,
+            format,
+            timings: RenderTimings {
+                export: self.started.elapsed(),
+                ..self.timings
+            },
+            data_images: Vec::new(),
+            console: Vec::new(),
+            thumbnail: None,
+        })
+    }
+}
 
-                #[cfg(not(target_os = "windows"))]
-                let cors = "wry://render-bokeh".to_string();
+/// `text` as a JavaScript string literal, for arguments of page calls. Unlike
+/// a template literal it reaches the page as it is, escape sequences included.
+pub(crate) fn js_string(text: &str) -> String {
+    serde_json::to_string(text).unwrap()
+}
 
-                http::Response::builder()
-                    .header(http::header::CONTENT_TYPE, mimetype)
-                    .header(http::header::ACCESS_CONTROL_ALLOW_ORIGIN, cors)
-                    .body(content)
-                    .map_err(Into::into)
-            }
-            _ => {
-                return Err("BokehResource is not Local".into());
+/// The page call that exports a document in `format`, see `render_bokeh.js`.
+pub(crate) fn render_call(json_data: &str, dpi: Dpi, format: &RenderFormat) -> String {
+    match format {
+        RenderFormat::Image(typ) => {
+            format!("renderBokeh({}, {}, {})", js_string(json_data), dpi, js_string(typ))
+        }
+        RenderFormat::Pdf { margins, .. } => {
+            format!("printBokeh({}, `{}`)", js_string(json_data), print_padding(margins))
+        }
+    }
+}
+
+fn report_call(documents: &[String], layout: &ReportLayout) -> String {
+    let content_size = match layout.content_px() {
+        Some((width, height)) => format!("[{}, {}]", width, height),
+        None => "null".to_string(),
+    };
+    format!(
+        "printReport({}, {}, {}, {}, `{}`)",
+        serde_json::to_string(documents).unwrap(),
+        layout.columns,
+        layout.rows,
+        content_size,
+        print_padding(&layout.margins)
+    )
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn new_web_context(options: &RenderOptions) -> WebContext {
+    WebContext::new(Some(crate::data_dir::user_data_dir(
+        options.webview_data_dir.as_deref(),
+    )))
+}
+
+/// Point `webview_builder` at the render page, serving it and the Bokeh
+/// resources over the `wry://` protocol and forwarding IPC to the event loop.
+/// The window render webviews are built in: hidden, unless `RenderOptions::debug`,
+/// and sized to `RenderOptions::viewport`.
+pub(crate) fn render_window_builder(options: &RenderOptions) -> WindowBuilder {
+    let builder = WindowBuilder::new()
+        .with_title("wry_bokeh_helper")
+        .with_decorations(options.debug)
+        .with_visible(options.debug)
+        .with_transparent(true);
+    match options.viewport {
+        Some((width, height)) => builder.with_inner_size(LogicalSize::new(width, height)),
+        None => builder,
+    }
+}
+
+pub(crate) fn render_webview_builder(
+    webview_builder: WebViewBuilder<'_>,
+    resource: Option<BokehResource>,
+    options: RenderOptions,
+    events: EventSink,
+) -> WebViewBuilder<'_> {
+    let html = build_bokeh_render_html(resource.clone(), &options);
+    page_webview_builder(webview_builder, html, resource, options, events)
+}
+
+/// Point `webview_builder` at the page of a Bokeh server app, which loads
+/// BokehJS itself, with the page script and `call` run ahead of the page's own
+/// scripts on every load.
+fn server_webview_builder<'a>(
+    webview_builder: WebViewBuilder<'a>,
+    url: &str,
+    call: &str,
+    options: RenderOptions,
+    events: EventSink,
+) -> WebViewBuilder<'a> {
+    let mut page_options = page_options(&options);
+    // The server's origin can't upload exports to the `wry://` protocol.
+    page_options["binary_transfer"] = false.into();
+    let script = format!(
+        "{}\nconst pageOptions = {};\n{}\n{}\n{};",
+        CONSOLE_SCRIPT, page_options, BUNDLE_ERROR_SCRIPT, RENDER_SCRIPT, call
+    );
+    // The server serves the page; the protocol only answers the page's uploads.
+    page_webview_builder(webview_builder, String::new(), None, options, events)
+        .with_url(url)
+        .with_initialization_script(&script)
+}
+
+/// The settings, IPC and `wry://` protocol every render webview shares, pointed
+/// at `html` served as `wry://render-bokeh`. Other pages set their own URL.
+fn page_webview_builder(
+    webview_builder: WebViewBuilder<'_>,
+    html: String,
+    resource: Option<BokehResource>,
+    options: RenderOptions,
+    events: EventSink,
+) -> WebViewBuilder<'_> {
+    let page_load_events = events.clone();
+    let console_sender = options.console_sender.clone();
+    let preview_sender = options.preview_sender.clone();
+    let options_debug = options.debug;
+    let capture = CaptureSlot::default();
+    let ipc_capture = capture.clone();
+    let failures = ResourceFailures::default();
+    let ipc_failures = failures.clone();
+    #[cfg(target_os = "windows")]
+    let webview_builder = {
+        let mut args = Vec::new();
+        if let Some(proxy) = &options.proxy {
+            args.push(format!("--proxy-server={}", proxy.url()));
+        }
+        if let Some(megabytes) = options.js_heap_limit_mb {
+            args.push(format!("--js-flags=--max-old-space-size={}", megabytes));
+        }
+        args.extend(options.browser_args.iter().cloned());
+        match args.is_empty() {
+            true => webview_builder,
+            // Replaces wry's default arguments, which are kept, and the proxy
+            // argument it derives from `with_proxy_config`.
+            false => webview_builder.with_additional_browser_args(format!(
+                "--disable-features=msWebOOUI,msPdfOOUI,msSmartScreenProtection {}",
+                args.join(" ")
+            )),
+        }
+    };
+    let webview_builder = match &options.proxy {
+        Some(proxy) => webview_builder.with_proxy_config(proxy.to_wry()),
+        None => webview_builder,
+    };
+    webview_builder
+        .with_html(html.clone())
+        .with_url("wry://render-bokeh")
+        .with_ipc_handler(move |payload| {
+            ipc_handler(
+                &payload,
+                &events,
+                console_sender.as_ref(),
+                preview_sender.as_ref(),
+                &ipc_capture,
+                &ipc_failures,
+            )
+        })
+        .with_on_page_load_handler(move |event, _| {
+            if let PageLoadEvent::Finished = event {
+                page_load_events.send(UserEvent::PageLoaded);
             }
+        })
+        .with_custom_protocol(
+            "wry".into(),
+            move |_, request| {
+                let path = request.uri().path().to_string();
+                let response =
+                    custom_protocol_handler(request, &html, &resource, &options, &capture);
+                let status = response.status();
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    path = %path,
+                    status = status.as_u16(),
+                    bytes = response.body().len(),
+                    "served render page resource"
+                );
+                // Kept for the page's report of the element that failed to load,
+                // which can't see the response.
+                if status.is_client_error() || status.is_server_error() {
+                    let reason = String::from_utf8_lossy(response.body()).into_owned();
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        path = %path,
+                        status = status.as_u16(),
+                        reason = %reason,
+                        "refused render page request"
+                    );
+                    let failure = ResourceFailure {
+                        path: path.clone(),
+                        status: status.as_u16(),
+                        reason,
+                    };
+                    failures.lock().unwrap().insert(path, failure);
+                }
+                response.map(Into::into)
+            },
+        )
+        .with_transparent(true)
+        .with_devtools(options_debug)
+}
+
+/// Apply `RenderOptions::browser_args` to a built webview where the platform
+/// takes no command line: WebKitGTK turns hardware acceleration off for
+/// `--disable-gpu` and ignores other arguments.
+pub(crate) fn apply_browser_args(webview: &WebView, options: &RenderOptions) {
+    #[cfg(target_os = "linux")]
+    if options.browser_args.iter().any(|arg| arg == "--disable-gpu") {
+        use webkit2gtk::{HardwareAccelerationPolicy, SettingsExt, WebViewExt};
+        use wry::WebViewExtUnix;
+
+        if let Some(settings) = WebViewExt::settings(&webview.webview()) {
+            settings.set_hardware_acceleration_policy(HardwareAccelerationPolicy::Never);
         }
-    } else {
-        Err(format!("Invalid path {}", path.to_str().unwrap()).into())
     }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (webview, options);
 }
 
+/// The page a render webview loads.
+pub(crate) enum RenderPage {
+    /// The render page, loading BokehJS from the resource, or the CDN without one.
+    Document(Option<BokehResource>),
+    /// The page at this URL of a Bokeh server app.
+    Server(String),
+    /// A render page for a `ChartEngine`, see `build_chart_render_html`.
+    Chart(String),
+}
+
+/// Load `page`, make `call` once it has loaded and deliver the exported result.
+/// `stats` describe the document in case the page runs out of memory.
 fn do_render_bokeh_in_webview(
-    json_data: &str,
-    dpi: u64,
-    typ: &str,
-    sender: Sender<String>,
-    resource: Option<BokehResource>,
+    call: &str,
+    stats: DocumentStats,
+    dpi: Dpi,
+    format: RenderFormat,
+    sender: oneshot::Sender<Result<RenderOutput, BokehRenderError>>,
+    page: RenderPage,
+    options: RenderOptions,
 ) {
     let mut event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build();
     let event_loop_proxy = event_loop.create_proxy();
-    let window = WindowBuilder::new()
-        .with_decorations(false)
-        .with_visible(false)
-        .with_transparent(true)
-        .build(&event_loop)
-        .unwrap();
+    let window = match render_window_builder(&options).build(&event_loop) {
+        Ok(window) => window,
+        Err(e) => {
+            let _ = sender.send(Err(BokehRenderError::Renderer(e.to_string())));
+            return;
+        }
+    };
+    let dpi = dpi.on_window(&window);
+    let variant_dpis: Vec<Dpi> = options.dpis.iter().map(|dpi| dpi.on_window(&window)).collect();
 
+    #[cfg(feature = "tracing")]
+    let build_started = Instant::now();
     #[cfg(target_os = "windows")]
-    let mut web_context = WebContext::new(Some(
-        (std::env::var("APPDATA")
-            .map(PathBuf::from)
-            .unwrap_or_else(|_| std::env::temp_dir()))
-        .join("wry_bokeh_helper"),
-    ));
+    let mut web_context = new_web_context(&options);
     #[cfg(target_os = "windows")]
     let webview_builder = WebViewBuilder::with_web_context(&mut web_context);
 
     #[cfg(not(target_os = "windows"))]
     let webview_builder = WebViewBuilder::new();
 
-    let webview = webview_builder
-        .with_html(build_bokeh_render_html(resource.clone()))
-        .with_url("wry://render-bokeh")
-        .with_ipc_handler(move |payload| ipc_handler(&payload, &event_loop_proxy))
-        .with_custom_protocol(
-            "wry".into(),
-            move |_, request| match custom_protocol_handler(request, &resource) {
-                Ok(response) => response.map(Into::into),
-                Err(e) => http::Response::builder()
-                    .status(500)
-                    .body(e.to_string().as_bytes().to_vec())
-                    .unwrap()
-                    .map(Into::into),
-            },
-        )
-        .with_transparent(true)
-        .build(&window)
-        .unwrap();
+    // A server page runs the call itself, see `server_webview_builder`.
+    let call_on_load = !matches!(page, RenderPage::Server(_));
+    let webview_builder = match page {
+        RenderPage::Document(resource) => render_webview_builder(
+            webview_builder,
+            resource,
+            options.clone(),
+            event_loop_proxy.clone().into(),
+        ),
+        RenderPage::Server(url) => server_webview_builder(
+            webview_builder,
+            &url,
+            call,
+            options.clone(),
+            event_loop_proxy.clone().into(),
+        ),
+        RenderPage::Chart(html) => page_webview_builder(
+            webview_builder,
+            html,
+            None,
+            options.clone(),
+            event_loop_proxy.clone().into(),
+        ),
+    };
+    let webview = match webview_builder.build(&window) {
+        Ok(webview) => webview,
+        Err(e) => {
+            let _ = sender.send(Err(webview_error(e)));
+            return;
+        }
+    };
+    apply_browser_args(&webview, &options);
+    watch_web_process(&webview, event_loop_proxy.clone().into());
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        duration_ms = build_started.elapsed().as_secs_f64() * 1000.0,
+        "built webview"
+    );
 
-    webview
-        .evaluate_script(&format!(
-            "window.onload = () => renderBokeh(`{}`, {}, `{}`)",
-            json_data, dpi, typ
-        ))
-        .unwrap();
+    if call_on_load {
+        webview
+            .evaluate_script(&format!("window.onload = () => {}", call))
+            .unwrap();
+    }
 
+    let mut delivery = Delivery::new(sender);
+    let mut pending_pdf = None;
+    let mut stitcher = Stitcher::default();
+    let mut console = Vec::new();
     let _ = event_loop.run_return(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
+        *control_flow = delivery.control_flow();
 
         match event {
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                *control_flow = ControlFlow::Exit;
+            }
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
-            } => *control_flow = ControlFlow::Exit,
-            Event::UserEvent(UserEvent::PayloadReceived(payload)) => {
-                sender.send(payload).unwrap();
+            } => {
+                delivery.deliver(
+                    Err(BokehRenderError::Renderer(
+                        "The render window was closed before the export".to_string(),
+                    )),
+                    &options,
+                );
                 *control_flow = ControlFlow::Exit;
             }
+            Event::UserEvent(UserEvent::Console(message)) => console.push(message),
+            Event::UserEvent(UserEvent::PayloadReceived(image)) => {
+                // One of the other resolutions of `render_bokeh_at_dpis`, posted
+                // ahead of the result.
+                if let (Some(index), Some(sender)) = (image.variant(), &options.variant_sender) {
+                    let dpi = variant_dpis.get(index).copied().unwrap_or(dpi);
+                    let output = image_output(image, dpi, format.clone(), &options);
+                    let _ = sender.send((index, output));
+                    return;
+                }
+                let output = image_output(image, dpi, format.clone(), &options)
+                    .map(|output| output.with_console(std::mem::take(&mut console)));
+                *control_flow = delivery.deliver(output, &options);
+            }
+            Event::UserEvent(UserEvent::Tile(tile)) => {
+                if let Some(output) = stitcher.add(tile, dpi, &options) {
+                    let output =
+                        output.map(|output| output.with_console(std::mem::take(&mut console)));
+                    *control_flow = delivery.deliver(output, &options);
+                }
+            }
+            Event::UserEvent(UserEvent::Printable {
+                width,
+                height,
+                timings,
+            }) => {
+                pending_pdf = PendingPdf::start(
+                    &webview,
+                    &format,
+                    width,
+                    height,
+                    timings,
+                    event_loop_proxy.clone().into(),
+                );
+            }
+            Event::UserEvent(UserEvent::PageError(message)) => {
+                *control_flow = delivery.deliver(Err(BokehRenderError::Page(message)), &options);
+            }
+            Event::UserEvent(UserEvent::ResourceLoadFailed(error)) => {
+                *control_flow = delivery.deliver(Err(error), &options);
+            }
+            Event::UserEvent(UserEvent::ProcessFailed { out_of_memory }) => {
+                let error = BokehRenderError::process_failed(out_of_memory, stats);
+                *control_flow = delivery.deliver(Err(error), &options);
+            }
+            Event::UserEvent(UserEvent::PdfPrinted(result)) => {
+                if let Some(pending) = pending_pdf.take() {
+                    let output = pending
+                        .finish(result, dpi, format.clone())
+                        .map(|output| output.with_console(std::mem::take(&mut console)));
+                    *control_flow = delivery.deliver(output, &options);
+                }
+            }
             _ => (),
         }
     });
 }
 
-pub async fn render_bokeh_in_webview(
+/// Where a render loop delivers its result. Only the first result is
+/// delivered; a debugged page may fail after it.
+struct Delivery {
+    sender: Option<oneshot::Sender<Result<RenderOutput, BokehRenderError>>>,
+    /// How the loop carries on after the first result, see `after_result`.
+    lingering: Option<ControlFlow>,
+}
+
+impl Delivery {
+    fn new(sender: oneshot::Sender<Result<RenderOutput, BokehRenderError>>) -> Self {
+        Delivery {
+            sender: Some(sender),
+            lingering: None,
+        }
+    }
+
+    /// How the loop waits for its next event.
+    fn control_flow(&self) -> ControlFlow {
+        self.lingering.unwrap_or(ControlFlow::Wait)
+    }
+
+    /// Deliver `result` unless an earlier one was, and how the loop carries on:
+    /// see `after_result` for the first result, while results after it change
+    /// nothing. A loop whose caller has stopped waiting exits right away.
+    fn deliver(
+        &mut self,
+        result: Result<RenderOutput, BokehRenderError>,
+        options: &RenderOptions,
+    ) -> ControlFlow {
+        let delivered = match self.sender.take() {
+            Some(sender) => sender.send(result).is_ok(),
+            None => true,
+        };
+        match delivered {
+            true => *self.lingering.get_or_insert_with(|| after_result(options)),
+            false => ControlFlow::Exit,
+        }
+    }
+}
+
+/// How the render loop carries on once the result is delivered: it exits right
+/// away, after `RenderOptions::keep_alive`, or in debug mode once the window is closed.
+fn after_result(options: &RenderOptions) -> ControlFlow {
+    match options.keep_alive {
+        _ if options.debug => ControlFlow::Wait,
+        Some(keep_alive) => ControlFlow::WaitUntil(Instant::now() + keep_alive),
+        None => ControlFlow::Exit,
+    }
+}
+
+pub async fn render_bokeh_in_webview<'a>(
+    json_data: impl Into<RenderInput<'a>>,
+    dpi: impl Into<Dpi>,
+    format: RenderFormat,
+    resource: Option<BokehResource>,
+    options: RenderOptions,
+) -> Result<RenderOutput, BokehRenderError> {
+    let json_data = json_data.into().into_json()?;
+    let json_data = embeddable_json(&json_data)?;
+    let json_data = json_data.as_ref();
+    let dpi = options.dpi_for(dpi).validate()?;
+    let call = export_call(json_data, dpi, &format, &options)?;
+    if options.offline {
+        check_offline_resources(resource.as_ref(), &options)?;
+        check_offline_document(json_data)?;
+    }
+    let options = options.sized_for(json_data);
+    let stats = DocumentStats::of(json_data);
+    let result = render_call_in_webview(
+        &call,
+        stats,
+        dpi,
+        format.clone(),
+        resource.clone(),
+        options.clone(),
+    )
+    .await;
+    match (result, options.blank_retry_delay) {
+        // The capture fired before the canvas painted; give it longer once.
+        (Err(BokehRenderError::BlankRender), Some(delay)) => {
+            let options = RenderOptions {
+                settle_delay: delay,
+                ..options
+            };
+            render_call_in_webview(&call, stats, dpi, format, resource, options).await
+        }
+        (result, _) => result,
+    }
+}
+
+/// Render a document the caller has already parsed, without serializing it
+/// anywhere but for the page; see `render_bokeh_in_webview`.
+pub async fn render_bokeh_value(
+    doc: &serde_json::Value,
+    dpi: impl Into<Dpi>,
+    format: RenderFormat,
+    resource: Option<BokehResource>,
+    options: RenderOptions,
+) -> Result<RenderOutput, BokehRenderError> {
+    render_bokeh_in_webview(doc, dpi, format, resource, options).await
+}
+
+/// The page call exporting `json_data` in `format`, tiled if the options ask for it.
+pub(crate) fn export_call(
     json_data: &str,
-    dpi: u64,
-    typ: &str,
+    dpi: Dpi,
+    format: &RenderFormat,
+    options: &RenderOptions,
+) -> Result<String, BokehRenderError> {
+    match options.tiled {
+        Some(_) if *format != RenderFormat::Image("image/png".to_string()) => Err(
+            BokehRenderError::InvalidRequest("Tiled exports are always PNG".to_string()),
+        ),
+        Some(_) => Ok(format!(
+            "renderBokeh({}, {}, `image/png`, `tiled`)",
+            js_string(json_data),
+            dpi
+        )),
+        None => Ok(render_call(json_data, dpi, format)),
+    }
+}
+
+/// Render `json_data` to PNG and decode it, for callers that go on to crop,
+/// resize or compose the plot. Post-processing options still apply.
+#[cfg(feature = "image")]
+pub async fn render_bokeh_to_image<'a>(
+    json_data: impl Into<RenderInput<'a>>,
+    dpi: impl Into<Dpi>,
     resource: Option<BokehResource>,
-) -> String {
-    let (tx, mut rx) = tokio::sync::broadcast::channel(1);
-    do_render_bokeh_in_webview(json_data, dpi, typ, tx, resource);
+    options: RenderOptions,
+) -> Result<image::DynamicImage, BokehRenderError> {
+    let dpi = dpi.into();
+    let format = RenderFormat::Image("image/png".to_string());
+    let output = render_bokeh_in_webview(json_data, dpi, format, resource, options).await?;
+    image::load_from_memory_with_format(&output.bytes, image::ImageFormat::Png)
+        .map_err(|e| BokehRenderError::Decode(e.to_string()))
+}
+
+/// Render `json_data` at each resolution of `RenderOptions::dpis` in one pass,
+/// keyed by the DPI asked for: the document is embedded once, at the highest,
+/// and the other exports are downscaled from its canvas instead of embedding
+/// it again for each, unless `RenderOptions::native_resolution` asks for the
+/// sharper exports of an embed at each. Only image exports without an
+/// `output_size`, tiling or preview can be rendered this way.
+pub async fn render_bokeh_at_dpis(
+    json_data: &str,
+    format: RenderFormat,
+    resource: Option<BokehResource>,
+    options: RenderOptions,
+) -> Result<BTreeMap<Dpi, RenderOutput>, BokehRenderError> {
+    let json_data = embeddable_json(json_data)?;
+    let json_data = json_data.as_ref();
+    let mut dpis = options.dpis.clone();
+    dpis.sort();
+    dpis.dedup();
+    for dpi in &dpis {
+        dpi.validate()?;
+    }
+    let Some(embedded) = (0..dpis.len()).max_by(|&a, &b| dpis[a].get().total_cmp(&dpis[b].get()))
+    else {
+        return Err(BokehRenderError::InvalidRequest(
+            "Rendering at several DPIs needs `RenderOptions::dpis`".to_string(),
+        ));
+    };
+    let RenderFormat::Image(typ) = &format else {
+        return Err(BokehRenderError::InvalidRequest(
+            "PDFs can't be rendered at several DPIs".to_string(),
+        ));
+    };
+    if options.output_size.is_some() || options.tiled.is_some() {
+        return Err(BokehRenderError::InvalidRequest(
+            "Exports with an output size or in tiles can't be rendered at several DPIs"
+                .to_string(),
+        ));
+    }
+    if options.offline {
+        check_offline_resources(resource.as_ref(), &options)?;
+        check_offline_document(json_data)?;
+    }
+
+    let variants: Vec<(usize, f64)> = (0..dpis.len())
+        .filter(|&index| index != embedded)
+        .map(|index| (index, dpis[index].get()))
+        .collect();
+    let call = format!(
+        "renderBokeh({}, {}, {}, `canvas`, {})",
+        js_string(json_data),
+        dpis[embedded],
+        js_string(typ),
+        serde_json::to_string(&variants).unwrap()
+    );
+    let (sender, mut receiver) = unbounded_channel();
+    let options = RenderOptions {
+        dpis: dpis.clone(),
+        variant_sender: Some(sender),
+        preview_sender: None,
+        ..options.sized_for(json_data)
+    };
+    let stats = DocumentStats::of(json_data);
+    let output =
+        render_call_in_webview(&call, stats, dpis[embedded], format, resource, options).await?;
 
-    rx.recv().await.unwrap()
+    let mut outputs = BTreeMap::from([(dpis[embedded], output)]);
+    while let Ok((index, output)) = receiver.try_recv() {
+        outputs.insert(dpis[index], output?);
+    }
+    if outputs.len() < dpis.len() {
+        return Err(BokehRenderError::Renderer(format!(
+            "The page exported {} of {} resolutions",
+            outputs.len(),
+            dpis.len()
+        )));
+    }
+    Ok(outputs)
+}
+
+/// Capture an app running on a Bokeh server: load the app's page at `url`, with
+/// `session_args` added to its query for the app to read from the session's
+/// request, wait until the session's first root has rendered and settled, and
+/// export it. The server sends BokehJS itself, so there is no `BokehResource`;
+/// without `RenderOptions::viewport` the window has its default size, which
+/// sets the size of responsive layouts. Only image exports, untiled, are
+/// supported.
+pub async fn render_bokeh_server_session(
+    url: &str,
+    session_args: &[(&str, &str)],
+    dpi: impl Into<Dpi>,
+    format: RenderFormat,
+    options: RenderOptions,
+) -> Result<RenderOutput, BokehRenderError> {
+    let mut url = url::Url::parse(url)
+        .map_err(|e| BokehRenderError::InvalidRequest(format!("Invalid server URL: {}", e)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(BokehRenderError::InvalidRequest(format!(
+            "Bokeh server sessions are served over HTTP, not `{}`",
+            url.scheme()
+        )));
+    }
+    if !session_args.is_empty() {
+        url.query_pairs_mut().extend_pairs(session_args);
+    }
+    let dpi = options.dpi_for(dpi).validate()?;
+    let RenderFormat::Image(typ) = &format else {
+        return Err(BokehRenderError::InvalidRequest(
+            "Bokeh server sessions can't be printed to PDF".to_string(),
+        ));
+    };
+    if options.tiled.is_some() {
+        return Err(BokehRenderError::InvalidRequest(
+            "Bokeh server sessions can't be exported in tiles".to_string(),
+        ));
+    }
+    let call = format!("snapshotServerSession({}, {})", dpi, js_string(typ));
+    let options = RenderOptions {
+        preview_sender: None,
+        ..options
+    };
+    let page = RenderPage::Server(url.into());
+    render_page_in_webview(&call, DocumentStats::default(), dpi, format, page, options).await
+}
+
+/// Make `call` on a fresh render page and deliver its export, under the
+/// process-wide webview limit.
+pub(crate) async fn render_call_in_webview(
+    call: &str,
+    stats: DocumentStats,
+    dpi: Dpi,
+    format: RenderFormat,
+    resource: Option<BokehResource>,
+    options: RenderOptions,
+) -> Result<RenderOutput, BokehRenderError> {
+    let page = RenderPage::Document(resource);
+    render_page_in_webview(call, stats, dpi, format, page, options).await
+}
+
+/// Make `call` on `page` and deliver its export, under the process-wide
+/// webview limit.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "render_bokeh", skip_all, fields(dpi, format = ?format))
+)]
+pub(crate) async fn render_page_in_webview(
+    call: &str,
+    stats: DocumentStats,
+    dpi: Dpi,
+    format: RenderFormat,
+    page: RenderPage,
+    options: RenderOptions,
+) -> Result<RenderOutput, BokehRenderError> {
+    let _permit = acquire_webview_permit().await;
+    #[cfg(feature = "tracing")]
+    let started = Instant::now();
+    let (tx, rx) = oneshot::channel();
+    do_render_bokeh_in_webview(call, stats, dpi, format, tx, page, options);
+
+    let result = rx.await.unwrap_or_else(|_| Err(loop_exited()));
+    #[cfg(feature = "tracing")]
+    trace_render_result(started, &result);
+    result
+}
+
+fn loop_exited() -> BokehRenderError {
+    BokehRenderError::Renderer("The render loop exited without a result".to_string())
+}
+
+/// Log how long a render that began at `started` took and what it produced.
+#[cfg(feature = "tracing")]
+fn trace_render_result(started: Instant, result: &Result<RenderOutput, BokehRenderError>) {
+    let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    match result {
+        Ok(output) => tracing::info!(duration_ms, bytes = output.bytes.len(), "rendered"),
+        Err(error) => tracing::warn!(duration_ms, %error, "render failed"),
+    }
+}
+
+/// Render each request in turn, each in a fresh webview.
+pub async fn render_bokeh_batch(
+    requests: Vec<RenderRequest>,
+) -> Vec<Result<RenderOutput, BokehRenderError>> {
+    let mut results = Vec::with_capacity(requests.len());
+    for request in requests {
+        results.push(
+            render_bokeh_in_webview(
+                &request.json_data,
+                request.dpi,
+                request.format,
+                request.resource,
+                request.options,
+            )
+            .await,
+        );
+    }
+    results
+}
+
+/// How `render_bokeh_batch_as` packages a batch into a single file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// One TIFF page per figure, as some lab information systems require for archival.
+    TiffMultiPage,
+}
+
+/// Render each request in turn, like `render_bokeh_batch`, and package all
+/// outputs into one `format` file. Fails if any of the renders fails.
+pub async fn render_bokeh_batch_as(
+    requests: Vec<RenderRequest>,
+    format: OutputFormat,
+) -> Result<Vec<u8>, BokehRenderError> {
+    if requests.is_empty() {
+        return Err(BokehRenderError::InvalidRequest(
+            "A batch needs at least one document".to_string(),
+        ));
+    }
+
+    let outputs = render_bokeh_batch(requests)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    match format {
+        OutputFormat::TiffMultiPage => encode_multipage_tiff(&outputs),
+    }
+}
+
+/// Print every document into a single PDF, one per page or one per grid cell.
+///
+/// All documents share one page, so the resource and options of the first
+/// request are used for the whole report; per-request `dpi` and `format` are ignored.
+/// WKWebView's `createPDF` doesn't paginate, so on macOS only the first page is captured.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(documents = docs.len()))
+)]
+pub async fn render_bokeh_report(
+    docs: Vec<RenderRequest>,
+    layout: ReportLayout,
+) -> Result<Vec<u8>, BokehRenderError> {
+    let Some(first) = docs.first() else {
+        return Err(BokehRenderError::InvalidRequest(
+            "A report needs at least one document".to_string(),
+        ));
+    };
+    if layout.columns == 0 || layout.rows == 0 {
+        return Err(BokehRenderError::InvalidRequest(
+            "A report layout needs at least one column and one row".to_string(),
+        ));
+    }
+
+    let resource = first.resource.clone();
+    let options = first.options.clone();
+    let documents = docs
+        .into_iter()
+        .map(|doc| embeddable_json(&doc.json_data).map(Cow::into_owned))
+        .collect::<Result<Vec<String>, _>>()?;
+    if options.offline {
+        check_offline_resources(resource.as_ref(), &options)?;
+        for json_data in &documents {
+            check_offline_document(json_data)?;
+        }
+    }
+    let stats = documents
+        .iter()
+        .map(|json_data| DocumentStats::of(json_data))
+        .fold(DocumentStats::default(), |total, stats| total + stats);
+    let format = RenderFormat::Pdf {
+        page_size: layout.page_size,
+        margins: layout.margins,
+    };
+
+    let _permit = acquire_webview_permit().await;
+    #[cfg(feature = "tracing")]
+    let started = Instant::now();
+    let (tx, rx) = oneshot::channel();
+    let call = report_call(&documents, &layout);
+    let page = RenderPage::Document(resource);
+    do_render_bokeh_in_webview(&call, stats, Dpi::CSS, format, tx, page, options);
+
+    let result = rx.await.unwrap_or_else(|_| Err(loop_exited()));
+    #[cfg(feature = "tracing")]
+    trace_render_result(started, &result);
+    result.map(|output| output.bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn blank_render() -> Result<RenderOutput, BokehRenderError> {
+        Err(BokehRenderError::BlankRender)
+    }
+
+    #[test]
+    fn delivery_exits_once_the_caller_is_gone() {
+        let (tx, rx) = oneshot::channel();
+        drop(rx);
+        let options = RenderOptions {
+            keep_alive: Some(Duration::from_secs(60)),
+            ..RenderOptions::default()
+        };
+        let mut delivery = Delivery::new(tx);
+        assert_eq!(delivery.deliver(blank_render(), &options), ControlFlow::Exit);
+        assert_eq!(delivery.control_flow(), ControlFlow::Wait);
+    }
+
+    #[test]
+    fn delivery_lingers_after_the_first_result_only() {
+        let (tx, mut rx) = oneshot::channel();
+        let options = RenderOptions {
+            debug: true,
+            ..RenderOptions::default()
+        };
+        let mut delivery = Delivery::new(tx);
+        assert_eq!(delivery.control_flow(), ControlFlow::Wait);
+        assert_eq!(delivery.deliver(blank_render(), &options), ControlFlow::Wait);
+        let page_error = Err(BokehRenderError::Page("after the export".to_string()));
+        assert_eq!(delivery.deliver(page_error, &options), ControlFlow::Wait);
+        assert!(matches!(rx.try_recv(), Ok(Err(BokehRenderError::BlankRender))));
+    }
+
+    #[test]
+    fn delivery_exits_after_the_result_without_keep_alive() {
+        let (tx, _rx) = oneshot::channel();
+        let mut delivery = Delivery::new(tx);
+        let exit = delivery.deliver(blank_render(), &RenderOptions::default());
+        assert_eq!(exit, ControlFlow::Exit);
+        assert_eq!(delivery.control_flow(), ControlFlow::Exit);
+    }
 }
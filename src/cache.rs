@@ -0,0 +1,299 @@
+//! Exports kept on disk and returned again for identical renders, since
+//! iterative report generation re-renders mostly unchanged figures. Interactive
+//! applications can also keep their hottest figures in memory.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bokeh_helpers::{build_bokeh_render_html, render_bokeh_in_webview};
+use crate::fonts;
+use crate::options::{Dpi, RenderFormat, RenderOptions};
+use crate::output::{BokehRenderError, RenderOutput, RenderTimings};
+use crate::resources::{
+    sha256_hex, write_atomically, BokehResource, CssSource, ResourceProvider, ScriptSource,
+    LOCAL_BUNDLES,
+};
+
+/// What `RenderCache` stores next to the exported bytes.
+#[derive(Serialize, Deserialize)]
+struct CachedOutput {
+    width: u32,
+    height: u32,
+    thumbnail: bool,
+}
+
+/// A directory of exports keyed by a hash of the document, the DPI and format,
+/// the options and the Bokeh bundles. Cached outputs come back without timings
+/// or console messages. Renders with `RenderOptions::data_images`, or whose Bokeh
+/// bundles can't be read to identify them, always go to the webview.
+pub struct RenderCache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+}
+
+impl RenderCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        RenderCache {
+            dir: dir.into(),
+            ttl: None,
+        }
+    }
+
+    /// Render again once an entry is older than `ttl`.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// `render_bokeh_in_webview`, unless an identical render is cached.
+    pub async fn render(
+        &self,
+        json_data: &str,
+        dpi: impl Into<Dpi>,
+        format: RenderFormat,
+        resource: Option<BokehResource>,
+        options: RenderOptions,
+    ) -> Result<RenderOutput, BokehRenderError> {
+        let dpi = options.dpi_for(dpi);
+        let path = match options.data_image_format {
+            Some(_) => None,
+            None => cache_key(json_data, dpi, &format, resource.as_ref(), &options)
+                .map(|key| self.dir.join(key)),
+        };
+        if let Some(output) = path.as_deref().and_then(|path| self.read(path, dpi, &format)) {
+            return Ok(output);
+        }
+
+        let output = render_bokeh_in_webview(json_data, dpi, format, resource, options).await?;
+        if let Some(path) = &path {
+            // A failed write only costs the next identical render its export.
+            let _ = write(path, &output);
+        }
+        Ok(output)
+    }
+
+    /// Remove every cached export.
+    pub fn purge(&self) -> std::io::Result<()> {
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn read(&self, path: &Path, dpi: Dpi, format: &RenderFormat) -> Option<RenderOutput> {
+        let meta_path = path.with_extension("json");
+        if let Some(ttl) = self.ttl {
+            let age = std::fs::metadata(&meta_path)
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+            if age > ttl {
+                return None;
+            }
+        }
+        let meta: CachedOutput = serde_json::from_slice(&std::fs::read(&meta_path).ok()?).ok()?;
+        let thumbnail = match meta.thumbnail {
+            true => Some(std::fs::read(path.with_extension("thumbnail")).ok()?),
+            false => None,
+        };
+        Some(RenderOutput {
+            bytes: std::fs::read(path).ok()?,
+            width: meta.width,
+            height: meta.height,
+            dpi,
+            format: format.clone(),
+            timings: RenderTimings::default(),
+            data_images: Vec::new(),
+            console: Vec::new(),
+            thumbnail,
+        })
+    }
+}
+
+fn write(path: &Path, output: &RenderOutput) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    write_atomically(path, &output.bytes)?;
+    if let Some(thumbnail) = &output.thumbnail {
+        write_atomically(&path.with_extension("thumbnail"), thumbnail)?;
+    }
+    // The metadata goes last: it's what marks the entry as complete, and its
+    // modification time is the entry's age.
+    let meta = CachedOutput {
+        width: output.width,
+        height: output.height,
+        thumbnail: output.thumbnail.is_some(),
+    };
+    write_atomically(&path.with_extension("json"), &serde_json::to_vec(&meta).unwrap())
+}
+
+/// Hash of everything that decides what a render exports. The page markup
+/// covers the page options and the render script itself, but names extra
+/// scripts, stylesheets and fonts only by their URL on the page.
+fn cache_key(
+    json_data: &str,
+    dpi: Dpi,
+    format: &RenderFormat,
+    resource: Option<&BokehResource>,
+    options: &RenderOptions,
+) -> Option<String> {
+    let key = format!(
+        "{}\n{}\n{}\n{:?}\n{}\n{:?}\n{:?}\n{:?} {} {} {:?} {:?}\n{}",
+        resource_key(resource)?,
+        page_resources_key(options)?,
+        dpi,
+        format,
+        build_bokeh_render_html(None, options),
+        options.watermark,
+        options.icc_profile,
+        options.tiled,
+        options.convert_to_icc_profile,
+        options.trim_margins,
+        options.thumbnail,
+        options.viewport,
+        json_data
+    );
+    Some(sha256_hex(key.as_bytes()))
+}
+
+/// Identifies the Bokeh bundles of `resource`: the release for downloaded ones,
+/// otherwise a hash of their content.
+fn resource_key(resource: Option<&BokehResource>) -> Option<String> {
+    let provider: &dyn ResourceProvider = match resource {
+        // The page markup holds the default release.
+        None => return Some("default".to_string()),
+        Some(BokehResource::CDN(cdn)) => return Some(format!("cdn {}", cdn.version)),
+        Some(BokehResource::AutoCache(auto_cache)) => {
+            return Some(format!("cdn {}", auto_cache.version))
+        }
+        Some(BokehResource::Local(local)) => local,
+        Some(BokehResource::Memory(bundles)) => bundles,
+        Some(BokehResource::Archive(archive)) => archive,
+        Some(BokehResource::Provider(provider)) => provider.as_ref(),
+    };
+    let mut content = Vec::new();
+    for bundle in LOCAL_BUNDLES {
+        content.extend_from_slice(&provider.fetch(bundle).ok()?.content);
+    }
+    Some(sha256_hex(&content))
+}
+
+/// Identifies what the page loads besides the bundles: the extra scripts and
+/// stylesheets, by content or, for downloaded ones, URL, and the fonts served
+/// under `/fonts/`. `None` if a file among them can't be read.
+fn page_resources_key(options: &RenderOptions) -> Option<String> {
+    let mut parts = Vec::new();
+    for script in &options.extra_scripts {
+        parts.push(match script {
+            ScriptSource::Url(url) => format!("script {}", url),
+            _ => format!("script {}", sha256_hex(&script.fetch(options).ok()?.content)),
+        });
+    }
+    for css in &options.extra_css {
+        parts.push(match css {
+            CssSource::Url(url) => format!("css {}", url),
+            _ => format!("css {}", sha256_hex(&css.fetch(options).ok()?.content)),
+        });
+    }
+    let fallback_fonts = (0..).map(|index| format!("fallback-{}", index));
+    for name in ["default-regular", "default-bold"]
+        .map(String::from)
+        .into_iter()
+        .chain(fallback_fonts)
+    {
+        match fonts::fetch(options, &name) {
+            Some(font) => parts.push(format!("{} {}", name, sha256_hex(&font.content))),
+            // The default font may lack a bold face; the fallback fonts end at
+            // the first index without a file.
+            None if name.starts_with("default-") => (),
+            None => break,
+        }
+    }
+    Some(parts.join("\n"))
+}
+
+/// Key of a render of `json_data` by a renderer or pool, whose options are fixed.
+pub(crate) fn render_key(json_data: &str, dpi: Dpi, format: &RenderFormat) -> String {
+    sha256_hex(format!("{}\n{:?}\n{}", dpi, format, json_data).as_bytes())
+}
+
+/// The most recently used exports of a `BokehRenderer`, see
+/// `BokehRenderer::with_memory_cache`.
+pub(crate) struct MemoryCache {
+    capacity: usize,
+    /// Least recently used first.
+    entries: VecDeque<(String, RenderOutput)>,
+}
+
+impl MemoryCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        MemoryCache {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn get(&mut self, key: &str) -> Option<RenderOutput> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(index)?;
+        let output = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(output)
+    }
+
+    pub(crate) fn insert(&mut self, key: String, output: RenderOutput) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.retain(|(k, _)| *k != key);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, output));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(options: &RenderOptions) -> Option<String> {
+        let format = RenderFormat::Image("image/png".to_string());
+        cache_key("{}", Dpi::new(96.0), &format, None, options)
+    }
+
+    #[test]
+    fn cache_key_covers_extra_script_content() {
+        let with_script = |script: &str| RenderOptions {
+            extra_scripts: vec![ScriptSource::Inline(script.to_string())],
+            ..RenderOptions::default()
+        };
+        assert_ne!(key(&with_script("var a = 1;")), key(&with_script("var a = 2;")));
+        assert_eq!(key(&with_script("var a = 1;")), key(&with_script("var a = 1;")));
+    }
+
+    #[test]
+    fn cache_key_covers_default_font_bytes() {
+        let with_font = |regular: &[u8]| RenderOptions {
+            default_font: Some(fonts::DefaultFont::Custom {
+                regular: regular.to_vec(),
+                bold: None,
+            }),
+            ..RenderOptions::default()
+        };
+        assert_ne!(key(&with_font(b"wOF2 one")), key(&with_font(b"wOF2 two")));
+    }
+
+    #[test]
+    fn cache_key_is_none_for_a_missing_extra_file() {
+        let options = RenderOptions {
+            extra_css: vec![CssSource::File("/nonexistent/extra.css".into())],
+            ..RenderOptions::default()
+        };
+        assert_eq!(key(&options), None);
+    }
+}
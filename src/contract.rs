@@ -0,0 +1,144 @@
+//! Executable checks of the BokehJS APIs the render page relies on, to back
+//! claims about which Bokeh versions are supported. See `tests/contract_matrix.rs`
+//! for a run against several releases.
+
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tao::{
+    event::{Event, StartCause, WindowEvent},
+    event_loop::{ControlFlow, EventLoopBuilder},
+    platform::run_return::EventLoopExtRunReturn,
+};
+use wry::WebViewBuilder;
+
+use crate::bokeh_helpers::{
+    apply_browser_args, render_bokeh_in_webview, render_webview_builder, render_window_builder,
+};
+use crate::fixtures;
+use crate::options::{RenderFormat, RenderOptions};
+use crate::output::BokehRenderError;
+use crate::protocol::UserEvent;
+use crate::resources::BokehResource;
+use crate::runtime::webview_error;
+
+/// How long the page may take to load the bundles and report its API checks.
+pub const API_CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The outcome of probing one BokehJS API.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ApiCheck {
+    pub name: String,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/// Everything checked against one set of Bokeh bundles.
+#[derive(Debug)]
+pub struct ContractReport {
+    /// The API probes, or why the page couldn't run them at all.
+    pub api: Result<Vec<ApiCheck>, BokehRenderError>,
+    /// Each fixture rendered to PNG, keyed by fixture name.
+    pub fixtures: Vec<(&'static str, Result<(), BokehRenderError>)>,
+}
+
+impl ContractReport {
+    pub fn passed(&self) -> bool {
+        self.api
+            .as_ref()
+            .is_ok_and(|checks| checks.iter().all(|check| check.ok))
+            && self.fixtures.iter().all(|(_, result)| result.is_ok())
+    }
+}
+
+/// Probe the APIs of the bundles in `resource`, then render every fixture with them.
+pub async fn check_bokeh_version(resource: BokehResource) -> ContractReport {
+    let api = check_bokeh_api(resource.clone());
+    let mut results = Vec::new();
+    for (name, json_data) in fixtures::all() {
+        let output = render_bokeh_in_webview(
+            &json_data,
+            96,
+            RenderFormat::Image("image/png".to_string()),
+            Some(resource.clone()),
+            RenderOptions::default(),
+        )
+        .await;
+        results.push((name, output.map(|_| ())));
+    }
+    ContractReport {
+        api,
+        fixtures: results,
+    }
+}
+
+/// Run `probeBokehApi` on the line fixture with the bundles in `resource`,
+/// failing if the page hasn't reported within `API_CHECK_TIMEOUT`.
+pub fn check_bokeh_api(resource: BokehResource) -> Result<Vec<ApiCheck>, BokehRenderError> {
+    let mut event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build();
+    let event_loop_proxy = event_loop.create_proxy();
+    let window = render_window_builder(&RenderOptions::default())
+        .build(&event_loop)
+        .map_err(|e| BokehRenderError::Renderer(e.to_string()))?;
+
+    #[cfg(target_os = "windows")]
+    let mut web_context = crate::bokeh_helpers::new_web_context(&RenderOptions::default());
+    #[cfg(target_os = "windows")]
+    let webview_builder = WebViewBuilder::with_web_context(&mut web_context);
+
+    #[cfg(not(target_os = "windows"))]
+    let webview_builder = WebViewBuilder::new();
+
+    let webview = render_webview_builder(
+        webview_builder,
+        Some(resource),
+        RenderOptions::default(),
+        event_loop_proxy.into(),
+    )
+    .build(&window)
+    .map_err(webview_error)?;
+    apply_browser_args(&webview, &RenderOptions::default());
+
+    let json = serde_json::to_string(&fixtures::line()).unwrap();
+    webview
+        .evaluate_script(&format!("window.onload = () => probeBokehApi({})", json))
+        .map_err(|e| BokehRenderError::Renderer(e.to_string()))?;
+
+    let mut result = None;
+    let deadline = Instant::now() + API_CHECK_TIMEOUT;
+    let _ = event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::WaitUntil(deadline);
+
+        match event {
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                result = Some(Err(BokehRenderError::Renderer(format!(
+                    "The page didn't report its API checks within {:?}",
+                    API_CHECK_TIMEOUT
+                ))));
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => *control_flow = ControlFlow::Exit,
+            Event::UserEvent(UserEvent::ApiChecks(checks)) => {
+                result = Some(Ok(checks));
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::UserEvent(UserEvent::PageError(message)) => {
+                result = Some(Err(BokehRenderError::Page(message)));
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::UserEvent(UserEvent::ResourceLoadFailed(error)) => {
+                result = Some(Err(error));
+                *control_flow = ControlFlow::Exit;
+            }
+            _ => (),
+        }
+    });
+    result.unwrap_or_else(|| {
+        Err(BokehRenderError::Renderer(
+            "The event loop exited before the API checks finished".to_string(),
+        ))
+    })
+}
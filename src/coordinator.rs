@@ -0,0 +1,406 @@
+//! Scale-out for heavy report runs: a `Coordinator` spreads the jobs of a
+//! manifest over several render daemons, `http_server::RenderService`s or
+//! `ipc_server::serve_ipc` sockets, on this machine or others, and collects
+//! every job's export or failure.
+//!
+//! Each daemon starts with an even share of the jobs in a queue of its own. A
+//! daemon that runs dry steals from the back of the longest other queue, so
+//! fast daemons end up doing more of the work. A failed job is retried on
+//! another daemon, unless the daemon refused the request itself, and a daemon
+//! that can't be reached `MAX_CONSECUTIVE_FAILURES` times in a row is given up
+//! on, the rest stealing its jobs. See `examples/coordinator.rs`.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+
+use crate::wire::{read_frame, write_frame, MAX_FRAME_BYTES};
+
+/// How many times in a row a daemon may be unreachable before it's given up on.
+pub const MAX_CONSECUTIVE_FAILURES: usize = 3;
+
+/// How many times a failed job is retried unless `Coordinator::retries` says
+/// otherwise.
+pub const DEFAULT_RETRIES: usize = 2;
+
+/// A render daemon to send jobs to.
+#[derive(Clone, Debug)]
+pub enum Daemon {
+    /// A `RenderService` at `host:port`.
+    Http(String),
+    /// A `serve_ipc` Unix domain socket or Windows named pipe.
+    Ipc(PathBuf),
+}
+
+impl fmt::Display for Daemon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Daemon::Http(address) => write!(f, "http://{}", address),
+            Daemon::Ipc(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// One entry of a manifest: a `wire::WireRequest` and the name its export is
+/// reported under.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Job {
+    pub name: String,
+    pub request: Value,
+}
+
+/// An export a daemon answered with.
+#[derive(Clone, Debug)]
+pub struct Export {
+    pub mime_type: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Why a job failed.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum JobError {
+    #[error("{daemon} refused the request: {message}")]
+    Rejected { daemon: String, message: String },
+    #[error("{daemon} failed the render: {message}")]
+    Failed { daemon: String, message: String },
+    #[error("{daemon} could not be reached: {message}")]
+    Unreachable { daemon: String, message: String },
+    #[error("every daemon was given up on before the job could run")]
+    NoDaemon,
+}
+
+/// How one job of a run went.
+#[derive(Debug)]
+pub struct JobReport {
+    pub name: String,
+    /// How many daemons the job was sent to, retries included.
+    pub attempts: usize,
+    /// The daemon of the last attempt, if there was one.
+    pub daemon: Option<String>,
+    pub result: Result<Export, JobError>,
+}
+
+/// Spreads jobs over render daemons.
+pub struct Coordinator {
+    daemons: Vec<(Daemon, usize)>,
+    retries: usize,
+    timeout: Option<Duration>,
+}
+
+impl Default for Coordinator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Coordinator {
+    /// A coordinator with no daemons yet, retrying failed jobs
+    /// `DEFAULT_RETRIES` times and waiting on renders without a limit.
+    pub fn new() -> Self {
+        Coordinator {
+            daemons: Vec::new(),
+            retries: DEFAULT_RETRIES,
+            timeout: None,
+        }
+    }
+
+    /// Send jobs to `daemon`, up to `concurrency` at once; a `RenderService`
+    /// renders as many at once as its pool is large, a `serve_ipc` renderer one.
+    pub fn daemon(mut self, daemon: Daemon, concurrency: usize) -> Self {
+        self.daemons.push((daemon, concurrency.max(1)));
+        self
+    }
+
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Fail an attempt, to be retried, if the daemon takes longer to answer.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Render every job and report each, in the order of `jobs`. A job's
+    /// export needn't come from the daemon it was first queued on.
+    pub async fn run(&self, jobs: Vec<Job>) -> Vec<JobReport> {
+        let mut queues: Vec<_> = self.daemons.iter().map(|_| VecDeque::new()).collect();
+        let mut bodies = Vec::with_capacity(jobs.len());
+        let mut reports = Vec::with_capacity(jobs.len());
+        for (index, job) in jobs.into_iter().enumerate() {
+            if !queues.is_empty() {
+                let queue = index % queues.len();
+                queues[queue].push_back(Queued {
+                    index,
+                    attempts: 0,
+                });
+            }
+            bodies.push(job.request.to_string().into_bytes());
+            reports.push(JobReport {
+                name: job.name,
+                attempts: 0,
+                daemon: None,
+                result: Err(JobError::NoDaemon),
+            });
+        }
+        let shared = Arc::new(Shared {
+            daemons: self
+                .daemons
+                .iter()
+                .map(|(daemon, _)| DaemonState {
+                    daemon: daemon.clone(),
+                    failures: AtomicUsize::new(0),
+                    given_up: AtomicBool::new(false),
+                })
+                .collect(),
+            pending: AtomicUsize::new(bodies.len()),
+            queues: Mutex::new(queues),
+            reports: Mutex::new(reports),
+            changed: Notify::new(),
+            bodies,
+            retries: self.retries,
+            timeout: self.timeout,
+        });
+        let mut workers = JoinSet::new();
+        for (slot, (_, concurrency)) in self.daemons.iter().enumerate() {
+            for _ in 0..*concurrency {
+                let shared = Arc::clone(&shared);
+                workers.spawn(async move { shared.work(slot).await });
+            }
+        }
+        while workers.join_next().await.is_some() {}
+        // Whatever is still queued was left when the last daemon was given up on,
+        // and keeps the error of its last attempt.
+        let reports = std::mem::take(&mut *shared.reports.lock().unwrap());
+        reports
+    }
+}
+
+struct Queued {
+    index: usize,
+    attempts: usize,
+}
+
+struct DaemonState {
+    daemon: Daemon,
+    /// Attempts in a row that couldn't reach the daemon.
+    failures: AtomicUsize,
+    given_up: AtomicBool,
+}
+
+struct Shared {
+    daemons: Vec<DaemonState>,
+    /// Jobs neither done nor failed for good.
+    pending: AtomicUsize,
+    queues: Mutex<Vec<VecDeque<Queued>>>,
+    reports: Mutex<Vec<JobReport>>,
+    /// Notified when a job is queued or finished, or a daemon given up on.
+    changed: Notify,
+    bodies: Vec<Vec<u8>>,
+    retries: usize,
+    timeout: Option<Duration>,
+}
+
+impl Shared {
+    /// Run jobs on the daemon of `slot` until none are left or it's given up on.
+    async fn work(&self, slot: usize) {
+        while let Some(mut queued) = self.next(slot).await {
+            queued.attempts += 1;
+            let daemon = &self.daemons[slot];
+            let result = self.attempt(&daemon.daemon, &self.bodies[queued.index]).await;
+            match &result {
+                Err(JobError::Unreachable { .. }) => {
+                    let failures = daemon.failures.fetch_add(1, Ordering::SeqCst) + 1;
+                    if failures >= MAX_CONSECUTIVE_FAILURES {
+                        daemon.given_up.store(true, Ordering::SeqCst);
+                    }
+                }
+                _ => daemon.failures.store(0, Ordering::SeqCst),
+            }
+            let retry = matches!(
+                result,
+                Err(JobError::Failed { .. } | JobError::Unreachable { .. })
+            ) && queued.attempts <= self.retries;
+            {
+                let mut reports = self.reports.lock().unwrap();
+                let report = &mut reports[queued.index];
+                report.attempts = queued.attempts;
+                report.daemon = Some(daemon.daemon.to_string());
+                report.result = result;
+            }
+            if retry {
+                self.requeue(queued, slot);
+            } else {
+                self.pending.fetch_sub(1, Ordering::SeqCst);
+            }
+            self.changed.notify_waiters();
+        }
+    }
+
+    /// The next job for the daemon of `slot`: the front of its own queue, else
+    /// the back of the longest other one. `None` once every job is done or the
+    /// daemon is given up on.
+    async fn next(&self, slot: usize) -> Option<Queued> {
+        loop {
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+            if self.daemons[slot].given_up.load(Ordering::SeqCst)
+                || self.pending.load(Ordering::SeqCst) == 0
+            {
+                return None;
+            }
+            {
+                let mut queues = self.queues.lock().unwrap();
+                if let Some(queued) = queues[slot].pop_front() {
+                    return Some(queued);
+                }
+                let longest = (0..queues.len()).max_by_key(|&other| queues[other].len());
+                if let Some(queued) = longest.and_then(|other| queues[other].pop_back()) {
+                    return Some(queued);
+                }
+            }
+            // Jobs are in flight elsewhere and may come back to be retried.
+            changed.await;
+        }
+    }
+
+    /// Queue a failed job again on the daemon with the shortest queue, another
+    /// than the one of `slot` if any is left.
+    fn requeue(&self, queued: Queued, slot: usize) {
+        let mut queues = self.queues.lock().unwrap();
+        let target = (0..queues.len())
+            .filter(|&other| !self.daemons[other].given_up.load(Ordering::SeqCst))
+            .min_by_key(|&other| (other == slot, queues[other].len()))
+            .unwrap_or(slot);
+        queues[target].push_back(queued);
+    }
+
+    async fn attempt(&self, daemon: &Daemon, body: &[u8]) -> Result<Export, JobError> {
+        let send = async {
+            match daemon {
+                Daemon::Http(address) => send_http(daemon, address, body).await,
+                Daemon::Ipc(path) => send_ipc(daemon, path, body).await,
+            }
+        };
+        match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, send).await {
+                Ok(result) => result,
+                Err(_) => Err(JobError::Failed {
+                    daemon: daemon.to_string(),
+                    message: format!("no answer within {:?}", timeout),
+                }),
+            },
+            None => send.await,
+        }
+    }
+}
+
+fn unreachable(daemon: &Daemon, error: impl fmt::Display) -> JobError {
+    JobError::Unreachable {
+        daemon: daemon.to_string(),
+        message: error.to_string(),
+    }
+}
+
+/// `POST /render` `body` to the `RenderService` at `address`.
+async fn send_http(daemon: &Daemon, address: &str, body: &[u8]) -> Result<Export, JobError> {
+    let mut stream = tokio::net::TcpStream::connect(address)
+        .await
+        .map_err(|e| unreachable(daemon, e))?;
+    let head = format!(
+        "POST /render HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        address,
+        body.len()
+    );
+    stream
+        .write_all(head.as_bytes())
+        .await
+        .map_err(|e| unreachable(daemon, e))?;
+    stream.write_all(body).await.map_err(|e| unreachable(daemon, e))?;
+    // The service closes the connection after its response.
+    let mut response = Vec::new();
+    stream
+        .take(MAX_FRAME_BYTES as u64)
+        .read_to_end(&mut response)
+        .await
+        .map_err(|e| unreachable(daemon, e))?;
+    let Some(end) = response.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return Err(unreachable(daemon, "the response has no end of headers"));
+    };
+    let head = String::from_utf8_lossy(&response[..end]);
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| unreachable(daemon, "the response has no status"))?;
+    let mime_type = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-type"))
+        .map(|(_, value)| value.trim().to_string())
+        .unwrap_or_default();
+    let bytes = response[end + 4..].to_vec();
+    let message = || String::from_utf8_lossy(&bytes).into_owned();
+    match status {
+        200 => Ok(Export { mime_type, bytes }),
+        // The document or request is at fault, and would fail anywhere.
+        400 | 413 | 422 => Err(JobError::Rejected {
+            daemon: daemon.to_string(),
+            message: message(),
+        }),
+        _ => Err(JobError::Failed {
+            daemon: daemon.to_string(),
+            message: message(),
+        }),
+    }
+}
+
+/// Send `body` as a request frame to the `serve_ipc` socket at `path`.
+async fn send_ipc(daemon: &Daemon, path: &Path, body: &[u8]) -> Result<Export, JobError> {
+    #[cfg(unix)]
+    let mut stream = tokio::net::UnixStream::connect(path)
+        .await
+        .map_err(|e| unreachable(daemon, e))?;
+    #[cfg(windows)]
+    let mut stream = tokio::net::windows::named_pipe::ClientOptions::new()
+        .open(path)
+        .map_err(|e| unreachable(daemon, e))?;
+    write_frame(&mut stream, body)
+        .await
+        .map_err(|e| unreachable(daemon, e))?;
+    stream.flush().await.map_err(|e| unreachable(daemon, e))?;
+    let closed = || unreachable(daemon, "the connection closed before the answer");
+    let header = read_frame(&mut stream)
+        .await
+        .map_err(|e| unreachable(daemon, e))?
+        .ok_or_else(closed)?;
+    let header: Value =
+        serde_json::from_slice(&header).map_err(|e| unreachable(daemon, e))?;
+    if header["ok"] != Value::Bool(true) {
+        // The server doesn't say whose fault a failure is, so it's retried.
+        return Err(JobError::Failed {
+            daemon: daemon.to_string(),
+            message: header["error"].as_str().unwrap_or_default().to_string(),
+        });
+    }
+    let bytes = read_frame(&mut stream)
+        .await
+        .map_err(|e| unreachable(daemon, e))?
+        .ok_or_else(closed)?;
+    Ok(Export {
+        mime_type: header["mime_type"].as_str().unwrap_or_default().to_string(),
+        bytes,
+    })
+}
@@ -0,0 +1,100 @@
+//! The WebView2 user data folder. Two processes sharing one corrupt each other's
+//! profile, so every process locks the folder it uses and falls back to a
+//! suffixed sibling (`wry_bokeh_helper-1`, `-2`, ...) while another holds it.
+
+use std::fs::{File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::System::Com::CoTaskMemFree;
+use windows::Win32::UI::Shell::{FOLDERID_RoamingAppData, SHGetKnownFolderPath, KF_FLAG_DEFAULT};
+
+const LOCK_FILE: &str = "wry_bokeh_helper.lock";
+
+/// Environment variable overriding the default base folder, e.g. for services
+/// whose account has no roaming profile.
+const DATA_DIR_ENV: &str = "WRY_BOKEH_HELPER_DATA_DIR";
+
+/// Suffixed folders tried before falling back to one of this process's own.
+const MAX_INSTANCES: u32 = 64;
+
+/// A data folder this process has locked for its whole lifetime, and the base
+/// folder it was chosen for.
+struct LockedDir {
+    base: PathBuf,
+    dir: PathBuf,
+    _lock: File,
+}
+
+static LOCKED_DIRS: Mutex<Vec<LockedDir>> = Mutex::new(Vec::new());
+
+/// The user data folder for webviews of this process, `base` or, by default,
+/// the folder named by `DATA_DIR_ENV` or `wry_bokeh_helper` in the roaming
+/// application data folder. Every webview of the process shares the folder;
+/// other processes get their own.
+pub(crate) fn user_data_dir(base: Option<&Path>) -> PathBuf {
+    let base = base.map(Path::to_path_buf).unwrap_or_else(default_base);
+    let mut locked_dirs = LOCKED_DIRS.lock().unwrap();
+    if let Some(locked) = locked_dirs.iter().find(|locked| locked.base == base) {
+        return locked.dir.clone();
+    }
+
+    for instance in 0..MAX_INSTANCES {
+        let dir = match instance {
+            0 => base.clone(),
+            _ => suffixed(&base, instance),
+        };
+        if let Ok(lock) = lock(&dir) {
+            locked_dirs.push(LockedDir {
+                base,
+                dir: dir.clone(),
+                _lock: lock,
+            });
+            return dir;
+        }
+    }
+    suffixed(&base, std::process::id())
+}
+
+fn lock(dir: &Path) -> std::io::Result<File> {
+    std::fs::create_dir_all(dir)?;
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(LOCK_FILE))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(std::io::ErrorKind::WouldBlock.into()),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+fn suffixed(base: &Path, instance: u32) -> PathBuf {
+    let mut name = base.file_name().unwrap_or_default().to_os_string();
+    name.push(format!("-{}", instance));
+    base.with_file_name(name)
+}
+
+fn default_base() -> PathBuf {
+    if let Some(dir) = std::env::var_os(DATA_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    // SAFETY: the returned string is only read before being freed once.
+    let app_data = unsafe {
+        SHGetKnownFolderPath(&FOLDERID_RoamingAppData, KF_FLAG_DEFAULT, HANDLE::default()).map(
+            |path| {
+                let app_data = path.to_string().ok();
+                CoTaskMemFree(Some(path.0 as *const _));
+                app_data
+            },
+        )
+    };
+    app_data
+        .ok()
+        .flatten()
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("wry_bokeh_helper")
+}
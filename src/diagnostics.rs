@@ -0,0 +1,287 @@
+//! Checks that help decide which export path to trust for a given document.
+
+use std::fmt;
+use std::ops::Add;
+
+use image::RgbaImage;
+use serde_json::Value;
+
+use crate::bokeh_helpers::{js_string, render_bokeh_in_webview, render_call_in_webview};
+use crate::fixtures;
+use crate::options::{Dpi, RenderFormat, RenderOptions};
+use crate::output::{BokehRenderError, RenderOutput};
+use crate::pdf::{Margins, PageSize};
+use crate::postprocess::PNG_SIGNATURE;
+use crate::resources::BokehResource;
+
+/// Side of the square cells `compare_backends` compares coverage in, in pixels.
+const CELL_SIZE: u32 = 32;
+
+/// A channel difference above which two pixels count as different; small
+/// differences are antialiasing, which the two backends do differently.
+const PIXEL_TOLERANCE: u8 = 48;
+
+/// How large a document is, attached to `BokehRenderError::PageOutOfMemory`
+/// to tell which documents outgrow the webview.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DocumentStats {
+    pub json_bytes: usize,
+    /// Bokeh models defined in the document: plots, glyphs, data sources and so on.
+    pub models: usize,
+    /// Numbers in the document's arrays, and elements of its encoded ndarrays.
+    pub data_points: u64,
+}
+
+impl DocumentStats {
+    /// Count the models and data points of `json_data`. Invalid JSON only
+    /// counts its bytes.
+    pub fn of(json_data: &str) -> Self {
+        let mut stats = DocumentStats {
+            json_bytes: json_data.len(),
+            ..Default::default()
+        };
+        if let Ok(value) = serde_json::from_str::<Value>(json_data) {
+            stats.count(&value);
+        }
+        stats
+    }
+
+    fn count(&mut self, value: &Value) {
+        match value {
+            Value::Array(items) => {
+                for item in items {
+                    match item {
+                        Value::Number(_) => self.data_points += 1,
+                        item => self.count(item),
+                    }
+                }
+            }
+            Value::Object(object) => {
+                // Bokeh 3 tags definitions `"type": "object"`, Bokeh 2 gives them
+                // `attributes`; references carry only the id.
+                if object.contains_key("id")
+                    && (object.get("type").and_then(Value::as_str) == Some("object")
+                        || object.contains_key("attributes"))
+                {
+                    self.models += 1;
+                }
+                let ndarray = object.get("type").and_then(Value::as_str) == Some("ndarray")
+                    || object.contains_key("__ndarray__");
+                match object.get("shape").and_then(Value::as_array) {
+                    Some(shape) if ndarray => {
+                        self.data_points += shape.iter().filter_map(Value::as_u64).product::<u64>()
+                    }
+                    _ => object.values().for_each(|item| self.count(item)),
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Add for DocumentStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        DocumentStats {
+            json_bytes: self.json_bytes + other.json_bytes,
+            models: self.models + other.models,
+            data_points: self.data_points + other.data_points,
+        }
+    }
+}
+
+impl fmt::Display for DocumentStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes of JSON, {} models and {} data points",
+            self.json_bytes, self.models, self.data_points
+        )
+    }
+}
+
+/// A rectangle of the exported image, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How the canvas and SVG backends exported the same document.
+#[derive(Debug)]
+pub struct BackendComparison {
+    /// PNG export drawn with the canvas backend.
+    pub canvas: RenderOutput,
+    /// PNG rasterization of the SVG backend's export.
+    pub svg: RenderOutput,
+    /// Fraction of the overlapping area whose pixels differ beyond antialiasing.
+    pub differing_pixels: f64,
+    /// Cells the canvas backend drew on that the SVG backend left blank.
+    pub missing_in_svg: Vec<Region>,
+    /// Cells the SVG backend drew on that the canvas backend left blank.
+    pub missing_in_canvas: Vec<Region>,
+}
+
+impl BackendComparison {
+    pub fn size_mismatch(&self) -> bool {
+        (self.canvas.width, self.canvas.height) != (self.svg.width, self.svg.height)
+    }
+
+    /// Whether both exports have the same size and draw in the same places.
+    pub fn is_consistent(&self) -> bool {
+        !self.size_mismatch() && self.missing_in_svg.is_empty() && self.missing_in_canvas.is_empty()
+    }
+}
+
+/// Export `json_data` once with each backend and compare the results: their
+/// size, how many pixels differ and which areas only one of them drew on.
+pub async fn compare_backends(
+    json_data: &str,
+    dpi: impl Into<Dpi>,
+    resource: Option<BokehResource>,
+    options: RenderOptions,
+) -> Result<BackendComparison, BokehRenderError> {
+    let dpi = options.dpi_for(dpi).validate()?;
+    let format = RenderFormat::Image("image/png".to_string());
+    let options = options.sized_for(json_data);
+    let stats = DocumentStats::of(json_data);
+    let canvas = render_bokeh_in_webview(
+        json_data,
+        dpi,
+        format.clone(),
+        resource.clone(),
+        options.clone(),
+    )
+    .await?;
+    let call = format!("renderBokeh({}, {}, `image/png`, `svg`)", js_string(json_data), dpi);
+    let svg = render_call_in_webview(&call, stats, dpi, format, resource, options).await?;
+
+    let canvas_image = decode(&canvas)?;
+    let svg_image = decode(&svg)?;
+    let width = canvas_image.width().min(svg_image.width());
+    let height = canvas_image.height().min(svg_image.height());
+
+    let mut differing = 0u64;
+    for y in 0..height {
+        for x in 0..width {
+            if differs(canvas_image.get_pixel(x, y).0, svg_image.get_pixel(x, y).0) {
+                differing += 1;
+            }
+        }
+    }
+
+    let mut missing_in_svg = Vec::new();
+    let mut missing_in_canvas = Vec::new();
+    for y in (0..height).step_by(CELL_SIZE as usize) {
+        for x in (0..width).step_by(CELL_SIZE as usize) {
+            let region = Region {
+                x,
+                y,
+                width: CELL_SIZE.min(width - x),
+                height: CELL_SIZE.min(height - y),
+            };
+            let canvas_ink = ink(&canvas_image, region);
+            let svg_ink = ink(&svg_image, region);
+            // Drawn in one export, nearly blank in the other.
+            if canvas_ink > 0.01 && svg_ink < canvas_ink * 0.1 {
+                missing_in_svg.push(region);
+            } else if svg_ink > 0.01 && canvas_ink < svg_ink * 0.1 {
+                missing_in_canvas.push(region);
+            }
+        }
+    }
+
+    let area = u64::from(width) * u64::from(height);
+    Ok(BackendComparison {
+        canvas,
+        svg,
+        differing_pixels: if area == 0 {
+            0.0
+        } else {
+            differing as f64 / area as f64
+        },
+        missing_in_svg,
+        missing_in_canvas,
+    })
+}
+
+/// Render the line fixture to PNG and to PDF and check that each export is what
+/// it claims to be, and that the plot was actually drawn on the PNG. For
+/// packagers to verify that an install renders on the target machine, see
+/// `examples/smoke.rs`.
+pub async fn smoke_test(resource: Option<BokehResource>) -> Result<(), BokehRenderError> {
+    let json_data = fixtures::line();
+    let options = RenderOptions::default();
+    let png = render_bokeh_in_webview(
+        &json_data,
+        96,
+        RenderFormat::Image("image/png".to_string()),
+        resource.clone(),
+        options.clone(),
+    )
+    .await?;
+    if !png.bytes.starts_with(&PNG_SIGNATURE) {
+        return Err(smoke_failure("the PNG export has no PNG signature"));
+    }
+    let image = decode(&png)?;
+    let whole = Region {
+        x: 0,
+        y: 0,
+        width: image.width(),
+        height: image.height(),
+    };
+    if whole.width == 0 || whole.height == 0 || ink(&image, whole) == 0.0 {
+        return Err(smoke_failure("the PNG export is blank"));
+    }
+
+    let format = RenderFormat::Pdf {
+        page_size: PageSize::Fit,
+        margins: Margins::default(),
+    };
+    let pdf = render_bokeh_in_webview(&json_data, 96, format, resource, options).await?;
+    if !pdf.bytes.starts_with(b"%PDF-") {
+        return Err(smoke_failure("the PDF export has no PDF signature"));
+    }
+    Ok(())
+}
+
+/// Whether the encoded image `bytes` is blank: every pixel transparent or white.
+/// Images that don't decode, such as SVG, never are.
+pub(crate) fn is_blank(bytes: &[u8]) -> bool {
+    image::load_from_memory(bytes).is_ok_and(|image| {
+        image
+            .to_rgba8()
+            .pixels()
+            .all(|pixel| pixel.0[3] == 0 || pixel.0 == [255, 255, 255, 255])
+    })
+}
+
+fn smoke_failure(message: &str) -> BokehRenderError {
+    BokehRenderError::Decode(message.to_string())
+}
+
+fn decode(output: &RenderOutput) -> Result<RgbaImage, BokehRenderError> {
+    image::load_from_memory(&output.bytes)
+        .map(|image| image.to_rgba8())
+        .map_err(|e| BokehRenderError::Decode(e.to_string()))
+}
+
+fn differs(a: [u8; 4], b: [u8; 4]) -> bool {
+    a.iter().zip(b).any(|(a, b)| a.abs_diff(b) > PIXEL_TOLERANCE)
+}
+
+/// Fraction of `region` drawn on, i.e. not the white page background.
+fn ink(image: &RgbaImage, region: Region) -> f64 {
+    let mut drawn = 0u32;
+    for y in region.y..region.y + region.height {
+        for x in region.x..region.x + region.width {
+            if differs(image.get_pixel(x, y).0, [255, 255, 255, 255]) {
+                drawn += 1;
+            }
+        }
+    }
+    f64::from(drawn) / f64::from(region.width * region.height)
+}
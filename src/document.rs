@@ -0,0 +1,209 @@
+//! Checks a document before a webview is spent on it. The page can only report
+//! garbage input as a script error, after the webview has launched and loaded
+//! BokehJS. Documents come as a `json_item`, or as a whole `Document.to_json()`
+//! whose roots are all exported.
+
+use std::borrow::Cow;
+
+use serde_json::{json, Map, Value};
+
+use crate::output::BokehRenderError;
+use crate::version::BokehVersion;
+
+/// What `validate_bokeh_json` found in a document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentInfo {
+    /// `doc.version`, the Bokeh release that produced the document, if it parses.
+    pub version: Option<BokehVersion>,
+    pub title: Option<String>,
+    /// The root a `json_item` embeds, one of `roots`; `None` for a whole
+    /// document, which is exported with all of its roots.
+    pub root_id: Option<String>,
+    pub roots: Vec<DocumentRoot>,
+}
+
+impl DocumentInfo {
+    /// The root a `json_item` embeds.
+    pub fn root(&self) -> Option<&DocumentRoot> {
+        let root_id = self.root_id.as_ref()?;
+        self.roots.iter().find(|root| root.id == *root_id)
+    }
+}
+
+/// A root model of a document, such as a figure or a layout of several.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocumentRoot {
+    pub id: String,
+    /// The model, e.g. `Figure` or `Column`.
+    pub model: String,
+    /// Width and height in CSS pixels, where the document sets them.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// E.g. `stretch_width`; `None` for the default, fixed sizing.
+    pub sizing_mode: Option<String>,
+}
+
+/// Check that `json_data` is a document the page can embed: valid JSON holding
+/// either a `json_item`, a `doc` with roots and a `root_id` naming one of them,
+/// or a whole document with `roots`. Renders run this before opening a webview;
+/// callers can run it to reject documents early and to read their roots and sizes.
+pub fn validate_bokeh_json(json_data: &str) -> Result<DocumentInfo, BokehRenderError> {
+    let item: Value =
+        serde_json::from_str(json_data).map_err(|e| invalid(format!("not JSON: {}", e)))?;
+    let item = item
+        .as_object()
+        .ok_or_else(|| invalid(format!("expected a `json_item` object, found {}", kind(&item))))?;
+    // `Document.to_json()` has the roots at its top.
+    if !item.contains_key("doc") && item.contains_key("roots") {
+        return document_info(item, None);
+    }
+    let doc = item
+        .get("doc")
+        .ok_or_else(|| invalid("`doc` is missing".to_string()))?
+        .as_object()
+        .ok_or_else(|| invalid("`doc` is not an object".to_string()))?;
+    let root_id = match item.get("root_id") {
+        Some(Value::String(root_id)) => root_id.clone(),
+        Some(root_id) => return Err(invalid(format!("`root_id` is {}", kind(root_id)))),
+        None => return Err(invalid("`root_id` is missing".to_string())),
+    };
+    document_info(doc, Some(root_id))
+}
+
+/// `json_data` as the `json_item` the page embeds: as it is, or for a whole
+/// document, an item whose root is a `Column` of all the document's roots.
+/// Fails like `validate_bokeh_json`.
+pub(crate) fn embeddable_json(json_data: &str) -> Result<Cow<'_, str>, BokehRenderError> {
+    if validate_bokeh_json(json_data)?.root_id.is_some() {
+        return Ok(Cow::Borrowed(json_data));
+    }
+    let mut doc: Value = serde_json::from_str(json_data).unwrap();
+    let roots = &mut doc["roots"];
+    match roots {
+        // Bokeh 3 defines the roots inline in the column.
+        Value::Array(models) => {
+            let column = json!({
+                "type": "object",
+                "name": "Column",
+                "id": DOCUMENT_ROOT_ID,
+                "attributes": { "children": std::mem::take(models) },
+            });
+            *roots = json!([column]);
+        }
+        // Bokeh 2 refers to the roots among the document's references.
+        roots => {
+            let children: Vec<Value> = roots["root_ids"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|id| json!({ "id": id }))
+                .collect();
+            if let Some(references) = roots["references"].as_array_mut() {
+                references.push(json!({
+                    "type": "Column",
+                    "id": DOCUMENT_ROOT_ID,
+                    "attributes": { "children": children },
+                }));
+            }
+            roots["root_ids"] = json!([DOCUMENT_ROOT_ID]);
+        }
+    }
+    let item = json!({
+        "target_id": null,
+        "root_id": DOCUMENT_ROOT_ID,
+        "doc": doc,
+    });
+    Ok(Cow::Owned(item.to_string()))
+}
+
+/// Id of the `Column` `embeddable_json` lays the roots of a whole document out in.
+const DOCUMENT_ROOT_ID: &str = "wry-bokeh-document-root";
+
+/// The info of `doc`, failing unless `root_id` names one of its roots.
+fn document_info(
+    doc: &Map<String, Value>,
+    root_id: Option<String>,
+) -> Result<DocumentInfo, BokehRenderError> {
+    let roots = document_roots(doc)?;
+    if let Some(root_id) = root_id.as_ref().filter(|id| !roots.iter().any(|root| root.id == **id))
+    {
+        let ids: Vec<&str> = roots.iter().map(|root| root.id.as_str()).collect();
+        return Err(invalid(format!(
+            "`root_id` {} is not one of the roots of `doc` ({})",
+            root_id,
+            ids.join(", ")
+        )));
+    }
+    Ok(DocumentInfo {
+        version: doc
+            .get("version")
+            .and_then(Value::as_str)
+            .and_then(|version| version.parse().ok()),
+        title: doc.get("title").and_then(Value::as_str).map(str::to_string),
+        root_id,
+        roots,
+    })
+}
+
+/// The roots of `doc`: a list of models in Bokeh 3, references picked out by
+/// `root_ids` in Bokeh 2.
+fn document_roots(doc: &Map<String, Value>) -> Result<Vec<DocumentRoot>, BokehRenderError> {
+    let (models, ids) = match doc.get("roots") {
+        Some(Value::Array(models)) => (models, None),
+        Some(Value::Object(roots)) => match (roots.get("references"), roots.get("root_ids")) {
+            (Some(Value::Array(references)), Some(Value::Array(ids))) => {
+                (references, Some(ids))
+            }
+            _ => {
+                return Err(invalid(
+                    "`doc.roots` has no `references` and `root_ids`".to_string(),
+                ))
+            }
+        },
+        Some(roots) => return Err(invalid(format!("`doc.roots` is {}", kind(roots)))),
+        None => return Err(invalid("`doc.roots` is missing".to_string())),
+    };
+    let roots: Vec<DocumentRoot> = models
+        .iter()
+        .filter_map(document_root)
+        .filter(|root| ids.is_none_or(|ids| ids.contains(&Value::from(root.id.as_str()))))
+        .collect();
+    match roots.is_empty() {
+        true => Err(invalid("`doc.roots` holds no models".to_string())),
+        false => Ok(roots),
+    }
+}
+
+fn document_root(model: &Value) -> Option<DocumentRoot> {
+    let id = model.get("id")?.as_str()?;
+    // Bokeh 3 names models, Bokeh 2 types them.
+    let name = model.get("name").or_else(|| model.get("type"))?.as_str()?;
+    let attributes = model.get("attributes");
+    let attribute = |name: &str| attributes.and_then(|attributes| attributes.get(name));
+    let size = |name: &str| attribute(name).and_then(Value::as_f64).map(|size| size.round() as u32);
+    Some(DocumentRoot {
+        id: id.to_string(),
+        model: name.to_string(),
+        width: size("width"),
+        height: size("height"),
+        sizing_mode: attribute("sizing_mode")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+    })
+}
+
+/// What a JSON value is, for error messages.
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+fn invalid(reason: String) -> BokehRenderError {
+    BokehRenderError::InvalidDocument(reason)
+}
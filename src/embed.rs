@@ -0,0 +1,205 @@
+//! Renders inside an event loop the application already runs, e.g. Tauri's,
+//! which can't start another one with `run_return`.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tao::{
+    event_loop::{EventLoopProxy, EventLoopWindowTarget},
+    window::Window,
+};
+use tokio::sync::oneshot;
+use wry::{WebView, WebViewBuilder};
+
+use crate::bokeh_helpers::{
+    apply_browser_args, export_call, render_webview_builder, render_window_builder, PendingPdf,
+};
+use crate::diagnostics::DocumentStats;
+use crate::document::embeddable_json;
+use crate::offline::{check_offline_document, check_offline_resources};
+use crate::options::{Dpi, RenderFormat, RenderOptions};
+use crate::output::{BokehRenderError, ConsoleMessage, RenderOutput};
+use crate::process::watch_web_process;
+use crate::protocol::{image_output, EventSink, UserEvent};
+use crate::resources::BokehResource;
+use crate::runtime::webview_error;
+use crate::tiles::Stitcher;
+
+static NEXT_JOB: AtomicU64 = AtomicU64::new(0);
+
+/// An event of one `BokehRenderJob`'s page, delivered to the host event loop
+/// wrapped in the host's own user event type.
+pub struct RenderJobEvent {
+    job: u64,
+    event: UserEvent,
+}
+
+impl RenderJobEvent {
+    /// The `BokehRenderJob::id` of the job that should handle this event.
+    pub fn job(&self) -> u64 {
+        self.job
+    }
+}
+
+/// One render in a hidden window and webview created in the host's event loop.
+/// The host hands the job every `RenderJobEvent` its loop receives until
+/// `handle_event` reports it done, then drops it.
+pub struct BokehRenderJob {
+    id: u64,
+    webview: WebView,
+    events: EventSink,
+    _window: Window,
+    #[cfg(target_os = "windows")]
+    _web_context: wry::WebContext,
+    dpi: Dpi,
+    format: RenderFormat,
+    options: RenderOptions,
+    stats: DocumentStats,
+    reply: Option<oneshot::Sender<Result<RenderOutput, BokehRenderError>>>,
+    pending_pdf: Option<PendingPdf>,
+    stitcher: Stitcher,
+    console: Vec<ConsoleMessage>,
+}
+
+impl BokehRenderJob {
+    /// Start rendering `json_data` like `render_bokeh_in_webview` does, with the
+    /// window created on `target` and the page's events sent through `proxy`.
+    /// The returned future resolves with the export once the job has handled
+    /// the event carrying it, or fails if the job is dropped before.
+    pub fn attach<T: From<RenderJobEvent> + Send + 'static>(
+        target: &EventLoopWindowTarget<T>,
+        proxy: EventLoopProxy<T>,
+        json_data: &str,
+        dpi: impl Into<Dpi>,
+        format: RenderFormat,
+        resource: Option<BokehResource>,
+        options: RenderOptions,
+    ) -> Result<
+        (
+            Self,
+            impl Future<Output = Result<RenderOutput, BokehRenderError>> + Send + 'static,
+        ),
+        BokehRenderError,
+    > {
+        let json_data = embeddable_json(json_data)?;
+        let json_data = json_data.as_ref();
+        let dpi = options.dpi_for(dpi).validate()?;
+        let call = export_call(json_data, dpi, &format, &options)?;
+        if options.offline {
+            check_offline_resources(resource.as_ref(), &options)?;
+            check_offline_document(json_data)?;
+        }
+        let options = options.sized_for(json_data);
+        let id = NEXT_JOB.fetch_add(1, Ordering::Relaxed);
+        let events = EventSink::new(move |event| {
+            let _ = proxy.send_event(T::from(RenderJobEvent { job: id, event }));
+        });
+
+        let window = render_window_builder(&options)
+            .build(target)
+            .map_err(|e| BokehRenderError::Renderer(e.to_string()))?;
+        let dpi = dpi.on_window(&window);
+
+        #[cfg(target_os = "windows")]
+        let mut web_context = crate::bokeh_helpers::new_web_context(&options);
+        #[cfg(target_os = "windows")]
+        let webview_builder = WebViewBuilder::with_web_context(&mut web_context);
+
+        #[cfg(not(target_os = "windows"))]
+        let webview_builder = WebViewBuilder::new();
+
+        let webview =
+            render_webview_builder(webview_builder, resource, options.clone(), events.clone())
+                .build(&window)
+                .map_err(webview_error)?;
+        apply_browser_args(&webview, &options);
+        watch_web_process(&webview, events.clone());
+        webview
+            .evaluate_script(&format!("window.onload = () => {}", call))
+            .map_err(|e| BokehRenderError::Renderer(e.to_string()))?;
+
+        let (tx, rx) = oneshot::channel();
+        let job = BokehRenderJob {
+            id,
+            webview,
+            events,
+            _window: window,
+            #[cfg(target_os = "windows")]
+            _web_context: web_context,
+            dpi,
+            format,
+            options,
+            stats: DocumentStats::of(json_data),
+            reply: Some(tx),
+            pending_pdf: None,
+            stitcher: Stitcher::default(),
+            console: Vec::new(),
+        };
+        let output = async move {
+            rx.await.unwrap_or_else(|_| {
+                Err(BokehRenderError::Renderer(
+                    "The render job was dropped before the export".to_string(),
+                ))
+            })
+        };
+        Ok((job, output))
+    }
+
+    /// Identifies the job's events, see `RenderJobEvent::job`.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Handle an event of the job's page. Returns whether the job is done, its
+    /// result delivered, and can be dropped. Events of other jobs are ignored.
+    pub fn handle_event(&mut self, event: RenderJobEvent) -> bool {
+        if event.job != self.id {
+            return self.reply.is_none();
+        }
+        let result = match event.event {
+            UserEvent::Console(message) => {
+                self.console.push(message);
+                None
+            }
+            UserEvent::PayloadReceived(image) => Some(image_output(
+                image,
+                self.dpi,
+                self.format.clone(),
+                &self.options,
+            )),
+            UserEvent::Tile(tile) => self.stitcher.add(tile, self.dpi, &self.options),
+            UserEvent::Printable {
+                width,
+                height,
+                timings,
+            } => {
+                self.pending_pdf = PendingPdf::start(
+                    &self.webview,
+                    &self.format,
+                    width,
+                    height,
+                    timings,
+                    self.events.clone(),
+                );
+                None
+            }
+            UserEvent::PdfPrinted(result) => self
+                .pending_pdf
+                .take()
+                .map(|pending| pending.finish(result, self.dpi, self.format.clone())),
+            UserEvent::PageError(message) => Some(Err(BokehRenderError::Page(message))),
+            UserEvent::ResourceLoadFailed(error) => Some(Err(error)),
+            UserEvent::ProcessFailed { out_of_memory } => Some(Err(
+                BokehRenderError::process_failed(out_of_memory, self.stats),
+            )),
+            _ => None,
+        };
+        if let Some(result) = result {
+            let console = std::mem::take(&mut self.console);
+            if let Some(reply) = self.reply.take() {
+                let _ = reply.send(result.map(|output| output.with_console(console)));
+            }
+        }
+        self.reply.is_none()
+    }
+}
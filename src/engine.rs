@@ -0,0 +1,227 @@
+//! Rasterizes charts of other JavaScript libraries in the same hidden webview
+//! as Bokeh documents: the event loop, the `wry://` protocol, IPC, DPI and
+//! post-processing don't care which library drew the canvas. A `ChartEngine`
+//! says how to load its library and draw a spec; Vega-Lite, Plotly and ECharts
+//! ship with the crate.
+
+use crate::bokeh_helpers::{
+    build_chart_render_html, js_string, render_page_in_webview, RenderPage,
+};
+use crate::diagnostics::DocumentStats;
+use crate::offline::check_offline_extras;
+use crate::options::{Dpi, RenderFormat, RenderOptions};
+use crate::output::{BokehRenderError, RenderOutput};
+use crate::resources::ScriptSource;
+
+/// A chart library the render page can draw specs of.
+pub trait ChartEngine {
+    /// The library's name, for errors, e.g. `Vega-Lite`.
+    fn name(&self) -> &str;
+
+    /// The library's scripts in load order, served ahead of
+    /// `RenderOptions::extra_scripts`.
+    fn scripts(&self) -> Vec<ScriptSource>;
+
+    /// Page JavaScript defining `renderChart(container, spec, pixelRatio)`,
+    /// which draws the parsed `spec` inside the `container` element and
+    /// returns, or resolves to, a canvas of the chart drawn at `pixelRatio`
+    /// device pixels per CSS pixel.
+    fn render_script(&self) -> String;
+}
+
+/// Vega-Lite specs, compiled to Vega and drawn headless with Vega's canvas
+/// renderer.
+#[derive(Clone, Debug)]
+pub struct VegaLiteEngine {
+    /// Vega, then Vega-Lite; by default from jsDelivr.
+    pub scripts: Vec<ScriptSource>,
+}
+
+impl Default for VegaLiteEngine {
+    fn default() -> Self {
+        VegaLiteEngine {
+            scripts: vec![
+                ScriptSource::Url(
+                    "https://cdn.jsdelivr.net/npm/vega@5.30.0/build/vega.min.js".into(),
+                ),
+                ScriptSource::Url(
+                    "https://cdn.jsdelivr.net/npm/vega-lite@5.21.0/build/vega-lite.min.js".into(),
+                ),
+            ],
+        }
+    }
+}
+
+const VEGA_LITE_SCRIPT: &str = "
+    function renderChart(container, spec, pixelRatio) {
+        const view = new vega.View(vega.parse(vegaLite.compile(spec).spec), { renderer: 'none' });
+        return view.toCanvas(pixelRatio).finally(() => view.finalize());
+    }
+";
+
+impl ChartEngine for VegaLiteEngine {
+    fn name(&self) -> &str {
+        "Vega-Lite"
+    }
+
+    fn scripts(&self) -> Vec<ScriptSource> {
+        self.scripts.clone()
+    }
+
+    fn render_script(&self) -> String {
+        VEGA_LITE_SCRIPT.to_string()
+    }
+}
+
+/// Plotly figures, `{ data, layout }` as `plotly.io.to_json` writes them, drawn
+/// as a static plot at the size of their layout, or Plotly's default size.
+#[derive(Clone, Debug)]
+pub struct PlotlyEngine {
+    /// `plotly.js`; by default Plotly's CDN build.
+    pub scripts: Vec<ScriptSource>,
+}
+
+impl Default for PlotlyEngine {
+    fn default() -> Self {
+        PlotlyEngine {
+            scripts: vec![ScriptSource::Url("https://cdn.plot.ly/plotly-2.35.2.min.js".into())],
+        }
+    }
+}
+
+/// `Plotly.toImage` only hands out an encoded image, decoded back to a canvas.
+const PLOTLY_SCRIPT: &str = "
+    function renderChart(container, figure, pixelRatio) {
+        const config = { staticPlot: true };
+        return Plotly.newPlot(container, figure.data ?? [], figure.layout ?? {}, config)
+            .then((plot) => Plotly.toImage(plot, { format: 'png', scale: pixelRatio }))
+            .then((url) => {
+                const image = new Image();
+                image.src = url;
+                return image.decode().then(() => {
+                    const canvas = document.createElement('canvas');
+                    canvas.width = image.naturalWidth;
+                    canvas.height = image.naturalHeight;
+                    canvas.getContext('2d').drawImage(image, 0, 0);
+                    Plotly.purge(container);
+                    return canvas;
+                });
+            });
+    }
+";
+
+impl ChartEngine for PlotlyEngine {
+    fn name(&self) -> &str {
+        "Plotly"
+    }
+
+    fn scripts(&self) -> Vec<ScriptSource> {
+        self.scripts.clone()
+    }
+
+    fn render_script(&self) -> String {
+        PLOTLY_SCRIPT.to_string()
+    }
+}
+
+/// ECharts options, drawn with animations off by the canvas renderer. An
+/// option doesn't size its chart, so the engine does.
+#[derive(Clone, Debug)]
+pub struct EChartsEngine {
+    /// `echarts`; by default from jsDelivr.
+    pub scripts: Vec<ScriptSource>,
+    /// Width and height of the chart in CSS pixels.
+    pub size: (u32, u32),
+}
+
+impl Default for EChartsEngine {
+    fn default() -> Self {
+        EChartsEngine {
+            scripts: vec![ScriptSource::Url(
+                "https://cdn.jsdelivr.net/npm/echarts@5.5.1/dist/echarts.min.js".into(),
+            )],
+            size: (600, 400),
+        }
+    }
+}
+
+const ECHARTS_SCRIPT: &str = "
+    function renderChart(container, option, pixelRatio) {
+        const [width, height] = ECHARTS_SIZE;
+        const chart = echarts.init(container, null, {
+            renderer: 'canvas',
+            devicePixelRatio: pixelRatio,
+            width,
+            height,
+        });
+        return new Promise((resolve) => {
+            chart.on('finished', () => resolve(chart.getDom().querySelector('canvas')));
+            chart.setOption({ ...option, animation: false });
+        });
+    }
+";
+
+impl ChartEngine for EChartsEngine {
+    fn name(&self) -> &str {
+        "ECharts"
+    }
+
+    fn scripts(&self) -> Vec<ScriptSource> {
+        self.scripts.clone()
+    }
+
+    fn render_script(&self) -> String {
+        format!(
+            "const ECHARTS_SIZE = [{}, {}];\n{}",
+            self.size.0, self.size.1, ECHARTS_SCRIPT
+        )
+    }
+}
+
+/// Render `spec`, a JSON chart of `engine`, like `render_bokeh_in_webview`
+/// renders a Bokeh document. Options about Bokeh documents, such as
+/// `wait_until` or `data_image_format`, don't apply; only untiled image exports
+/// are supported.
+pub async fn render_chart_in_webview(
+    engine: &dyn ChartEngine,
+    spec: &str,
+    dpi: impl Into<Dpi>,
+    format: RenderFormat,
+    options: RenderOptions,
+) -> Result<RenderOutput, BokehRenderError> {
+    if let Err(e) = serde_json::from_str::<serde_json::Value>(spec) {
+        return Err(BokehRenderError::InvalidDocument(format!(
+            "the {} spec is not JSON: {}",
+            engine.name(),
+            e
+        )));
+    }
+    let dpi = options.dpi_for(dpi).validate()?;
+    let RenderFormat::Image(typ) = &format else {
+        return Err(BokehRenderError::InvalidRequest(format!(
+            "{} charts can't be printed to PDF",
+            engine.name()
+        )));
+    };
+    if options.tiled.is_some() {
+        return Err(BokehRenderError::InvalidRequest(format!(
+            "{} charts can't be exported in tiles",
+            engine.name()
+        )));
+    }
+    let mut scripts = engine.scripts();
+    scripts.extend(options.extra_scripts.iter().cloned());
+    let options = RenderOptions {
+        extra_scripts: scripts,
+        data_image_format: None,
+        preview_sender: None,
+        ..options
+    };
+    if options.offline {
+        check_offline_extras(&options)?;
+    }
+    let call = format!("exportChart({}, {}, {})", js_string(spec), dpi, js_string(typ));
+    let page = RenderPage::Chart(build_chart_render_html(&engine.render_script(), &options));
+    let stats = DocumentStats::of(spec);
+    render_page_in_webview(&call, stats, dpi, format, page, options).await
+}
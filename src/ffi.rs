@@ -0,0 +1,203 @@
+//! A C ABI over `BokehRenderer`, for C, C++, C# or Java applications that load
+//! the `cdylib` instead of linking Rust; `include/wry_bokeh_helper.h` declares
+//! it. A renderer handle keeps one webview warm across renders. Functions that
+//! can fail return 0 on success and -1 on failure, with the reason from
+//! `wbh_last_error`. Buffers handed out are freed with `wbh_buffer_free`.
+//!
+//! The renderer runs its event loop on a thread of its own, which macOS doesn't
+//! allow, so `wbh_renderer_new` fails there.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+
+use crate::options::{RenderFormat, RenderOptions};
+use crate::output::BokehRenderError;
+use crate::renderer::BokehRenderer;
+use crate::resources::BokehResource;
+
+/// An opaque renderer, created with `wbh_renderer_new`.
+pub struct WbhRenderer {
+    renderer: BokehRenderer,
+    runtime: tokio::runtime::Runtime,
+}
+
+/// Settings of one render. A null pointer renders at 300 DPI.
+#[repr(C)]
+pub struct WbhRenderOptions {
+    pub dpi: f64,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl ToString) {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+/// The reason the last call on this thread failed, or null if none has. Valid
+/// until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn wbh_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(ptr::null(), |error| error.as_ptr()))
+}
+
+/// Start a renderer loading Bokeh from the bundle folder `resource_dir`, or
+/// from the CDN if it's null. Returns null on failure.
+///
+/// # Safety
+///
+/// `resource_dir` must be null or a NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn wbh_renderer_new(resource_dir: *const c_char) -> *mut WbhRenderer {
+    let resource = match resource_dir.is_null() {
+        true => None,
+        false => match CStr::from_ptr(resource_dir).to_str() {
+            Ok(dir) => match BokehResource::local(dir) {
+                Ok(resource) => Some(resource),
+                Err(e) => {
+                    set_last_error(e);
+                    return ptr::null_mut();
+                }
+            },
+            Err(e) => {
+                set_last_error(format!("resource_dir is not UTF-8: {}", e));
+                return ptr::null_mut();
+            }
+        },
+    };
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            set_last_error(e);
+            return ptr::null_mut();
+        }
+    };
+    match BokehRenderer::new(resource, RenderOptions::default()) {
+        Ok(renderer) => Box::into_raw(Box::new(WbhRenderer { renderer, runtime })),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Shut a renderer down and free it. Null is ignored.
+///
+/// # Safety
+///
+/// `renderer` must be null or a handle from `wbh_renderer_new` not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn wbh_renderer_free(renderer: *mut WbhRenderer) {
+    if !renderer.is_null() {
+        drop(Box::from_raw(renderer));
+    }
+}
+
+/// Render the `len` bytes of document JSON at `json` to PNG. On success
+/// `*out_buf` and `*out_len` hold the export, to be freed with
+/// `wbh_buffer_free`.
+///
+/// # Safety
+///
+/// `renderer` must be a live handle, `json` must point to `len` readable bytes,
+/// `options` must be null or valid, and `out_buf` and `out_len` must be valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn wbh_render_png(
+    renderer: *const WbhRenderer,
+    json: *const u8,
+    len: usize,
+    options: *const WbhRenderOptions,
+    out_buf: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    let format = RenderFormat::Image("image/png".to_string());
+    render(renderer, json, len, options, format, out_buf, out_len)
+}
+
+/// Like `wbh_render_png`, in the format `format` names: `png`, `jpeg`, `webp`
+/// or `pdf`, or their MIME type.
+///
+/// # Safety
+///
+/// As for `wbh_render_png`; `format` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn wbh_render(
+    renderer: *const WbhRenderer,
+    json: *const u8,
+    len: usize,
+    format: *const c_char,
+    options: *const WbhRenderOptions,
+    out_buf: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    let name = CStr::from_ptr(format).to_string_lossy();
+    match RenderFormat::from_name(&name) {
+        Some(format) => render(renderer, json, len, options, format, out_buf, out_len),
+        None => {
+            set_last_error(BokehRenderError::InvalidRequest(format!(
+                "unsupported format {}",
+                name
+            )));
+            -1
+        }
+    }
+}
+
+/// Free a buffer handed out by a render. Null is ignored.
+///
+/// # Safety
+///
+/// `buf` and `len` must be a buffer and its length from a render, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn wbh_buffer_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)));
+    }
+}
+
+unsafe fn render(
+    renderer: *const WbhRenderer,
+    json: *const u8,
+    len: usize,
+    options: *const WbhRenderOptions,
+    format: RenderFormat,
+    out_buf: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    let Some(renderer) = renderer.as_ref() else {
+        set_last_error("the renderer is null");
+        return -1;
+    };
+    if json.is_null() || out_buf.is_null() || out_len.is_null() {
+        set_last_error("json, out_buf and out_len must not be null");
+        return -1;
+    }
+    let json_data = match std::str::from_utf8(std::slice::from_raw_parts(json, len)) {
+        Ok(json_data) => json_data,
+        Err(e) => {
+            set_last_error(format!("the document is not UTF-8: {}", e));
+            return -1;
+        }
+    };
+    let dpi = options.as_ref().map_or(300.0, |options| options.dpi);
+    let render = renderer.renderer.render(json_data, dpi, format);
+    match renderer.runtime.block_on(render) {
+        Ok(output) => {
+            let bytes = output.bytes.into_boxed_slice();
+            *out_len = bytes.len();
+            *out_buf = Box::into_raw(bytes) as *mut u8;
+            0
+        }
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
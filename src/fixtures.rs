@@ -0,0 +1,250 @@
+//! Representative `json_item` documents built without Python, as stable inputs
+//! for tests. They follow the serialization format of Bokeh 3.x and carry
+//! deterministic ids, so the same fixture always produces the same JSON.
+
+use serde_json::{json, Value};
+
+/// The Bokeh version the fixtures declare, matching the default CDN bundles.
+pub const FIXTURE_BOKEH_VERSION: &str = "3.5.2";
+
+/// A line plot of a sine wave.
+pub fn line() -> String {
+    let mut ids = Ids::default();
+    let x: Vec<f64> = (0..50).map(|i| i as f64 / 5.0).collect();
+    let y: Vec<f64> = x.iter().map(|x| x.sin()).collect();
+    let renderer = glyph_renderer(&mut ids, "Line", json!({ "x": x, "y": y }), json!({}));
+    let plot = figure(&mut ids, "Line", Axis::Linear, vec![renderer]);
+    json_item(plot)
+}
+
+/// Scatter markers of varying size and color.
+pub fn scatter() -> String {
+    let mut ids = Ids::default();
+    let palette = ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728"];
+    let x: Vec<f64> = (0..40).map(|i| (i * 37 % 100) as f64).collect();
+    let y: Vec<f64> = (0..40).map(|i| (i * 61 % 100) as f64).collect();
+    let size: Vec<f64> = (0..40).map(|i| 6.0 + (i % 5) as f64 * 3.0).collect();
+    let color: Vec<&str> = (0..40).map(|i| palette[i % palette.len()]).collect();
+    let renderer = glyph_renderer(
+        &mut ids,
+        "Scatter",
+        json!({ "x": x, "y": y, "size": size, "color": color }),
+        json!({
+            "size": field("size"),
+            "fill_color": field("color"),
+            "line_color": field("color"),
+            "fill_alpha": 0.6,
+        }),
+    );
+    let plot = figure(&mut ids, "Scatter", Axis::Linear, vec![renderer]);
+    json_item(plot)
+}
+
+/// A daily time series on a datetime x axis.
+pub fn datetime_axis() -> String {
+    let mut ids = Ids::default();
+    // 2024-01-01T00:00:00Z in milliseconds, as Bokeh encodes datetimes.
+    let start = 1_704_067_200_000.0;
+    let day = 86_400_000.0;
+    let x: Vec<f64> = (0..60).map(|i| start + i as f64 * day).collect();
+    let y: Vec<f64> = (0..60).map(|i| 100.0 + (i as f64 / 6.0).sin() * 10.0).collect();
+    let renderer = glyph_renderer(&mut ids, "Line", json!({ "x": x, "y": y }), json!({}));
+    let plot = figure(&mut ids, "Datetime axis", Axis::Datetime, vec![renderer]);
+    json_item(plot)
+}
+
+/// A 2 x 2 `gridplot` of small line plots sharing one toolbar.
+pub fn gridplot() -> String {
+    let mut ids = Ids::default();
+    let mut children = Vec::new();
+    for (i, frequency) in [1.0, 2.0, 3.0, 4.0].into_iter().enumerate() {
+        let x: Vec<f64> = (0..50).map(|i| i as f64 / 8.0).collect();
+        let y: Vec<f64> = x.iter().map(|x| (x * frequency).sin()).collect();
+        let renderer = glyph_renderer(&mut ids, "Line", json!({ "x": x, "y": y }), json!({}));
+        let mut plot = figure(
+            &mut ids,
+            &format!("Frequency {}", frequency),
+            Axis::Linear,
+            vec![renderer],
+        );
+        plot["attributes"]["width"] = json!(300);
+        plot["attributes"]["height"] = json!(250);
+        children.push(json!([plot, i / 2, i % 2]));
+    }
+    let toolbar = object(&mut ids, "Toolbar", json!({ "tools": [] }));
+    let grid = object(
+        &mut ids,
+        "GridPlot",
+        json!({ "children": children, "toolbar": toolbar }),
+    );
+    json_item(grid)
+}
+
+/// A column of common widgets above a line plot.
+pub fn widgets() -> String {
+    let mut ids = Ids::default();
+    let slider = object(
+        &mut ids,
+        "Slider",
+        json!({ "title": "Frequency", "start": 0.1, "end": 5.0, "value": 1.0, "step": 0.1 }),
+    );
+    let select = object(
+        &mut ids,
+        "Select",
+        json!({
+            "title": "Palette",
+            "options": ["Viridis", "Magma", "Plasma"],
+            "value": "Viridis",
+        }),
+    );
+    let button = object(&mut ids, "Button", json!({ "label": "Reset" }));
+    let x: Vec<f64> = (0..50).map(|i| i as f64 / 5.0).collect();
+    let y: Vec<f64> = x.iter().map(|x| x.cos()).collect();
+    let renderer = glyph_renderer(&mut ids, "Line", json!({ "x": x, "y": y }), json!({}));
+    let plot = figure(&mut ids, "Widgets", Axis::Linear, vec![renderer]);
+    let column = object(
+        &mut ids,
+        "Column",
+        json!({ "children": [slider, select, button, plot] }),
+    );
+    json_item(column)
+}
+
+/// Every fixture, keyed by name.
+pub fn all() -> Vec<(&'static str, String)> {
+    vec![
+        ("line", line()),
+        ("scatter", scatter()),
+        ("datetime_axis", datetime_axis()),
+        ("gridplot", gridplot()),
+        ("widgets", widgets()),
+    ]
+}
+
+/// Sequential model ids in the `p1001` style Bokeh itself generates.
+#[derive(Default)]
+struct Ids(u32);
+
+impl Ids {
+    fn next(&mut self) -> String {
+        self.0 += 1;
+        format!("p{}", 1000 + self.0)
+    }
+}
+
+enum Axis {
+    Linear,
+    Datetime,
+}
+
+fn object(ids: &mut Ids, name: &str, attributes: Value) -> Value {
+    json!({ "type": "object", "name": name, "id": ids.next(), "attributes": attributes })
+}
+
+fn field(name: &str) -> Value {
+    json!({ "type": "field", "field": name })
+}
+
+/// A `GlyphRenderer` drawing `glyph` from a `ColumnDataSource` holding `data`;
+/// the glyph reads `x` and `y` from the columns of the same name.
+fn glyph_renderer(ids: &mut Ids, glyph: &str, data: Value, glyph_attributes: Value) -> Value {
+    let entries: Vec<Value> = data
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(column, values)| json!([column, values]))
+        .collect();
+    let source = object(
+        ids,
+        "ColumnDataSource",
+        json!({ "data": { "type": "map", "entries": entries } }),
+    );
+    let mut attributes = json!({ "x": field("x"), "y": field("y") });
+    for (key, value) in glyph_attributes.as_object().unwrap() {
+        attributes[key] = value.clone();
+    }
+    let glyph = object(ids, glyph, attributes);
+    let filter = object(ids, "AllIndices", json!({}));
+    let view = object(ids, "CDSView", json!({ "filter": filter }));
+    object(
+        ids,
+        "GlyphRenderer",
+        json!({ "data_source": source, "view": view, "glyph": glyph }),
+    )
+}
+
+fn figure(ids: &mut Ids, title: &str, x_axis: Axis, renderers: Vec<Value>) -> Value {
+    let title = object(ids, "Title", json!({ "text": title }));
+    let x_range = object(ids, "DataRange1d", json!({}));
+    let y_range = object(ids, "DataRange1d", json!({}));
+    let x_scale = object(ids, "LinearScale", json!({}));
+    let y_scale = object(ids, "LinearScale", json!({}));
+    let (x_ticker, x_axis) = match x_axis {
+        Axis::Linear => {
+            let ticker = object(ids, "BasicTicker", json!({}));
+            let formatter = object(ids, "BasicTickFormatter", json!({}));
+            let axis = object(
+                ids,
+                "LinearAxis",
+                json!({ "ticker": ticker, "formatter": formatter }),
+            );
+            (ticker, axis)
+        }
+        Axis::Datetime => {
+            let ticker = object(ids, "DatetimeTicker", json!({}));
+            let formatter = object(ids, "DatetimeTickFormatter", json!({}));
+            let axis = object(
+                ids,
+                "DatetimeAxis",
+                json!({ "ticker": ticker, "formatter": formatter }),
+            );
+            (ticker, axis)
+        }
+    };
+    let y_ticker = object(ids, "BasicTicker", json!({}));
+    let y_formatter = object(ids, "BasicTickFormatter", json!({}));
+    // Models are serialized in full where first met and referenced by id after
+    // that. `serde_json` sorts keys, so `below` is read before `center` and
+    // `center` before `left`.
+    let x_grid = object(
+        ids,
+        "Grid",
+        json!({ "dimension": 0, "ticker": { "id": x_ticker["id"] } }),
+    );
+    let y_axis = object(
+        ids,
+        "LinearAxis",
+        json!({ "ticker": { "id": y_ticker["id"] }, "formatter": y_formatter }),
+    );
+    let y_grid = object(ids, "Grid", json!({ "dimension": 1, "ticker": y_ticker }));
+    object(
+        ids,
+        "Figure",
+        json!({
+            "width": 600,
+            "height": 400,
+            "title": title,
+            "x_range": x_range,
+            "y_range": y_range,
+            "x_scale": x_scale,
+            "y_scale": y_scale,
+            "renderers": renderers,
+            "below": [x_axis],
+            "left": [y_axis],
+            "center": [x_grid, y_grid],
+        }),
+    )
+}
+
+fn json_item(root: Value) -> String {
+    json!({
+        "target_id": null,
+        "root_id": root["id"],
+        "doc": {
+            "version": FIXTURE_BOKEH_VERSION,
+            "title": "Bokeh Application",
+            "roots": [root],
+        },
+    })
+    .to_string()
+}
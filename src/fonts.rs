@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+
+use crate::options::RenderOptions;
+use crate::resources::Resource;
+
+const DEJAVU_SANS: &[u8] = include_bytes!("fonts/DejaVuSans.ttf");
+const DEJAVU_SANS_BOLD: &[u8] = include_bytes!("fonts/DejaVuSans-Bold.ttf");
+
+/// Families Bokeh draws text with by default. The default font is registered
+/// under each of them, which takes precedence over the installed fonts.
+const ALIASED_FAMILIES: [&str; 2] = ["helvetica", "arial"];
+
+/// Font files `RenderOptions::fallback_fonts` picks up from its directory.
+/// Font collections (`.ttc`) aren't supported by every webview and are skipped.
+const FALLBACK_FONT_EXTENSIONS: [&str; 4] = ["ttf", "otf", "woff", "woff2"];
+
+/// A font the page uses in place of the platform's Helvetica/Arial, so text
+/// measures, and labels wrap, the same on Windows, macOS and Linux.
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum DefaultFont {
+    /// DejaVu Sans, bundled with the crate.
+    DejaVuSans,
+    /// TrueType, OpenType or WOFF files for the regular and, optionally, bold weight.
+    /// Without a bold face the browser emboldens the regular one.
+    Custom {
+        regular: Vec<u8>,
+        bold: Option<Vec<u8>>,
+    },
+}
+
+impl DefaultFont {
+    fn face(&self, weight: &str) -> Option<&[u8]> {
+        match (self, weight) {
+            (DefaultFont::DejaVuSans, "regular") => Some(DEJAVU_SANS),
+            (DefaultFont::DejaVuSans, "bold") => Some(DEJAVU_SANS_BOLD),
+            (DefaultFont::Custom { regular, .. }, "regular") => Some(regular),
+            (DefaultFont::Custom { bold, .. }, "bold") => bold.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// The font files in `dir`, in file name order. Their index in this list is
+/// their family, `wry-fallback-<index>`, and their URL, `/fonts/fallback-<index>`.
+fn fallback_font_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    FALLBACK_FONT_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+                })
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+fn fallback_families(options: &RenderOptions) -> Vec<String> {
+    let count = options
+        .fallback_font_dir
+        .as_deref()
+        .map_or(0, |dir| fallback_font_files(dir).len());
+    (0..count).map(|index| format!("wry-fallback-{}", index)).collect()
+}
+
+/// `@font-face` rules for the default and fallback fonts, served from `/fonts/`,
+/// the document font for widgets and other DOM text and, with fallback fonts,
+/// a canvas `font` setter that appends them to every font Bokeh draws with.
+pub(crate) fn fonts_as_head_html(options: &RenderOptions) -> String {
+    let fallbacks = fallback_families(options);
+    if options.default_font.is_none() && fallbacks.is_empty() {
+        return String::new();
+    }
+
+    let mut css = String::new();
+    if let Some(font) = &options.default_font {
+        for family in ALIASED_FAMILIES {
+            for (weight, css_weight) in [("regular", "normal"), ("bold", "bold")] {
+                if font.face(weight).is_some() {
+                    css.push_str(&format!(
+                        "@font-face {{ font-family: '{}'; font-weight: {}; src: url('/fonts/default-{}'); }}\n",
+                        family, css_weight, weight
+                    ));
+                }
+            }
+        }
+    }
+    for (index, family) in fallbacks.iter().enumerate() {
+        css.push_str(&format!(
+            "@font-face {{ font-family: '{}'; src: url('/fonts/fallback-{}'); }}\n",
+            family, index
+        ));
+    }
+    let families: Vec<String> = ALIASED_FAMILIES
+        .iter()
+        .map(|family| family.to_string())
+        .chain(fallbacks.iter().map(|family| format!("'{}'", family)))
+        .collect();
+    css.push_str(&format!(
+        "html, body {{ font-family: {}; }}\n",
+        families.join(", ")
+    ));
+
+    let mut html = format!("<style>\n{}</style>", css);
+    if !fallbacks.is_empty() {
+        let fallback_list = fallbacks
+            .iter()
+            .map(|family| format!("\"{}\"", family))
+            .collect::<Vec<_>>()
+            .join(", ");
+        html.push_str(&format!(
+            "
+    <script type='text/javascript'>
+        (() => {{
+            const fallbacks = {};
+            const font = Object.getOwnPropertyDescriptor(CanvasRenderingContext2D.prototype, 'font');
+            Object.defineProperty(CanvasRenderingContext2D.prototype, 'font', {{
+                get() {{
+                    return font.get.call(this);
+                }},
+                set(value) {{
+                    font.set.call(this, value.endsWith(fallbacks) ? value : `${{value}}, ${{fallbacks}}`);
+                }},
+            }});
+        }})();
+    </script>
+    ",
+            serde_json::to_string(&fallback_list).unwrap()
+        ));
+    }
+    html
+}
+
+/// CSS `font` values the page loads before embedding, since `@font-face` fonts
+/// otherwise only start loading once text first uses them.
+pub(crate) fn preloaded_fonts(options: &RenderOptions) -> Vec<String> {
+    let mut fonts = Vec::new();
+    if let Some(font) = &options.default_font {
+        fonts.push("16px helvetica".to_string());
+        if font.face("bold").is_some() {
+            fonts.push("bold 16px helvetica".to_string());
+        }
+    }
+    for family in fallback_families(options) {
+        fonts.push(format!("16px '{}'", family));
+    }
+    fonts
+}
+
+/// The font file requested as `/fonts/<file_name>`.
+pub(crate) fn fetch(options: &RenderOptions, file_name: &str) -> Option<Resource> {
+    let content = if let Some(weight) = file_name.strip_prefix("default-") {
+        options.default_font.as_ref()?.face(weight)?.to_vec()
+    } else {
+        let index: usize = file_name.strip_prefix("fallback-")?.parse().ok()?;
+        let dir = options.fallback_font_dir.as_deref()?;
+        std::fs::read(fallback_font_files(dir).get(index)?).ok()?
+    };
+    let mime_type = font_mime_type(&content).to_string();
+    Some(Resource { content, mime_type })
+}
+
+fn font_mime_type(content: &[u8]) -> &'static str {
+    match content.get(..4) {
+        Some(b"wOF2") => "font/woff2",
+        Some(b"wOFF") => "font/woff",
+        Some(b"OTTO") => "font/otf",
+        _ => "font/ttf",
+    }
+}
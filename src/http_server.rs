@@ -0,0 +1,104 @@
+//! A Bokeh export service: an axum `Router` whose `POST /render` with a JSON
+//! body answers with the export's bytes, rendered on a `RendererPool` whose
+//! size bounds how many renders run at once. It can be served as it is, behind
+//! a reverse proxy or a container's port mapping, or nested in an
+//! application's own router.
+//!
+//! The body is a `wire::WireRequest`, rendered at 300 DPI unless it says
+//! otherwise. `GET /health` answers `200 OK` while the service runs.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use tokio::net::TcpListener;
+
+use crate::output::BokehRenderError;
+use crate::pool::RendererPool;
+use crate::wire::WireRequest;
+
+/// Request bodies larger than this are refused unless
+/// `RenderService::max_body_bytes` says otherwise.
+pub const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+/// How long a client may take to send a request body.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Serves `POST /render` off a `RendererPool`.
+pub struct RenderService {
+    pub pool: Arc<RendererPool>,
+    pub max_body_bytes: usize,
+}
+
+impl RenderService {
+    pub fn new(pool: Arc<RendererPool>) -> Self {
+        RenderService {
+            pool,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+
+    /// The routes of the service. Other methods on them answer
+    /// `405 Method Not Allowed`, other paths `404 Not Found`.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/render", post(render))
+            .route("/health", get(|| async { "OK" }))
+            .with_state(Arc::new(self))
+    }
+
+    /// Answer connections on `listener` until accepting fails.
+    pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        axum::serve(listener, self.router()).await
+    }
+}
+
+async fn render(State(service): State<Arc<RenderService>>, body: Body) -> Response {
+    // Read here rather than by an extractor, to bound how long it may take.
+    let read = axum::body::to_bytes(body, service.max_body_bytes);
+    let body = match tokio::time::timeout(READ_TIMEOUT, read).await {
+        Ok(Ok(body)) => body,
+        // Also a client hanging up mid-body, which reads no answer anyway.
+        Ok(Err(_)) => return (StatusCode::PAYLOAD_TOO_LARGE, "Payload Too Large").into_response(),
+        Err(_) => return (StatusCode::REQUEST_TIMEOUT, "Request Timeout").into_response(),
+    };
+    let body: WireRequest = match serde_json::from_slice(&body) {
+        Ok(body) => body,
+        Err(e) => {
+            let message = format!("Invalid request body: {}", e);
+            return (StatusCode::BAD_REQUEST, message).into_response();
+        }
+    };
+    let request = match body.into_request(300.0) {
+        Ok(request) => request,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    match service.pool.render_request(&request).await {
+        Ok(output) => {
+            let content_type = output.format.mime_type().to_string();
+            ([(header::CONTENT_TYPE, content_type)], output.bytes).into_response()
+        }
+        Err(e) => (error_status(&e), e.to_string()).into_response(),
+    }
+}
+
+/// The status a failed render answers with: the client's fault for documents
+/// and requests the page can't render, the service's otherwise.
+fn error_status(error: &BokehRenderError) -> StatusCode {
+    match error {
+        BokehRenderError::InvalidRequest(_) | BokehRenderError::InvalidDocument(_) => {
+            StatusCode::BAD_REQUEST
+        }
+        BokehRenderError::PageOutOfMemory(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        BokehRenderError::Page(_) | BokehRenderError::BlankRender => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        BokehRenderError::Cancelled => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
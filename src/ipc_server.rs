@@ -0,0 +1,108 @@
+//! Renders for other processes of a desktop app over a Unix domain socket, or
+//! a named pipe on Windows: cheaper than HTTP on localhost, and only processes
+//! that can open the socket's path get to render.
+//!
+//! Every message is a frame, a 4-byte big-endian length followed by that many
+//! bytes. A request is a JSON frame holding a `wire::WireRequest`, rendered at
+//! 300 DPI unless it says otherwise. It is answered with a JSON frame,
+//! `{"ok": true, "mime_type", "width", "height"}` followed by a frame of the
+//! export's bytes, or `{"ok": false, "error": "..."}` alone. A connection may
+//! send any number of requests, answered in order.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::output::BokehRenderError;
+use crate::renderer::BokehRenderer;
+use crate::wire::{read_frame, write_frame, WireRequest};
+
+pub use crate::wire::MAX_FRAME_BYTES;
+
+/// Listen on the Unix domain socket at `path`, replacing a stale socket left
+/// there, and answer every connection's requests with `renderer`, one render
+/// at a time. Runs until accepting fails.
+#[cfg(unix)]
+pub async fn serve_ipc(
+    path: impl AsRef<Path>,
+    renderer: Arc<BokehRenderer>,
+) -> std::io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let path = path.as_ref();
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let renderer = Arc::clone(&renderer);
+        tokio::spawn(async move {
+            let _ = serve_connection(stream, &renderer).await;
+        });
+    }
+}
+
+/// Listen on the named pipe at `path`, e.g. `\\.\pipe\wry-bokeh-render`, and
+/// answer every connection's requests with `renderer`, one render at a time.
+/// Fails if another server has the pipe already. Runs until connecting fails.
+#[cfg(windows)]
+pub async fn serve_ipc(
+    path: impl AsRef<Path>,
+    renderer: Arc<BokehRenderer>,
+) -> std::io::Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let path = path.as_ref();
+    let mut server = ServerOptions::new().first_pipe_instance(true).create(path)?;
+    loop {
+        server.connect().await?;
+        // The next client connects to a new instance of the pipe.
+        let connected = std::mem::replace(&mut server, ServerOptions::new().create(path)?);
+        let renderer = Arc::clone(&renderer);
+        tokio::spawn(async move {
+            let _ = serve_connection(connected, &renderer).await;
+        });
+    }
+}
+
+/// Answer the requests of one connection until it closes.
+async fn serve_connection(
+    mut stream: impl AsyncRead + AsyncWrite + Unpin,
+    renderer: &BokehRenderer,
+) -> std::io::Result<()> {
+    while let Some(frame) = read_frame(&mut stream).await? {
+        match render(&frame, renderer).await {
+            Ok((header, bytes)) => {
+                write_frame(&mut stream, header.to_string().as_bytes()).await?;
+                write_frame(&mut stream, &bytes).await?;
+            }
+            Err(error) => {
+                let header = json!({ "ok": false, "error": error });
+                write_frame(&mut stream, header.to_string().as_bytes()).await?;
+            }
+        }
+        stream.flush().await?;
+    }
+    Ok(())
+}
+
+/// The header and bytes answering a request frame, or why it failed.
+async fn render(frame: &[u8], renderer: &BokehRenderer) -> Result<(Value, Vec<u8>), String> {
+    let request: WireRequest = serde_json::from_slice(frame)
+        .map_err(|e| BokehRenderError::InvalidRequest(e.to_string()).to_string())?;
+    let request = request.into_request(300.0).map_err(|e| e.to_string())?;
+    let output = renderer
+        .render(&request.json_data, request.dpi, request.format)
+        .await
+        .map_err(|e| e.to_string())?;
+    let header = json!({
+        "ok": true,
+        "mime_type": output.format.mime_type(),
+        "width": output.width,
+        "height": output.height,
+    });
+    Ok((header, output.bytes))
+}
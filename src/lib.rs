@@ -1,53 +1,41 @@
-use pyo3::exceptions::PyValueError;
-use pyo3::prelude::*;
-
-mod bokeh_helpers;
-
-#[pyfunction]
-#[pyo3(signature = (json_data, dpi=300, typ="image/png", resource=None))]
-fn render_bokeh(
-    json_data: &str,
-    dpi: u64,
-    typ: &str,
-    resource: Option<[String; 2]>,
-) -> PyResult<String> {
-    let resource = match resource {
-        Some(resource) => {
-            let variant = &resource[0];
-            let value = (&resource[1]).clone();
-
-            if value == "" {
-                return Err(PyValueError::new_err("Resource value cannot be empty"));
-            }
-
-            match variant.as_str() {
-                "cdn" => Some(bokeh_helpers::BokehResource::CDN(
-                    bokeh_helpers::BokehCDNResource { version: value },
-                )),
-                "local" => Some(bokeh_helpers::BokehResource::Local(
-                    bokeh_helpers::BokehLocalResource { folder_uri: value },
-                )),
-                _ => {
-                    return Err(PyValueError::new_err(format!(
-                        "Invalid resource variant: {}",
-                        variant
-                    )))
-                }
-            }
-        }
-        None => None,
-    };
-
-    Ok(tokio::runtime::Runtime::new()
-        .unwrap()
-        .block_on(bokeh_helpers::render_bokeh_in_webview(
-            json_data, dpi, typ, resource,
-        )))
-}
-
-/// A Python module implemented in Rust.
-#[pymodule]
-fn wry_bokeh_helper(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(render_bokeh, m)?)?;
-    Ok(())
-}
+mod archive;
+pub mod bokeh_helpers;
+pub mod cache;
+#[cfg(feature = "contract-checks")]
+pub mod contract;
+#[cfg(feature = "coordinator")]
+pub mod coordinator;
+#[cfg(target_os = "windows")]
+mod data_dir;
+pub mod diagnostics;
+pub mod document;
+pub mod embed;
+pub mod engine;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fixtures;
+pub mod fonts;
+#[cfg(feature = "http-server")]
+pub mod http_server;
+#[cfg(feature = "ipc-server")]
+pub mod ipc_server;
+#[cfg(feature = "napi")]
+mod node;
+mod offline;
+pub mod options;
+pub mod output;
+pub mod pdf;
+pub mod pool;
+pub mod postprocess;
+pub mod prelude;
+mod process;
+pub mod protocol;
+mod proxy;
+#[cfg(feature = "python")]
+mod python;
+pub mod renderer;
+pub mod resources;
+pub mod runtime;
+pub mod tiles;
+pub mod version;
+pub mod wire;
@@ -0,0 +1,450 @@
+//! The Node.js addon, built with the `napi` feature: Node services export
+//! Bokeh figures off the same warm `RendererPool` as Rust callers, with neither
+//! Python nor a browser driver. Copy the `cdylib` to `wry_bokeh_helper.node`
+//! and `require` it:
+//!
+//!     const { configure, render } = require('./wry_bokeh_helper.node');
+//!     configure({ size: 2, resourceDir: 'bokeh/dist' });
+//!     const png = await render(JSON.stringify(item), { dpi: 192, format: 'png' });
+//!
+//! `render(json, options?)` returns a `Promise<Buffer>` that rejects with the
+//! render's error; `options.dpi` defaults to 300 and `options.format` (`png`,
+//! `jpeg`, `webp`, `pdf` or a MIME type) to png. `configure({ size,
+//! resourceDir })` sizes the pool and picks the Bokeh bundle before the first
+//! render; without it the pool is sized with `auto_size` and Bokeh comes from
+//! its CDN.
+//!
+//! The addon talks to the stable N-API C interface directly, resolved against
+//! the `node` executable when it loads the addon. macOS builds need
+//! `-C link-arg=-undefined -C link-arg=dynamic_lookup`, and Windows builds
+//! `node.lib` on the library path. Every renderer runs its event loop on a
+//! thread of its own, which macOS doesn't allow, so renders fail there.
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::options::{RenderFormat, RenderOptions};
+use crate::output::{BokehRenderError, RenderOutput};
+use crate::pool::RendererPool;
+use crate::resources::BokehResource;
+
+/// The subset of `node_api.h` the addon calls.
+#[allow(non_camel_case_types)]
+mod sys {
+    use std::ffi::{c_char, c_int, c_void};
+
+    pub type napi_env = *mut c_void;
+    pub type napi_value = *mut c_void;
+    pub type napi_callback_info = *mut c_void;
+    pub type napi_deferred = *mut c_void;
+    pub type napi_threadsafe_function = *mut c_void;
+    pub type napi_status = c_int;
+    pub type napi_callback = unsafe extern "C" fn(napi_env, napi_callback_info) -> napi_value;
+    pub type napi_threadsafe_function_call_js =
+        unsafe extern "C" fn(napi_env, napi_value, *mut c_void, *mut c_void);
+    pub type napi_finalize = unsafe extern "C" fn(napi_env, *mut c_void, *mut c_void);
+
+    pub const NAPI_OK: napi_status = 0;
+    pub const NAPI_UNDEFINED: c_int = 0;
+    pub const NAPI_NULL: c_int = 1;
+    pub const NAPI_TSFN_RELEASE: c_int = 0;
+    pub const NAPI_TSFN_NONBLOCKING: c_int = 0;
+
+    #[cfg_attr(windows, link(name = "node"))]
+    extern "C" {
+        pub fn napi_create_function(
+            env: napi_env,
+            utf8name: *const c_char,
+            length: usize,
+            cb: napi_callback,
+            data: *mut c_void,
+            result: *mut napi_value,
+        ) -> napi_status;
+        pub fn napi_set_named_property(
+            env: napi_env,
+            object: napi_value,
+            utf8name: *const c_char,
+            value: napi_value,
+        ) -> napi_status;
+        pub fn napi_get_named_property(
+            env: napi_env,
+            object: napi_value,
+            utf8name: *const c_char,
+            result: *mut napi_value,
+        ) -> napi_status;
+        pub fn napi_get_cb_info(
+            env: napi_env,
+            cbinfo: napi_callback_info,
+            argc: *mut usize,
+            argv: *mut napi_value,
+            this_arg: *mut napi_value,
+            data: *mut *mut c_void,
+        ) -> napi_status;
+        pub fn napi_typeof(env: napi_env, value: napi_value, result: *mut c_int) -> napi_status;
+        pub fn napi_get_value_string_utf8(
+            env: napi_env,
+            value: napi_value,
+            buf: *mut c_char,
+            bufsize: usize,
+            result: *mut usize,
+        ) -> napi_status;
+        pub fn napi_get_value_double(env: napi_env, value: napi_value, result: *mut f64)
+            -> napi_status;
+        pub fn napi_get_value_uint32(env: napi_env, value: napi_value, result: *mut u32)
+            -> napi_status;
+        pub fn napi_get_undefined(env: napi_env, result: *mut napi_value) -> napi_status;
+        pub fn napi_create_string_utf8(
+            env: napi_env,
+            str: *const c_char,
+            length: usize,
+            result: *mut napi_value,
+        ) -> napi_status;
+        pub fn napi_create_buffer_copy(
+            env: napi_env,
+            length: usize,
+            data: *const c_void,
+            result_data: *mut *mut c_void,
+            result: *mut napi_value,
+        ) -> napi_status;
+        pub fn napi_create_error(
+            env: napi_env,
+            code: napi_value,
+            msg: napi_value,
+            result: *mut napi_value,
+        ) -> napi_status;
+        pub fn napi_throw_error(env: napi_env, code: *const c_char, msg: *const c_char)
+            -> napi_status;
+        pub fn napi_create_promise(
+            env: napi_env,
+            deferred: *mut napi_deferred,
+            promise: *mut napi_value,
+        ) -> napi_status;
+        pub fn napi_resolve_deferred(
+            env: napi_env,
+            deferred: napi_deferred,
+            resolution: napi_value,
+        ) -> napi_status;
+        pub fn napi_reject_deferred(
+            env: napi_env,
+            deferred: napi_deferred,
+            rejection: napi_value,
+        ) -> napi_status;
+        #[allow(clippy::too_many_arguments)]
+        pub fn napi_create_threadsafe_function(
+            env: napi_env,
+            func: napi_value,
+            async_resource: napi_value,
+            async_resource_name: napi_value,
+            max_queue_size: usize,
+            initial_thread_count: usize,
+            thread_finalize_data: *mut c_void,
+            thread_finalize_cb: Option<napi_finalize>,
+            context: *mut c_void,
+            call_js_cb: Option<napi_threadsafe_function_call_js>,
+            result: *mut napi_threadsafe_function,
+        ) -> napi_status;
+        pub fn napi_call_threadsafe_function(
+            func: napi_threadsafe_function,
+            data: *mut c_void,
+            is_blocking: c_int,
+        ) -> napi_status;
+        pub fn napi_release_threadsafe_function(
+            func: napi_threadsafe_function,
+            mode: c_int,
+        ) -> napi_status;
+    }
+}
+
+use sys::*;
+
+/// What `configure` set before the pool started.
+#[derive(Default)]
+struct Config {
+    size: Option<usize>,
+    resource: Option<BokehResource>,
+}
+
+static CONFIG: Mutex<Option<Config>> = Mutex::new(None);
+
+/// The runtime the addon's renders run on, and the pool they share.
+struct Addon {
+    runtime: tokio::runtime::Runtime,
+    pool: Arc<RendererPool>,
+}
+
+static ADDON: OnceLock<Result<Addon, String>> = OnceLock::new();
+
+fn addon() -> Result<&'static Addon, String> {
+    ADDON
+        .get_or_init(|| {
+            let config = CONFIG.lock().unwrap().take().unwrap_or_default();
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| e.to_string())?;
+            let options = RenderOptions::default();
+            let pool = match config.size {
+                Some(size) => RendererPool::new(size, config.resource, options),
+                None => RendererPool::with_auto_size(config.resource, options),
+            };
+            Ok(Addon {
+                runtime,
+                pool: Arc::new(pool),
+            })
+        })
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+/// Register `configure` and `render` on the module's exports.
+///
+/// # Safety
+///
+/// Called by Node when it loads the addon, with its environment and exports.
+#[no_mangle]
+pub unsafe extern "C" fn napi_register_module_v1(env: napi_env, exports: napi_value) -> napi_value {
+    let functions: [(&CStr, napi_callback); 2] = [(c"configure", configure), (c"render", render)];
+    for (name, cb) in functions {
+        let name = name.as_ptr();
+        let mut function = ptr::null_mut();
+        let data = ptr::null_mut();
+        if napi_create_function(env, name, usize::MAX, cb, data, &mut function) != NAPI_OK
+            || napi_set_named_property(env, exports, name, function) != NAPI_OK
+        {
+            throw(env, "registering the addon's functions failed");
+            break;
+        }
+    }
+    exports
+}
+
+/// `configure({ size, resourceDir })`.
+unsafe extern "C" fn configure(env: napi_env, info: napi_callback_info) -> napi_value {
+    let result = (|| -> Result<(), String> {
+        let [options] = args::<1>(env, info)?;
+        let mut config = Config::default();
+        if let Some(size) = property(env, options, c"size")? {
+            let mut value = 0;
+            check(napi_get_value_uint32(env, size, &mut value), "size is not a number")?;
+            config.size = Some(value.max(1) as usize);
+        }
+        if let Some(dir) = property(env, options, c"resourceDir")? {
+            let dir = string(env, dir).ok_or("resourceDir is not a string")?;
+            config.resource = Some(BokehResource::local(dir).map_err(|e| e.to_string())?);
+        }
+        if ADDON.get().is_some() {
+            return Err("configure must be called before the first render".to_string());
+        }
+        *CONFIG.lock().unwrap() = Some(config);
+        Ok(())
+    })();
+    if let Err(e) = result {
+        throw(env, &e);
+    }
+    let mut undefined = ptr::null_mut();
+    napi_get_undefined(env, &mut undefined);
+    undefined
+}
+
+/// A render waiting to settle its promise on the JavaScript thread.
+struct Pending {
+    deferred: napi_deferred,
+    function: napi_threadsafe_function,
+}
+
+// Both handles are only used on the JavaScript thread or through the
+// thread-safe calls N-API allows from any thread.
+unsafe impl Send for Pending {}
+
+/// `render(json, options?)`.
+unsafe extern "C" fn render(env: napi_env, info: napi_callback_info) -> napi_value {
+    let request = (|| -> Result<_, String> {
+        let [json, options] = args::<2>(env, info)?;
+        let json_data = string(env, json).ok_or("the document must be a JSON string")?;
+        let mut dpi = 300.0;
+        if let Some(value) = property(env, options, c"dpi")? {
+            check(napi_get_value_double(env, value, &mut dpi), "dpi is not a number")?;
+        }
+        let format = match property(env, options, c"format")? {
+            Some(value) => {
+                let name = string(env, value).ok_or("format is not a string")?;
+                RenderFormat::from_name(&name).ok_or_else(|| {
+                    BokehRenderError::InvalidRequest(format!("unsupported format {}", name))
+                        .to_string()
+                })?
+            }
+            None => RenderFormat::Image("image/png".to_string()),
+        };
+        Ok((json_data, dpi, format))
+    })();
+    let (json_data, dpi, format) = match request {
+        Ok(request) => request,
+        Err(e) => {
+            throw(env, &e);
+            return ptr::null_mut();
+        }
+    };
+
+    let mut deferred = ptr::null_mut();
+    let mut promise = ptr::null_mut();
+    let mut name = ptr::null_mut();
+    let mut function = ptr::null_mut();
+    let created = napi_create_promise(env, &mut deferred, &mut promise) == NAPI_OK
+        && napi_create_string_utf8(env, c"wry_bokeh_helper.render".as_ptr(), usize::MAX, &mut name)
+            == NAPI_OK
+        && napi_create_threadsafe_function(
+            env,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            name,
+            0,
+            1,
+            ptr::null_mut(),
+            None,
+            ptr::null_mut(),
+            Some(settle),
+            &mut function,
+        ) == NAPI_OK;
+    if !created {
+        throw(env, "creating the render's promise failed");
+        return ptr::null_mut();
+    }
+    let pending = Pending { deferred, function };
+
+    match addon() {
+        Ok(addon) => {
+            let pool = Arc::clone(&addon.pool);
+            addon.runtime.spawn(async move {
+                let result = pool.render(&json_data, dpi, format).await;
+                complete(pending, result.map_err(|e| e.to_string()));
+            });
+        }
+        Err(e) => complete(pending, Err(e)),
+    }
+    promise
+}
+
+/// The result of a render, on its way to the JavaScript thread.
+type Settled = (napi_deferred, Result<RenderOutput, String>);
+
+/// Hand a finished render to the JavaScript thread, where `settle` runs.
+fn complete(pending: Pending, result: Result<RenderOutput, String>) {
+    let Pending { deferred, function } = pending;
+    let data = Box::into_raw(Box::new((deferred, result) as Settled)) as *mut c_void;
+    unsafe {
+        if napi_call_threadsafe_function(function, data, NAPI_TSFN_NONBLOCKING) != NAPI_OK {
+            drop(Box::from_raw(data as *mut Settled));
+        }
+        napi_release_threadsafe_function(function, NAPI_TSFN_RELEASE);
+    }
+}
+
+/// Resolve or reject a render's promise. `env` is null if Node is shutting
+/// down, when the result is dropped.
+unsafe extern "C" fn settle(env: napi_env, _: napi_value, _: *mut c_void, data: *mut c_void) {
+    let (deferred, result) = *Box::from_raw(data as *mut Settled);
+    if env.is_null() {
+        return;
+    }
+    let buffer = result.and_then(|output| {
+        let mut copy = ptr::null_mut();
+        let mut buffer = ptr::null_mut();
+        let data = output.bytes.as_ptr() as *const c_void;
+        match napi_create_buffer_copy(env, output.bytes.len(), data, &mut copy, &mut buffer) {
+            NAPI_OK => Ok(buffer),
+            _ => Err("copying the export to a Buffer failed".to_string()),
+        }
+    });
+    match buffer {
+        Ok(buffer) => napi_resolve_deferred(env, deferred, buffer),
+        Err(e) => napi_reject_deferred(env, deferred, error(env, &e)),
+    };
+}
+
+fn check(status: napi_status, message: &str) -> Result<(), String> {
+    match status {
+        NAPI_OK => Ok(()),
+        _ => Err(message.to_string()),
+    }
+}
+
+/// The first `N` arguments of a call, `undefined` where it passed fewer.
+unsafe fn args<const N: usize>(
+    env: napi_env,
+    info: napi_callback_info,
+) -> Result<[napi_value; N], String> {
+    let mut argc = N;
+    let mut argv = [ptr::null_mut(); N];
+    let status = napi_get_cb_info(
+        env,
+        info,
+        &mut argc,
+        argv.as_mut_ptr(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+    );
+    check(status, "reading the arguments failed")?;
+    Ok(argv)
+}
+
+/// `object[name]`, or `None` if either is `undefined` or `null`.
+unsafe fn property(
+    env: napi_env,
+    object: napi_value,
+    name: &CStr,
+) -> Result<Option<napi_value>, String> {
+    if is_nullish(env, object) {
+        return Ok(None);
+    }
+    let mut value = ptr::null_mut();
+    let status = napi_get_named_property(env, object, name.as_ptr(), &mut value);
+    check(status, "the options must be an object")?;
+    Ok((!is_nullish(env, value)).then_some(value))
+}
+
+unsafe fn is_nullish(env: napi_env, value: napi_value) -> bool {
+    let mut typ = NAPI_UNDEFINED;
+    value.is_null()
+        || napi_typeof(env, value, &mut typ) != NAPI_OK
+        || typ == NAPI_UNDEFINED
+        || typ == NAPI_NULL
+}
+
+/// The JavaScript string `value` as UTF-8, or `None` if it isn't a string.
+unsafe fn string(env: napi_env, value: napi_value) -> Option<String> {
+    let mut length = 0;
+    if napi_get_value_string_utf8(env, value, ptr::null_mut(), 0, &mut length) != NAPI_OK {
+        return None;
+    }
+    let mut buf = vec![0u8; length + 1];
+    let status = napi_get_value_string_utf8(
+        env,
+        value,
+        buf.as_mut_ptr() as *mut c_char,
+        buf.len(),
+        &mut length,
+    );
+    if status != NAPI_OK {
+        return None;
+    }
+    buf.truncate(length);
+    String::from_utf8(buf).ok()
+}
+
+fn c_string(message: &str) -> CString {
+    CString::new(message.replace('\0', " ")).unwrap()
+}
+
+unsafe fn throw(env: napi_env, message: &str) {
+    napi_throw_error(env, ptr::null(), c_string(message).as_ptr());
+}
+
+/// A JavaScript `Error` with `message`.
+unsafe fn error(env: napi_env, message: &str) -> napi_value {
+    let mut msg = ptr::null_mut();
+    let mut error = ptr::null_mut();
+    napi_create_string_utf8(env, c_string(message).as_ptr(), usize::MAX, &mut msg);
+    napi_create_error(env, ptr::null_mut(), msg, &mut error);
+    error
+}
@@ -0,0 +1,112 @@
+//! `RenderOptions::offline`: renders that must not touch the network. The page
+//! blocks every request but its own, and renders that could only work online
+//! fail before a webview is opened rather than exporting a broken plot.
+
+use serde_json::Value;
+
+use crate::options::RenderOptions;
+use crate::output::BokehRenderError;
+use crate::resources::{BokehResource, CssSource, ScriptSource};
+
+/// The page's Content-Security-Policy: nothing but the render page itself,
+/// inline scripts and styles, and data and blob URLs.
+pub(crate) const OFFLINE_CSP: &str = "default-src 'none'; \
+    script-src 'self' 'unsafe-inline' 'unsafe-eval'; style-src 'self' 'unsafe-inline'; \
+    img-src 'self' data: blob:; font-src 'self' data:; connect-src 'self' data: blob:";
+
+/// Attributes, and column data source columns, whose strings are loaded from
+/// where they point: tile and image URLs, and `AjaxDataSource` endpoints.
+const URL_KEYS: [&str; 3] = ["url", "data_url", "src"];
+
+/// Models that load from remote services whatever their attributes.
+const ONLINE_MODELS: [&str; 2] = ["GMapPlot", "GMap"];
+
+/// Check that the Bokeh bundles and the extra resources of an offline render
+/// can be served without downloading anything.
+pub(crate) fn check_offline_resources(
+    resource: Option<&BokehResource>,
+    options: &RenderOptions,
+) -> Result<(), BokehRenderError> {
+    match resource {
+        None | Some(BokehResource::CDN(_)) => {
+            return Err(offline_error("Bokeh from the CDN; use local bundles"))
+        }
+        Some(BokehResource::AutoCache(auto_cache)) => {
+            let dir = auto_cache.cache_dir.join(auto_cache.version.to_string());
+            if !auto_cache.version.bundles().iter().all(|bundle| dir.join(bundle).is_file()) {
+                return Err(offline_error("Bokeh bundles that aren't cached yet"));
+            }
+        }
+        _ => (),
+    }
+    check_offline_extras(options)
+}
+
+/// Check that the extra resources of an offline render can be served without
+/// downloading anything.
+pub(crate) fn check_offline_extras(options: &RenderOptions) -> Result<(), BokehRenderError> {
+    if options
+        .extra_scripts
+        .iter()
+        .any(|script| matches!(script, ScriptSource::Url(_)))
+        || options
+            .extra_css
+            .iter()
+            .any(|css| matches!(css, CssSource::Url(_)))
+    {
+        return Err(offline_error("extra scripts or stylesheets by URL"));
+    }
+    if !options.proxy_hosts.is_empty() || !options.request_headers.is_empty() {
+        return Err(offline_error("anything through proxied hosts"));
+    }
+    Ok(())
+}
+
+/// Check that `json_data` loads nothing from the network, e.g. map tiles,
+/// `ImageURL` images or `AjaxDataSource` data.
+pub(crate) fn check_offline_document(json_data: &str) -> Result<(), BokehRenderError> {
+    let Ok(value) = serde_json::from_str::<Value>(json_data) else {
+        return Ok(());
+    };
+    match remote_reference(&value, false) {
+        Some(reference) => Err(offline_error(&reference)),
+        None => Ok(()),
+    }
+}
+
+/// The first remote URL or online model in `value`; `url` tells whether it's
+/// the value of one of the `URL_KEYS`.
+fn remote_reference(value: &Value, url: bool) -> Option<String> {
+    match value {
+        Value::String(s) if url && is_remote(s) => Some(s.clone()),
+        // An entry of a Bokeh 3 map, e.g. a column of a data source.
+        Value::Array(entry)
+            if entry.len() == 2 && entry[0].as_str().is_some_and(|key| URL_KEYS.contains(&key)) =>
+        {
+            remote_reference(&entry[1], true)
+        }
+        Value::Array(items) => items.iter().find_map(|item| remote_reference(item, url)),
+        Value::Object(object) => {
+            // Bokeh 3 names models, Bokeh 2 types them.
+            let model = object
+                .get("name")
+                .or_else(|| object.get("type"))
+                .and_then(Value::as_str);
+            if let Some(model) = model.filter(|model| ONLINE_MODELS.contains(model)) {
+                return Some(format!("a {} from Google Maps", model));
+            }
+            object.iter().find_map(|(key, item)| {
+                remote_reference(item, url || URL_KEYS.contains(&key.as_str()))
+            })
+        }
+        _ => None,
+    }
+}
+
+fn is_remote(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://") || url.starts_with("//")
+}
+
+fn offline_error(what: &str) -> BokehRenderError {
+    BokehRenderError::InvalidRequest(format!("An offline render can't load {}", what))
+}
@@ -0,0 +1,877 @@
+//! What to render and how: the export format and the per-render settings.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tao::window::Window;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::fonts::DefaultFont;
+use crate::output::{BokehRenderError, ConsoleMessage, RenderOutput};
+use crate::pdf::{Margins, PageSize};
+use crate::postprocess::{DataImageFormat, IccProfile, Watermark};
+use crate::protocol::VariantSender;
+use crate::resources::{BokehResource, CssSource, ScriptSource};
+use crate::tiles::TiledExport;
+
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum RenderFormat {
+    /// Raster capture of the plot canvas, encoded with the given `toDataURL` MIME type.
+    Image(String),
+    /// Vector capture through the platform webview's print-to-PDF pipeline.
+    Pdf { page_size: PageSize, margins: Margins },
+}
+
+/// A length on paper, e.g. `Millimeters(90.0)` for a journal's column width.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Millimeters(pub f64);
+
+impl Millimeters {
+    pub fn from_inches(inches: f64) -> Self {
+        Millimeters(inches * 25.4)
+    }
+
+    /// Whole pixels this length spans at `dpi`, as the page lays exports out.
+    pub fn to_pixels(self, dpi: impl Into<Dpi>) -> u32 {
+        ((self.0 / 25.4 * dpi.into().get()).round() as u32).max(1)
+    }
+}
+
+/// The resolution of an export in dots per inch; 96 draws one pixel per CSS
+/// pixel. Fractional resolutions such as 144.5, or scale factors such as 1.5×
+/// (`Dpi::from_scale`), are exported as they are. Render functions take
+/// anything that converts, so integer DPIs keep working.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(from = "f64", into = "f64")]
+pub enum Dpi {
+    /// This many dots per inch.
+    Fixed(f64),
+    /// The resolution of the monitor the render window opens on, as tao
+    /// reports its scale factor: 192 on a 2× display. Screenshots embedded
+    /// back into a desktop UI then look as crisp as the UI itself. Exports
+    /// report the resolution they were drawn at.
+    System,
+}
+
+impl Dpi {
+    /// The resolution of CSS pixels.
+    pub const CSS: Dpi = Dpi::Fixed(96.0);
+
+    pub fn new(dpi: f64) -> Self {
+        Dpi::Fixed(dpi)
+    }
+
+    /// The resolution drawing `scale` pixels per CSS pixel.
+    pub fn from_scale(scale: f64) -> Self {
+        Dpi::Fixed(scale * 96.0)
+    }
+
+    /// Dots per inch; `Dpi::System` counts as 96 until a render resolves it.
+    pub fn get(self) -> f64 {
+        match self {
+            Dpi::Fixed(dpi) => dpi,
+            // The page draws at the webview's own pixel ratio times `dpi / 96`,
+            // which is the monitor's scale factor.
+            Dpi::System => 96.0,
+        }
+    }
+
+    /// Pixels per CSS pixel.
+    pub fn scale(self) -> f64 {
+        self.get() / 96.0
+    }
+
+    /// The resolution rounded to whole dots per inch, as `RenderOutput::dpi`
+    /// reported it before resolutions could be fractional.
+    #[deprecated(note = "resolutions may be fractional, use `Dpi::get`")]
+    pub fn to_u64(self) -> u64 {
+        self.get().round() as u64
+    }
+
+    /// The resolution a render in `window` draws at.
+    pub(crate) fn on_window(self, window: &Window) -> Self {
+        match self {
+            Dpi::System => Dpi::from_scale(window.scale_factor()),
+            dpi => dpi,
+        }
+    }
+
+    /// Fails renders at resolutions the page can't draw at.
+    pub(crate) fn validate(self) -> Result<Self, BokehRenderError> {
+        match self {
+            Dpi::Fixed(dpi) if !dpi.is_finite() || dpi <= 0.0 => {
+                Err(BokehRenderError::InvalidRequest(format!(
+                    "Invalid DPI {}, expected a positive number",
+                    dpi
+                )))
+            }
+            dpi => Ok(dpi),
+        }
+    }
+}
+
+/// Fixed resolutions in order of their value, then `Dpi::System`, so DPIs can
+/// key the map `render_bokeh_at_dpis` returns.
+impl Ord for Dpi {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Dpi::Fixed(dpi), Dpi::Fixed(other)) => dpi.total_cmp(other),
+            (Dpi::Fixed(_), Dpi::System) => Ordering::Less,
+            (Dpi::System, Dpi::Fixed(_)) => Ordering::Greater,
+            (Dpi::System, Dpi::System) => Ordering::Equal,
+        }
+    }
+}
+
+impl PartialOrd for Dpi {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Dpi {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Dpi {}
+
+impl From<u64> for Dpi {
+    fn from(dpi: u64) -> Self {
+        Dpi::Fixed(dpi as f64)
+    }
+}
+
+impl From<f64> for Dpi {
+    fn from(dpi: f64) -> Self {
+        Dpi::Fixed(dpi)
+    }
+}
+
+impl From<Dpi> for f64 {
+    fn from(dpi: Dpi) -> Self {
+        dpi.get()
+    }
+}
+
+/// As the page reads it, e.g. `300` or `144.5`.
+impl fmt::Display for Dpi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get())
+    }
+}
+
+/// A proxy server the webview sends its requests through, see `RenderOptions::proxy`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProxyConfig {
+    /// Tunnels requests with HTTP `CONNECT`.
+    Http { host: String, port: u16 },
+    Socks5 { host: String, port: u16 },
+}
+
+impl ProxyConfig {
+    /// The proxy as a URL, e.g. `http://proxy.example.com:3128`.
+    pub fn url(&self) -> String {
+        match self {
+            ProxyConfig::Http { host, port } => format!("http://{}:{}", host, port),
+            ProxyConfig::Socks5 { host, port } => format!("socks5://{}:{}", host, port),
+        }
+    }
+
+    pub(crate) fn to_wry(&self) -> wry::ProxyConfig {
+        match self {
+            ProxyConfig::Http { host, port } => wry::ProxyConfig::Http(wry::ProxyEndpoint {
+                host: host.clone(),
+                port: port.to_string(),
+            }),
+            ProxyConfig::Socks5 { host, port } => wry::ProxyConfig::Socks5(wry::ProxyEndpoint {
+                host: host.clone(),
+                port: port.to_string(),
+            }),
+        }
+    }
+}
+
+/// How urgently a `RendererPool` should start a render waiting for a renderer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Batch exports nobody is waiting on.
+    Background,
+    #[default]
+    Normal,
+    /// Renders a user is waiting on, started ahead of everything queued.
+    Interactive,
+}
+
+/// One document to render, bundling the arguments of `render_bokeh_in_webview`.
+#[derive(Clone)]
+pub struct RenderRequest {
+    pub json_data: String,
+    pub dpi: Dpi,
+    pub format: RenderFormat,
+    pub resource: Option<BokehResource>,
+    pub options: RenderOptions,
+    /// Where the request queues in a `RendererPool`, see
+    /// `RendererPool::render_request`.
+    pub priority: Priority,
+}
+
+impl RenderRequest {
+    /// A 300 DPI PNG render with default resources and options.
+    pub fn new(json_data: impl Into<String>) -> Self {
+        RenderRequest {
+            json_data: json_data.into(),
+            dpi: Dpi::new(300.0),
+            format: RenderFormat::Image("image/png".to_string()),
+            resource: None,
+            options: RenderOptions::default(),
+            priority: Priority::Normal,
+        }
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// A `json_item` document in whatever form the caller holds it: JSON text, a
+/// parsed `serde_json::Value`, or the path of a `.json` file. Render functions
+/// take anything that converts; text is used as it is, without a copy.
+#[derive(Clone, Debug)]
+pub enum RenderInput<'a> {
+    Json(Cow<'a, str>),
+    Value(Cow<'a, Value>),
+    Path(Cow<'a, Path>),
+}
+
+impl<'a> RenderInput<'a> {
+    /// The document as the JSON text the page embeds: a parsed document is
+    /// serialized once, a file read once.
+    pub(crate) fn into_json(self) -> Result<Cow<'a, str>, BokehRenderError> {
+        match self {
+            RenderInput::Json(json) => Ok(json),
+            RenderInput::Value(value) => Ok(Cow::Owned(value.to_string())),
+            RenderInput::Path(path) => match std::fs::read_to_string(&path) {
+                Ok(json) => Ok(Cow::Owned(json)),
+                Err(e) => Err(BokehRenderError::InvalidRequest(format!(
+                    "Can't read the document {}: {}",
+                    path.display(),
+                    e
+                ))),
+            },
+        }
+    }
+}
+
+impl<'a> From<&'a str> for RenderInput<'a> {
+    fn from(json: &'a str) -> Self {
+        RenderInput::Json(Cow::Borrowed(json))
+    }
+}
+
+impl<'a> From<&'a String> for RenderInput<'a> {
+    fn from(json: &'a String) -> Self {
+        RenderInput::Json(Cow::Borrowed(json))
+    }
+}
+
+impl From<String> for RenderInput<'_> {
+    fn from(json: String) -> Self {
+        RenderInput::Json(Cow::Owned(json))
+    }
+}
+
+impl<'a> From<&'a Value> for RenderInput<'a> {
+    fn from(value: &'a Value) -> Self {
+        RenderInput::Value(Cow::Borrowed(value))
+    }
+}
+
+impl From<Value> for RenderInput<'_> {
+    fn from(value: Value) -> Self {
+        RenderInput::Value(Cow::Owned(value))
+    }
+}
+
+impl<'a> From<&'a Path> for RenderInput<'a> {
+    fn from(path: &'a Path) -> Self {
+        RenderInput::Path(Cow::Borrowed(path))
+    }
+}
+
+impl From<PathBuf> for RenderInput<'_> {
+    fn from(path: PathBuf) -> Self {
+        RenderInput::Path(Cow::Owned(path))
+    }
+}
+
+impl RenderFormat {
+    /// The format a request names, as its file extension or MIME type: `png`,
+    /// `jpeg`, `webp` or `pdf`, the latter on a page fitting the plot.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "png" | "image/png" => Some(RenderFormat::Image("image/png".to_string())),
+            "jpeg" | "jpg" | "image/jpeg" => Some(RenderFormat::Image("image/jpeg".to_string())),
+            "webp" | "image/webp" => Some(RenderFormat::Image("image/webp".to_string())),
+            "pdf" | "application/pdf" => Some(RenderFormat::Pdf {
+                page_size: PageSize::Fit,
+                margins: Margins::default(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn mime_type(&self) -> &str {
+        match self {
+            RenderFormat::Image(typ) => typ,
+            RenderFormat::Pdf { .. } => "application/pdf",
+        }
+    }
+}
+
+/// A readiness condition for documents that keep changing after `embed_item`
+/// resolves, e.g. through `CustomJS` callbacks run on load.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum WaitUntil {
+    /// Wait this long once the document is idle.
+    Idle(Duration),
+    /// Until an element matches the CSS selector. Bokeh renders plots into
+    /// shadow roots, so this only sees the page's own DOM, such as elements that
+    /// `extra_scripts` add.
+    Selector(String),
+    /// Until the JS expression is truthy, or resolves to a truthy value. The
+    /// root view exporting is in scope as `view`.
+    JsPredicate(String),
+    /// Two `requestAnimationFrame`s, for updates scheduled on the next frame.
+    DoubleAnimationFrame,
+}
+
+impl WaitUntil {
+    pub(crate) fn to_page_option(&self) -> serde_json::Value {
+        match self {
+            WaitUntil::Idle(duration) => {
+                serde_json::json!({ "type": "idle", "ms": duration.as_millis() as u64 })
+            }
+            WaitUntil::Selector(selector) => {
+                serde_json::json!({ "type": "selector", "selector": selector })
+            }
+            WaitUntil::JsPredicate(predicate) => {
+                serde_json::json!({ "type": "js_predicate", "predicate": predicate })
+            }
+            WaitUntil::DoubleAnimationFrame => {
+                serde_json::json!({ "type": "double_animation_frame" })
+            }
+        }
+    }
+}
+
+/// How the page lets the browser paint a settled document before the export.
+/// Some Bokeh versions resolve `embed_item` before the final paint.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SettleStrategy {
+    /// One `requestAnimationFrame`.
+    SingleFrame,
+    /// Two `requestAnimationFrame`s, so the frame drawn after the document
+    /// settled has been painted.
+    #[default]
+    DoubleFrame,
+    /// Two `requestAnimationFrame`s, then this long.
+    DoubleFrameThenDelay(Duration),
+}
+
+impl SettleStrategy {
+    fn frames(self) -> u32 {
+        match self {
+            SettleStrategy::SingleFrame => 1,
+            SettleStrategy::DoubleFrame | SettleStrategy::DoubleFrameThenDelay(_) => 2,
+        }
+    }
+
+    fn delay(self) -> Duration {
+        match self {
+            SettleStrategy::DoubleFrameThenDelay(delay) => delay,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// The `settle` page option, with `extra` added to the delay.
+    pub(crate) fn to_page_option(self, extra: Duration) -> serde_json::Value {
+        serde_json::json!({
+            "frames": self.frames(),
+            "delay": (self.delay() + extra).as_millis() as u64,
+        })
+    }
+}
+
+/// How long exports wait for MathJax unless `RenderOptions::mathjax_timeout` says otherwise.
+pub const DEFAULT_MATHJAX_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the page waits for its bundles and extra scripts unless
+/// `RenderOptions::bokeh_load_timeout` says otherwise.
+pub const DEFAULT_BOKEH_LOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A Content-Security-Policy for rendering untrusted documents: scripts only
+/// from the render page and the Bokeh CDN, and no requests to other hosts, so
+/// a document can't exfiltrate data or pull in scripts. Documents that load
+/// remote tiles, images or data render without them.
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; \
+    script-src 'self' 'unsafe-inline' 'unsafe-eval' https://cdn.bokeh.org; \
+    style-src 'self' 'unsafe-inline'; img-src 'self' data: blob:; font-src 'self' data:; \
+    connect-src 'self' data: blob:";
+
+/// Per-render settings that affect how the page is prepared, not the document.
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct RenderOptions {
+    /// Disable CSS animations/transitions and report `prefers-reduced-motion: reduce`
+    /// so exports never capture a frame in the middle of an animation.
+    pub reduced_motion: bool,
+    /// Overlay composited onto exported images after capture.
+    pub watermark: Option<Watermark>,
+    /// ICC profile to tag exported images with.
+    pub icc_profile: Option<IccProfile>,
+    /// Convert pixels from the canvas's sRGB into `icc_profile` rather than only
+    /// tagging them. Tagging a non-sRGB profile without converting shifts colors.
+    pub convert_to_icc_profile: bool,
+    /// Crop the border rows and columns of exported images that are all the
+    /// color of the top-left pixel, or all fully transparent, before the watermark.
+    pub trim_margins: bool,
+    /// Also export the values of every `Image` glyph as a high-bit-depth grayscale image.
+    pub data_image_format: Option<DataImageFormat>,
+    /// Seed the page's random number generators per figure so jittered glyphs
+    /// come out the same on every render.
+    pub random_seed: Option<u32>,
+    /// Scripts served under `/extra-scripts/` and loaded in order after the bundles.
+    pub extra_scripts: Vec<ScriptSource>,
+    /// Stylesheets served under `/extra-css/` and linked in `<head>` before the bundles.
+    pub extra_css: Vec<CssSource>,
+    /// Font drawn in place of Helvetica/Arial; the page waits for it to load
+    /// before embedding and exporting, so text renders the same everywhere.
+    pub default_font: Option<DefaultFont>,
+    /// Directory of fonts served under `/fonts/` and tried, in file name order,
+    /// for characters the plot's fonts lack, such as CJK or emoji. Latin text
+    /// keeps the platform font unless `default_font` is also set.
+    pub fallback_font_dir: Option<PathBuf>,
+    /// Google Maps API key given to every GMap plot, replacing any in the document.
+    /// Exports wait for the map tiles to load; since Google draws them outside
+    /// Bokeh's canvas, only PDF output includes them.
+    pub gmap_api_key: Option<String>,
+    /// How long exports of documents with LaTeX/MathML labels wait for MathJax
+    /// to finish typesetting; `None` waits `DEFAULT_MATHJAX_TIMEOUT`, zero doesn't wait.
+    pub mathjax_timeout: Option<Duration>,
+    /// How long the page waits for the Bokeh bundles, extra scripts and
+    /// stylesheets to load before failing with `BokehRenderError::ResourceLoad`,
+    /// e.g. when the CDN is unreachable; `None` waits `DEFAULT_BOKEH_LOAD_TIMEOUT`,
+    /// zero waits indefinitely.
+    pub bokeh_load_timeout: Option<Duration>,
+    /// Responses every `AjaxDataSource` waits for before the export; `None` waits
+    /// for the initial load only, more waits for further polling cycles and zero
+    /// doesn't wait.
+    pub ajax_polls: Option<u32>,
+    /// Extra condition the page waits for after the document settles, right before export.
+    pub wait_until: Option<WaitUntil>,
+    /// How the page waits for the browser to paint before the export, after
+    /// `wait_until`.
+    pub settle: SettleStrategy,
+    /// The resolution of every render with these options, overriding the `dpi`
+    /// the render is called with, e.g. `Dpi::System`.
+    pub dpi: Option<Dpi>,
+    /// The resolutions `render_bokeh_at_dpis` exports the document at, e.g. 300
+    /// for print and 96 for the web. Other renders draw at their own DPI.
+    pub dpis: Vec<Dpi>,
+    /// Receives the exports of `dpis` downscaled from the embedded one.
+    pub(crate) variant_sender: Option<VariantSender>,
+    /// Check image exports for a capture taken before the canvas painted, every
+    /// pixel transparent or white, and render such documents once more with
+    /// this much longer to settle. A second blank export fails with
+    /// `BokehRenderError::BlankRender`; only `render_bokeh_in_webview` retries,
+    /// other renders fail right away.
+    pub blank_retry_delay: Option<Duration>,
+    /// Extra wait after the document settles, set for the retry of a blank export.
+    pub(crate) settle_delay: Duration,
+    /// JS run before the document is embedded, with the `json_item` payload in
+    /// scope as `item` to adjust it. May return a promise.
+    pub before_render_js: Option<String>,
+    /// JS run once the document is embedded, with the root view in scope as
+    /// `view` and the document as `doc`, e.g. to hide toolbars or set ranges.
+    /// May return a promise; the page settles again before exporting.
+    pub after_render_js: Option<String>,
+    /// Windows only: the WebView2 user data folder. By default it's the folder
+    /// named by the `WRY_BOKEH_HELPER_DATA_DIR` environment variable, or else
+    /// `wry_bokeh_helper` in the roaming application data folder. A folder another
+    /// process is using is never shared; a suffixed sibling is used instead.
+    pub webview_data_dir: Option<PathBuf>,
+    /// Windows only: the page's JavaScript heap limit in megabytes, past which
+    /// renders fail with `BokehRenderError::PageOutOfMemory`. WebView2 keeps the
+    /// limit a user data folder was first opened with for the whole process, so
+    /// renders with another limit need another `webview_data_dir`.
+    pub js_heap_limit_mb: Option<u32>,
+    /// More command line arguments for the browser behind the webview, e.g.
+    /// `--disable-gpu` for software rendering in containers without a GPU, whose
+    /// canvases otherwise come out blank. WebView2 takes them as they are, with
+    /// the same caveat as `js_heap_limit_mb`; WebKitGTK only understands
+    /// `--disable-gpu`, and WKWebView none.
+    pub browser_args: Vec<String>,
+    /// Send the page's own requests, e.g. for CDN bundles or tile servers,
+    /// through this proxy rather than whatever the platform would pick, which
+    /// for a hidden webview is often no proxy at all. macOS needs 14.0 or later
+    /// and the `mac-proxy` feature. Unrelated to `proxy_hosts`.
+    pub proxy: Option<ProxyConfig>,
+    /// Block all network access: the page can only load what the renderer
+    /// serves it, and renders fail up front if they need the Bokeh CDN, extra
+    /// resources by URL or proxied hosts, or if the document loads remote tiles,
+    /// images or data.
+    pub offline: bool,
+    /// Content-Security-Policy of the render page, e.g.
+    /// `DEFAULT_CONTENT_SECURITY_POLICY`. The page needs `'unsafe-inline'` scripts
+    /// and, for hooks and `CustomJS`, `'unsafe-eval'`. `offline` replaces it with
+    /// a stricter policy of its own.
+    pub content_security_policy: Option<String>,
+    /// Receives every console message of the page as it is made, in addition
+    /// to the messages collected in `RenderOutput::console`.
+    pub console_sender: Option<UnboundedSender<ConsoleMessage>>,
+    /// Show the render window with DevTools enabled and keep it open after the
+    /// export until it is closed, to inspect why a document renders blank. The
+    /// render only returns once the window is closed.
+    pub debug: bool,
+    /// Size of the render window in CSS pixels, which `stretch_*` and `scale_*`
+    /// documents fill. By default it's taken from the document's root, see
+    /// `document_viewport`.
+    pub viewport: Option<(u32, u32)>,
+    /// Keep the webview up this long after the export, e.g. to attach DevTools
+    /// or take more snapshots. The render returns once it has been torn down.
+    pub keep_alive: Option<Duration>,
+    /// `BokehRenderer`s only: replace the webview with a fresh one after this
+    /// many renders, releasing whatever its web content process has leaked.
+    pub recycle_after: Option<u32>,
+    /// Exact pixel size of exported images. The root is laid out at this size
+    /// divided by the DPI scale, so `dpi` still sets how large text and lines are.
+    pub output_size: Option<(u32, u32)>,
+    /// Width and height of exported images on paper. Their pixel size follows
+    /// from the render's DPI, which PNGs also record for importing at this size.
+    /// `output_size` takes precedence.
+    pub physical_size: Option<(Millimeters, Millimeters)>,
+    /// Factor for every font size and line width of the document, e.g. 1.5 to
+    /// keep a thumbnail's labels readable. The DPI only scales pixels, leaving
+    /// text as small or as large relative to the plot as it was designed.
+    pub presentation_scale: Option<f64>,
+    /// Draw every export natively at its resolution rather than scaling a canvas
+    /// to it, so text and glyphs stay sharp: plots saved with `hidpi=False` draw
+    /// at the render's pixel ratio too, the pixel ratio of an `output_size` or
+    /// `physical_size` export is chosen to fill its width exactly, and
+    /// `render_bokeh_at_dpis` embeds the document at each of `dpis` instead of
+    /// downscaling one export.
+    pub native_resolution: bool,
+    /// Hand image exports over as raw bytes, uploaded to the render page's
+    /// `/capture` route, instead of as base64 data URLs over IPC, saving a third
+    /// of the transfer and a decode pass for large renders. On Linux this needs
+    /// the `linux-body` feature; without it exports fall back to data URLs.
+    pub binary_transfer: bool,
+    /// Headers, such as `Authorization`, added to every request for a host
+    /// (`example.com`, or `example.com:8443`). The page's requests to these
+    /// hosts are proxied through the renderer, under `/proxy/<host>/`, to add them.
+    pub request_headers: HashMap<String, Vec<(String, String)>>,
+    /// More hosts the page's requests are proxied to, without extra headers,
+    /// e.g. internal APIs an `AjaxDataSource` reads that the page can't reach
+    /// across origins. Other hosts are never proxied.
+    pub proxy_hosts: Vec<String>,
+    /// Keep successful proxied responses here and answer repeated requests
+    /// from it, so documents reading live endpoints render the same every time.
+    pub proxy_cache_dir: Option<PathBuf>,
+    /// Export PNGs in tiles stitched by the renderer, for sizes beyond the
+    /// canvas limit. Tiles are cut from the SVG backend's export.
+    pub tiled: Option<TiledExport>,
+    /// Also export the document at this DPI before the full export and send it
+    /// to `preview_sender`, so a UI can show something while the expensive
+    /// export runs. The document is embedded twice, running the hooks each time.
+    /// Only canvas image exports without an `output_size` are previewed.
+    pub preview_dpi: Option<Dpi>,
+    /// Receives the preview exported at `preview_dpi`, without post-processing.
+    pub preview_sender: Option<UnboundedSender<RenderOutput>>,
+    /// Also return exported images downscaled to fit this many pixels on their
+    /// longer edge, in `RenderOutput::thumbnail`, e.g. for gallery previews.
+    pub thumbnail: Option<u32>,
+}
+
+impl RenderOptions {
+    pub fn reduced_motion(mut self, reduced_motion: bool) -> Self {
+        self.reduced_motion = reduced_motion;
+        self
+    }
+
+    pub fn watermark(mut self, watermark: Watermark) -> Self {
+        self.watermark = Some(watermark);
+        self
+    }
+
+    pub fn icc_profile(mut self, icc_profile: IccProfile) -> Self {
+        self.icc_profile = Some(icc_profile);
+        self
+    }
+
+    pub fn convert_to_icc_profile(mut self, convert_to_icc_profile: bool) -> Self {
+        self.convert_to_icc_profile = convert_to_icc_profile;
+        self
+    }
+
+    pub fn trim_margins(mut self, trim_margins: bool) -> Self {
+        self.trim_margins = trim_margins;
+        self
+    }
+
+    pub fn data_images(mut self, format: DataImageFormat) -> Self {
+        self.data_image_format = Some(format);
+        self
+    }
+
+    pub fn random_seed(mut self, seed: u32) -> Self {
+        self.random_seed = Some(seed);
+        self
+    }
+
+    pub fn extra_scripts(mut self, scripts: Vec<ScriptSource>) -> Self {
+        self.extra_scripts = scripts;
+        self
+    }
+
+    pub fn extra_css(mut self, stylesheets: Vec<CssSource>) -> Self {
+        self.extra_css = stylesheets;
+        self
+    }
+
+    pub fn default_font(mut self, font: DefaultFont) -> Self {
+        self.default_font = Some(font);
+        self
+    }
+
+    pub fn fallback_fonts(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.fallback_font_dir = Some(dir.into());
+        self
+    }
+
+    pub fn gmap_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.gmap_api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn mathjax_timeout(mut self, timeout: Duration) -> Self {
+        self.mathjax_timeout = Some(timeout);
+        self
+    }
+
+    pub fn bokeh_load_timeout(mut self, timeout: Duration) -> Self {
+        self.bokeh_load_timeout = Some(timeout);
+        self
+    }
+
+    pub fn ajax_polls(mut self, polls: u32) -> Self {
+        self.ajax_polls = Some(polls);
+        self
+    }
+
+    pub fn wait_until(mut self, condition: WaitUntil) -> Self {
+        self.wait_until = Some(condition);
+        self
+    }
+
+    pub fn settle(mut self, strategy: SettleStrategy) -> Self {
+        self.settle = strategy;
+        self
+    }
+
+    pub fn dpi(mut self, dpi: impl Into<Dpi>) -> Self {
+        self.dpi = Some(dpi.into());
+        self
+    }
+
+    pub fn dpis(mut self, dpis: impl IntoIterator<Item = impl Into<Dpi>>) -> Self {
+        self.dpis = dpis.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The resolution a render called with `dpi` draws at, see `dpi`.
+    pub(crate) fn dpi_for(&self, dpi: impl Into<Dpi>) -> Dpi {
+        self.dpi.unwrap_or(dpi.into())
+    }
+
+    pub fn blank_retry_delay(mut self, delay: Duration) -> Self {
+        self.blank_retry_delay = Some(delay);
+        self
+    }
+
+    pub fn before_render_js(mut self, script: impl Into<String>) -> Self {
+        self.before_render_js = Some(script.into());
+        self
+    }
+
+    pub fn after_render_js(mut self, script: impl Into<String>) -> Self {
+        self.after_render_js = Some(script.into());
+        self
+    }
+
+    pub fn webview_data_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.webview_data_dir = Some(dir.into());
+        self
+    }
+
+    pub fn js_heap_limit(mut self, megabytes: u32) -> Self {
+        self.js_heap_limit_mb = Some(megabytes);
+        self
+    }
+
+    pub fn browser_arg(mut self, arg: impl Into<String>) -> Self {
+        self.browser_args.push(arg.into());
+        self
+    }
+
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn content_security_policy(mut self, policy: impl Into<String>) -> Self {
+        self.content_security_policy = Some(policy.into());
+        self
+    }
+
+    pub fn forward_console(mut self, sender: UnboundedSender<ConsoleMessage>) -> Self {
+        self.console_sender = Some(sender);
+        self
+    }
+
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    pub fn viewport(mut self, width: u32, height: u32) -> Self {
+        self.viewport = Some((width, height));
+        self
+    }
+
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    pub fn recycle_after(mut self, renders: u32) -> Self {
+        self.recycle_after = Some(renders);
+        self
+    }
+
+    pub fn output_size(mut self, width_px: u32, height_px: u32) -> Self {
+        self.output_size = Some((width_px, height_px));
+        self
+    }
+
+    pub fn physical_size(mut self, width: Millimeters, height: Millimeters) -> Self {
+        self.physical_size = Some((width, height));
+        self
+    }
+
+    pub fn presentation_scale(mut self, scale: f64) -> Self {
+        self.presentation_scale = Some(scale);
+        self
+    }
+
+    pub fn native_resolution(mut self, native_resolution: bool) -> Self {
+        self.native_resolution = native_resolution;
+        self
+    }
+
+    pub fn binary_transfer(mut self, binary_transfer: bool) -> Self {
+        self.binary_transfer = binary_transfer;
+        self
+    }
+
+    pub fn request_header(
+        mut self,
+        host: impl Into<String>,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.request_headers
+            .entry(host.into())
+            .or_default()
+            .push((name.into(), value.into()));
+        self
+    }
+
+    pub fn proxy_host(mut self, host: impl Into<String>) -> Self {
+        self.proxy_hosts.push(host.into());
+        self
+    }
+
+    pub fn proxy_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.proxy_cache_dir = Some(dir.into());
+        self
+    }
+
+    pub fn tiled(mut self, tiled: TiledExport) -> Self {
+        self.tiled = Some(tiled);
+        self
+    }
+
+    pub fn preview(mut self, dpi: impl Into<Dpi>, sender: UnboundedSender<RenderOutput>) -> Self {
+        self.preview_dpi = Some(dpi.into());
+        self.preview_sender = Some(sender);
+        self
+    }
+
+    pub fn thumbnail(mut self, max_edge: u32) -> Self {
+        self.thumbnail = Some(max_edge);
+        self
+    }
+
+    /// These options with the viewport sized for `json_data`, unless one is set.
+    pub(crate) fn sized_for(mut self, json_data: &str) -> Self {
+        if self.viewport.is_none() {
+            self.viewport = document_viewport(json_data);
+        }
+        self
+    }
+}
+
+/// Bokeh's default plot width and height, for a responsive root missing either.
+const DEFAULT_PLOT_SIZE: u32 = 600;
+
+/// The window size `json_data` asks for: the `width` and `height` of its root.
+/// Responsive roots (`stretch_*` and `scale_*` sizing modes) get Bokeh's default
+/// for a missing dimension; other roots without either size themselves to their
+/// content and don't need a particular window.
+pub fn document_viewport(json_data: &str) -> Option<(u32, u32)> {
+    let item: serde_json::Value = serde_json::from_str(json_data).ok()?;
+    let roots = item["doc"]["roots"].as_array()?;
+    let root = roots
+        .iter()
+        .find(|root| root["id"] == item["root_id"])
+        .or(roots.first())?;
+    let attributes = &root["attributes"];
+    let dimension = |name: &str| attributes[name].as_f64().map(|size| size.round() as u32);
+    let responsive = attributes["sizing_mode"]
+        .as_str()
+        .is_some_and(|mode| mode.starts_with("stretch") || mode.starts_with("scale"));
+    match (dimension("width"), dimension("height")) {
+        (None, None) if !responsive => None,
+        (width, height) => Some((
+            width.unwrap_or(DEFAULT_PLOT_SIZE),
+            height.unwrap_or(DEFAULT_PLOT_SIZE),
+        )),
+    }
+}
@@ -0,0 +1,151 @@
+//! What a render returns, and why it can fail.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::bokeh_helpers::as_data_url;
+use crate::diagnostics::DocumentStats;
+use crate::options::{Dpi, RenderFormat};
+use crate::postprocess::DataImage;
+use crate::version::{BokehVersion, MIN_SUPPORTED_VERSION};
+
+#[derive(Clone, Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum BokehRenderError {
+    #[error("Failed to print the page to PDF: {0}")]
+    Pdf(String),
+    #[error("Invalid render request: {0}")]
+    InvalidRequest(String),
+    /// The document isn't a `json_item` the page can embed, see
+    /// `document::validate_bokeh_json`.
+    #[error("Invalid Bokeh document: {0}")]
+    InvalidDocument(String),
+    #[error("Failed to start the renderer: {0}")]
+    Renderer(String),
+    #[error("Failed to decode the exported image: {0}")]
+    Decode(String),
+    #[error("Failed to post-process the exported image: {0}")]
+    PostProcess(String),
+    #[error("The render page failed: {0}")]
+    Page(String),
+    #[error("The render was cancelled")]
+    Cancelled,
+    #[error("The render page ran out of memory on a document of {0}")]
+    PageOutOfMemory(DocumentStats),
+    /// The WebView2 runtime isn't installed, with what to do about it.
+    #[error("The WebView2 runtime is not installed; {0}")]
+    RuntimeMissing(String),
+    /// A bundle, extra script or stylesheet the page needs was refused.
+    #[error("The render page failed to load {}: {} {}", .0.path, .0.status, .0.reason)]
+    ResourceFailed(ResourceFailure),
+    /// BokehJS, or an extra script or stylesheet, didn't load, e.g. because the
+    /// CDN is unreachable. `url` is the file that failed, if the page knows it.
+    #[error("The render page failed to load {}: {reason}", .url.as_deref().unwrap_or("BokehJS"))]
+    ResourceLoad { url: Option<String>, reason: String },
+    /// Every pixel of the export is transparent or white, see
+    /// `RenderOptions::blank_retry_delay`.
+    #[error("The export is blank; the capture may have fired before the plot was drawn")]
+    BlankRender,
+    /// The page loaded a BokehJS release it can't embed documents with, see
+    /// `version::MIN_SUPPORTED_VERSION`.
+    #[error(
+        "BokehJS {0} is not supported, the render page needs Bokeh {min} or later",
+        min = MIN_SUPPORTED_VERSION
+    )]
+    UnsupportedVersion(BokehVersion),
+}
+
+/// A request of the render page the custom protocol refused, see
+/// `BokehRenderError::ResourceFailed`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceFailure {
+    /// Path of the request, e.g. `/bokeh-resource-dir/bokeh.min.js`.
+    pub path: String,
+    /// Status of the response, e.g. 404 for a file that doesn't exist.
+    pub status: u16,
+    /// Why the request was refused, e.g. the error reading the file.
+    pub reason: String,
+}
+
+impl BokehRenderError {
+    /// How a render whose web content process terminated fails.
+    pub(crate) fn process_failed(out_of_memory: bool, stats: DocumentStats) -> Self {
+        match out_of_memory {
+            true => BokehRenderError::PageOutOfMemory(stats),
+            false => BokehRenderError::Renderer("The web content process crashed".to_string()),
+        }
+    }
+
+    /// How a render fails on a page without BokehJS, see `bokehMissing`.
+    pub(crate) fn bokeh_missing() -> Self {
+        BokehRenderError::ResourceLoad {
+            url: None,
+            reason: "window.Bokeh is undefined once the page loaded".to_string(),
+        }
+    }
+}
+
+/// Where the time of a render went, for diagnosing slow renders.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderTimings {
+    /// Loading the render page and the BokehJS bundles.
+    pub bokeh_load: Duration,
+    /// From `embed_item` until the document is idle and painted.
+    pub embed: Duration,
+    /// Encoding the canvas, or printing the page for PDFs.
+    pub export: Duration,
+    /// Posting the result from the page to the event loop.
+    pub ipc: Duration,
+}
+
+/// An exported document.
+#[derive(Clone, Debug)]
+pub struct RenderOutput {
+    pub bytes: Vec<u8>,
+    /// Pixel size of the image; for PDFs, the printed content size in CSS pixels.
+    pub width: u32,
+    pub height: u32,
+    pub dpi: Dpi,
+    pub format: RenderFormat,
+    pub timings: RenderTimings,
+    /// Full-precision `Image` glyph data, if `RenderOptions::data_images` was set.
+    pub data_images: Vec<DataImage>,
+    /// Console output and uncaught errors of the page during the render, in order.
+    pub console: Vec<ConsoleMessage>,
+    /// The image downscaled in the same format, if `RenderOptions::thumbnail` was set.
+    pub thumbnail: Option<Vec<u8>>,
+}
+
+impl RenderOutput {
+    pub fn to_data_url(&self) -> String {
+        as_data_url(self.format.mime_type(), &self.bytes)
+    }
+
+    pub(crate) fn with_console(self, console: Vec<ConsoleMessage>) -> Self {
+        RenderOutput { console, ..self }
+    }
+}
+
+/// Severity of a forwarded `console` call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ConsoleLevel {
+    Debug,
+    Log,
+    Info,
+    Warn,
+    Error,
+}
+
+/// A `console` call made in the page and forwarded over IPC.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConsoleMessage {
+    pub level: ConsoleLevel,
+    /// The arguments, formatted and joined with spaces.
+    pub message: String,
+    /// The code that made the call: `page`, a hook such as `before_render_js`, or
+    /// `window.onerror`/`unhandledrejection` for uncaught errors.
+    pub source: String,
+}
@@ -0,0 +1,317 @@
+use wry::WebView;
+
+use crate::protocol::{EventSink, UserEvent};
+
+/// CSS pixels per millimeter at the 96 DPI reference resolution.
+pub const PX_PER_MM: f64 = 96.0 / 25.4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum PageSize {
+    /// A single page exactly as large as the rendered plot plus margins.
+    Fit,
+    A4,
+    Letter,
+    Custom { width_mm: f64, height_mm: f64 },
+}
+
+impl PageSize {
+    /// Page dimensions in millimeters for a plot of `content_px` CSS pixels.
+    pub fn to_mm(self, content_px: (f64, f64), margins: &Margins) -> (f64, f64) {
+        match self {
+            PageSize::Fit => (
+                content_px.0 / PX_PER_MM + margins.left + margins.right,
+                content_px.1 / PX_PER_MM + margins.top + margins.bottom,
+            ),
+            PageSize::A4 => (210.0, 297.0),
+            PageSize::Letter => (215.9, 279.4),
+            PageSize::Custom {
+                width_mm,
+                height_mm,
+            } => (width_mm, height_mm),
+        }
+    }
+}
+
+/// Page margins in millimeters.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Margins {
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub left: f64,
+}
+
+impl Margins {
+    pub fn uniform(mm: f64) -> Self {
+        Margins {
+            top: mm,
+            right: mm,
+            bottom: mm,
+            left: mm,
+        }
+    }
+}
+
+/// How `render_bokeh_report` arranges documents on printed pages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReportLayout {
+    pub page_size: PageSize,
+    pub margins: Margins,
+    /// Grid cells per page; one column and one row puts each document on its own page.
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl Default for ReportLayout {
+    fn default() -> Self {
+        ReportLayout {
+            page_size: PageSize::Fit,
+            margins: Margins::default(),
+            columns: 1,
+            rows: 1,
+        }
+    }
+}
+
+impl ReportLayout {
+    pub fn grid(columns: u32, rows: u32) -> Self {
+        ReportLayout {
+            columns,
+            rows,
+            ..Default::default()
+        }
+    }
+
+    /// Size of one page's content box in CSS pixels, or `None` for `PageSize::Fit`.
+    pub fn content_px(&self) -> Option<(f64, f64)> {
+        match self.page_size {
+            PageSize::Fit => None,
+            page_size => {
+                let (width, height) = page_size.to_mm((0.0, 0.0), &self.margins);
+                Some((
+                    (width - self.margins.left - self.margins.right) * PX_PER_MM,
+                    (height - self.margins.top - self.margins.bottom) * PX_PER_MM,
+                ))
+            }
+        }
+    }
+}
+
+/// Ask the platform webview to print the current document to a PDF.
+///
+/// Printing completes asynchronously; the outcome is delivered to `events`,
+/// e.g. an event loop's `EventLoopProxy<UserEvent>`, as `UserEvent::PdfPrinted`.
+pub fn print_to_pdf(
+    webview: &WebView,
+    page_mm: (f64, f64),
+    margins: &Margins,
+    events: impl Into<EventSink>,
+) {
+    let events = events.into();
+    if let Err(e) = platform::print_to_pdf(webview, page_mm, margins, events.clone()) {
+        events.send(UserEvent::PdfPrinted(Err(e)));
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn temp_pdf_path() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!(
+        "wry_bokeh_helper-{}-{}.pdf",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default()
+    ))
+}
+
+/// Read and remove a PDF written by the platform print pipeline.
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn take_pdf(path: &std::path::Path) -> Result<Vec<u8>, String> {
+    let content = std::fs::read(path).map_err(|e| format!("Failed to read printed PDF: {}", e));
+    let _ = std::fs::remove_file(path);
+    content
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use gtk::{PageSetup, PaperSize, PrintSettings, Unit};
+    use webkit2gtk::{PrintOperation, PrintOperationExt};
+    use wry::{WebView, WebViewExtUnix};
+
+    use super::{take_pdf, temp_pdf_path, Margins};
+    use crate::protocol::{EventSink, UserEvent};
+
+    pub fn print_to_pdf(
+        webview: &WebView,
+        page_mm: (f64, f64),
+        margins: &Margins,
+        events: EventSink,
+    ) -> Result<(), String> {
+        let path = temp_pdf_path();
+
+        let settings = PrintSettings::new();
+        settings.set_printer("Print to File");
+        settings.set(gtk::PRINT_SETTINGS_OUTPUT_FILE_FORMAT, Some("pdf"));
+        settings.set(
+            gtk::PRINT_SETTINGS_OUTPUT_URI,
+            Some(&format!("file://{}", path.display())),
+        );
+
+        let page_setup = PageSetup::new();
+        page_setup.set_paper_size(&PaperSize::new_custom(
+            "wry-bokeh-helper",
+            "wry-bokeh-helper",
+            page_mm.0,
+            page_mm.1,
+            Unit::Mm,
+        ));
+        page_setup.set_top_margin(margins.top, Unit::Mm);
+        page_setup.set_right_margin(margins.right, Unit::Mm);
+        page_setup.set_bottom_margin(margins.bottom, Unit::Mm);
+        page_setup.set_left_margin(margins.left, Unit::Mm);
+
+        let operation = PrintOperation::new(&webview.webview());
+        operation.set_print_settings(&settings);
+        operation.set_page_setup(&page_setup);
+
+        let failed_events = events.clone();
+        operation.connect_failed(move |_, error| {
+            failed_events.send(UserEvent::PdfPrinted(Err(error.to_string())));
+        });
+        operation.connect_finished(move |_| {
+            events.send(UserEvent::PdfPrinted(take_pdf(&path)));
+        });
+        operation.print();
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use webview2_com::{
+        Microsoft::Web::WebView2::Win32::{
+            ICoreWebView2Environment6, ICoreWebView2_2, ICoreWebView2_7,
+        },
+        PrintToPdfCompletedHandler,
+    };
+    use windows::core::{Interface, HSTRING};
+    use wry::{WebView, WebViewExtWindows};
+
+    use super::{take_pdf, temp_pdf_path, Margins};
+    use crate::protocol::{EventSink, UserEvent};
+
+    const MM_PER_INCH: f64 = 25.4;
+
+    pub fn print_to_pdf(
+        webview: &WebView,
+        page_mm: (f64, f64),
+        margins: &Margins,
+        events: EventSink,
+    ) -> Result<(), String> {
+        let path = temp_pdf_path();
+
+        unsafe {
+            let core = webview
+                .controller()
+                .CoreWebView2()
+                .map_err(|e| e.to_string())?;
+            let settings = core
+                .cast::<ICoreWebView2_2>()
+                .and_then(|core| core.Environment())
+                .and_then(|env| env.cast::<ICoreWebView2Environment6>())
+                .and_then(|env| env.CreatePrintSettings())
+                .map_err(|e| format!("PrintToPdf is not supported by this WebView2 runtime: {}", e))?;
+
+            settings
+                .SetPageWidth(page_mm.0 / MM_PER_INCH)
+                .and_then(|_| settings.SetPageHeight(page_mm.1 / MM_PER_INCH))
+                .and_then(|_| settings.SetMarginTop(margins.top / MM_PER_INCH))
+                .and_then(|_| settings.SetMarginRight(margins.right / MM_PER_INCH))
+                .and_then(|_| settings.SetMarginBottom(margins.bottom / MM_PER_INCH))
+                .and_then(|_| settings.SetMarginLeft(margins.left / MM_PER_INCH))
+                .and_then(|_| settings.SetShouldPrintBackgrounds(true))
+                .and_then(|_| settings.SetShouldPrintHeaderAndFooter(false))
+                .map_err(|e| e.to_string())?;
+
+            let result_path = path.clone();
+            let handler = PrintToPdfCompletedHandler::create(Box::new(move |result, success| {
+                let pdf = match result {
+                    Ok(()) if success => take_pdf(&result_path),
+                    Ok(()) => Err("WebView2 failed to print the page to PDF".to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                events.send(UserEvent::PdfPrinted(pdf));
+                Ok(())
+            }));
+
+            core.cast::<ICoreWebView2_7>()
+                .and_then(|core| {
+                    core.PrintToPdf(&HSTRING::from(path.as_os_str()), &settings, &handler)
+                })
+                .map_err(|e| format!("PrintToPdf is not supported by this WebView2 runtime: {}", e))
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use block2::RcBlock;
+    use objc2_foundation::{CGPoint, CGRect, CGSize, NSData, NSError};
+    use objc2_web_kit::WKPDFConfiguration;
+        use wry::{WebView, WebViewExtMacOS};
+
+    use super::{Margins, PX_PER_MM};
+    use crate::protocol::{EventSink, UserEvent};
+
+    /// `createPDF` has no notion of printer margins; they are applied as page
+    /// padding by the render page instead, so the captured rect is the full page.
+    pub fn print_to_pdf(
+        webview: &WebView,
+        page_mm: (f64, f64),
+        _margins: &Margins,
+        events: EventSink,
+    ) -> Result<(), String> {
+        unsafe {
+            let configuration = WKPDFConfiguration::new();
+            configuration.setRect(CGRect::new(
+                CGPoint::new(0.0, 0.0),
+                CGSize::new(page_mm.0 * PX_PER_MM, page_mm.1 * PX_PER_MM),
+            ));
+
+            let handler = RcBlock::new(move |data: *mut NSData, error: *mut NSError| {
+                let pdf = match (data.as_ref(), error.as_ref()) {
+                    (Some(data), _) => Ok(data.bytes().to_vec()),
+                    (None, Some(error)) => Err(error.localizedDescription().to_string()),
+                    (None, None) => Err("WKWebView returned no PDF data".to_string()),
+                };
+                events.send(UserEvent::PdfPrinted(pdf));
+            });
+
+            webview
+                .webview()
+                .createPDFWithConfiguration_completionHandler(Some(&configuration), &handler);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+mod platform {
+    use wry::WebView;
+
+    use super::Margins;
+    use crate::protocol::EventSink;
+
+    pub fn print_to_pdf(
+        _webview: &WebView,
+        _page_mm: (f64, f64),
+        _margins: &Margins,
+        _events: EventSink,
+    ) -> Result<(), String> {
+        Err("PDF export is not supported on this platform".to_string())
+    }
+}
@@ -0,0 +1,682 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{
+    broadcast, oneshot, watch, Notify, OwnedSemaphorePermit, Semaphore, SemaphorePermit,
+};
+
+use crate::bokeh_helpers::render_bokeh_in_webview;
+use crate::cache::render_key;
+use crate::options::{Dpi, Priority, RenderFormat, RenderOptions, RenderRequest};
+use crate::output::{BokehRenderError, RenderOutput};
+use crate::resources::BokehResource;
+use crate::renderer::BokehRenderer;
+
+/// Estimated resident memory of one idle-to-busy renderer webview, used by
+/// `RendererPool::with_auto_size`.
+pub const DEFAULT_WORKER_MEMORY: u64 = 300 * 1024 * 1024;
+
+/// How long a queued render waits before it's treated as one `Priority` level
+/// more urgent, unless `RendererPool::with_priority_aging` says otherwise.
+pub const DEFAULT_PRIORITY_AGING: Duration = Duration::from_secs(10);
+
+/// Renders in progress by key, see `render_key`.
+type InFlight = Mutex<HashMap<String, InFlightRender>>;
+
+struct InFlightRender {
+    /// Where the result is broadcast to the callers that joined the render.
+    sender: broadcast::Sender<Result<RenderOutput, BokehRenderError>>,
+    /// The render's place in `RendererPool::waiting` until it has a renderer.
+    ticket: u64,
+}
+
+/// A render waiting for a renderer, see `RendererPool::acquire`.
+struct Waiting {
+    ticket: u64,
+    priority: Priority,
+    since: Instant,
+}
+
+/// A bounded set of `BokehRenderer`s shared between tasks. Renderers are
+/// started on demand and reused; at most `size` renders run at once. Callers
+/// asking for a document that is already being rendered at the same DPI and
+/// format share that render's result instead of starting another.
+///
+/// Renders waiting for a renderer start by `Priority`, then in the order they
+/// came. A waiting render rises a level every `with_priority_aging`, so that
+/// background work still gets through a steady stream of interactive renders.
+pub struct RendererPool {
+    resource: Option<BokehResource>,
+    options: RenderOptions,
+    idle: Mutex<Vec<BokehRenderer>>,
+    permits: Semaphore,
+    size: Mutex<usize>,
+    /// Permits still to be retired after a `resize` down while they were in use.
+    excess_permits: AtomicUsize,
+    in_flight: InFlight,
+    activity: Arc<Activity>,
+    /// Renders waiting for a permit, and a notification whenever one stops.
+    waiting: Mutex<Vec<Waiting>>,
+    waiting_changed: Notify,
+    next_ticket: AtomicU64,
+    priority_aging: Duration,
+}
+
+impl RendererPool {
+    pub fn new(size: usize, resource: Option<BokehResource>, options: RenderOptions) -> Self {
+        RendererPool {
+            resource,
+            options,
+            idle: Mutex::new(Vec::new()),
+            permits: Semaphore::new(size),
+            size: Mutex::new(size),
+            excess_permits: AtomicUsize::new(0),
+            in_flight: Mutex::new(HashMap::new()),
+            activity: Arc::new(Activity {
+                running: AtomicUsize::new(0),
+                finished: Notify::new(),
+            }),
+            waiting: Mutex::new(Vec::new()),
+            waiting_changed: Notify::new(),
+            next_ticket: AtomicU64::new(0),
+            priority_aging: DEFAULT_PRIORITY_AGING,
+        }
+    }
+
+    /// Raise the priority of a waiting render a level every `aging` instead of
+    /// every `DEFAULT_PRIORITY_AGING`.
+    pub fn with_priority_aging(mut self, aging: Duration) -> Self {
+        self.priority_aging = aging;
+        self
+    }
+
+    /// A pool sized to what the host can sustain, see `auto_size`.
+    pub fn with_auto_size(resource: Option<BokehResource>, options: RenderOptions) -> Self {
+        Self::new(auto_size(DEFAULT_WORKER_MEMORY), resource, options)
+    }
+
+    /// Like `with_auto_size`, with the memory each renderer is expected to use.
+    pub fn with_auto_size_per_worker(
+        memory_per_worker: u64,
+        resource: Option<BokehResource>,
+        options: RenderOptions,
+    ) -> Self {
+        Self::new(auto_size(memory_per_worker), resource, options)
+    }
+
+    pub fn size(&self) -> usize {
+        *self.size.lock().unwrap()
+    }
+
+    /// Change how many renders may run at once. Shrinking doesn't interrupt
+    /// running renders; the pool settles at the new size as they finish.
+    pub fn resize(&self, size: usize) {
+        let mut current = self.size.lock().unwrap();
+        if size > *current {
+            let added = size - *current;
+            // Cancel pending retirements before handing out new permits.
+            let excess = self
+                .excess_permits
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |excess| {
+                    Some(excess - excess.min(added))
+                })
+                .unwrap();
+            self.permits.add_permits(added - excess.min(added));
+        } else {
+            let removed = *current - size;
+            let forgotten = self.permits.forget_permits(removed);
+            self.excess_permits
+                .fetch_add(removed - forgotten, Ordering::SeqCst);
+        }
+        *current = size;
+        let removed = {
+            let mut idle = self.idle.lock().unwrap();
+            let kept = size.min(idle.len());
+            idle.split_off(kept)
+        };
+        for renderer in removed {
+            drop_renderer(renderer, None);
+        }
+    }
+
+    /// Stop taking renders, failing those still waiting for a renderer, wait up
+    /// to `grace` for the running ones and shut every renderer down, so that
+    /// their webviews release the WebView2 user data folder. Renderers still
+    /// busy after `grace` shut down once their render finishes. Returns whether
+    /// all of them finished in time.
+    pub async fn shutdown(&self, grace: Duration) -> bool {
+        self.permits.close();
+        let drained = tokio::time::timeout(grace, async {
+            loop {
+                let finished = self.activity.finished.notified();
+                if self.activity.running.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                finished.await;
+            }
+        })
+        .await
+        .is_ok();
+
+        let renderers = std::mem::take(&mut *self.idle.lock().unwrap());
+        // Dropping a renderer joins its event loop thread.
+        let _ = tokio::task::spawn_blocking(move || drop(renderers)).await;
+        drained
+    }
+
+    /// Queue a render on the pool and return a handle to follow or cancel it
+    /// without affecting the other jobs. Must be called within a tokio runtime.
+    /// A job joining a render already in progress stays `Queued` until it's done.
+    pub fn submit(
+        self: &Arc<Self>,
+        json_data: String,
+        dpi: impl Into<Dpi>,
+        format: RenderFormat,
+    ) -> JobHandle {
+        self.submit_at(json_data, dpi.into(), format, Priority::Normal)
+    }
+
+    /// Like `submit`, at the request's priority. The pool's own resource and
+    /// options apply, not the request's.
+    pub fn submit_request(self: &Arc<Self>, request: RenderRequest) -> JobHandle {
+        self.submit_at(request.json_data, request.dpi, request.format, request.priority)
+    }
+
+    fn submit_at(
+        self: &Arc<Self>,
+        json_data: String,
+        dpi: Dpi,
+        format: RenderFormat,
+        priority: Priority,
+    ) -> JobHandle {
+        let (progress_tx, progress) = watch::channel(JobProgress::Queued);
+        let (cancel, cancelled) = oneshot::channel();
+        let pool = Arc::clone(self);
+        let task = tokio::spawn(async move {
+            let render = pool.render_coalesced(&json_data, dpi, format, priority, || {
+                progress_tx.send_replace(JobProgress::Running);
+            });
+            let result = tokio::select! {
+                result = render => result,
+                // Only `JobHandle::cancel`; a dropped handle just stops following.
+                Ok(()) = cancelled => Err(BokehRenderError::Cancelled),
+            };
+            progress_tx.send_replace(match result {
+                Err(BokehRenderError::Cancelled) => JobProgress::Cancelled,
+                _ => JobProgress::Finished,
+            });
+            result
+        });
+        JobHandle {
+            cancel: Mutex::new(Some(cancel)),
+            progress,
+            task,
+        }
+    }
+
+    pub async fn render(
+        &self,
+        json_data: &str,
+        dpi: impl Into<Dpi>,
+        format: RenderFormat,
+    ) -> Result<RenderOutput, BokehRenderError> {
+        let dpi = dpi.into();
+        self.render_coalesced(json_data, dpi, format, Priority::Normal, || ())
+            .await
+    }
+
+    /// Like `render`, at the request's priority. The pool's own resource and
+    /// options apply, not the request's.
+    pub async fn render_request(
+        &self,
+        request: &RenderRequest,
+    ) -> Result<RenderOutput, BokehRenderError> {
+        let format = request.format.clone();
+        self.render_coalesced(&request.json_data, request.dpi, format, request.priority, || ())
+            .await
+    }
+
+    /// Wait for an identical render in progress, or render with the next free
+    /// renderer, calling `started` once it has one. Joining a render still
+    /// waiting for a renderer raises it to the joiner's priority if that's
+    /// more urgent.
+    async fn render_coalesced(
+        &self,
+        json_data: &str,
+        dpi: Dpi,
+        format: RenderFormat,
+        priority: Priority,
+        started: impl FnOnce(),
+    ) -> Result<RenderOutput, BokehRenderError> {
+        let key = render_key(json_data, dpi, &format);
+        let queued = loop {
+            let mut waiting = match self.in_flight.lock().unwrap().entry(key.clone()) {
+                Entry::Occupied(entry) => {
+                    self.raise(entry.get().ticket, priority);
+                    entry.get().sender.subscribe()
+                }
+                // Queued under the same lock, so a joiner always finds the
+                // render's ticket waiting until it has a renderer.
+                Entry::Vacant(entry) => {
+                    let queued = self.enqueue(priority);
+                    entry.insert(InFlightRender {
+                        sender: broadcast::channel(1).0,
+                        ticket: queued.ticket,
+                    });
+                    break queued;
+                }
+            };
+            // A closed channel means that render was cancelled; take over.
+            if let Ok(result) = waiting.recv().await {
+                return result;
+            }
+        };
+
+        let leader = Leader {
+            in_flight: &self.in_flight,
+            key: Some(key),
+        };
+        let permit = self.acquire(queued).await?;
+        started();
+        let result = self.render_with(permit, json_data, dpi, format).await;
+        leader.finish(&result);
+        result
+    }
+
+    /// Add a render at `priority` to the renders waiting for a permit.
+    fn enqueue(&self, priority: Priority) -> Queued<'_> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::SeqCst);
+        self.waiting.lock().unwrap().push(Waiting {
+            ticket,
+            priority,
+            since: Instant::now(),
+        });
+        Queued { pool: self, ticket }
+    }
+
+    /// Raise the waiting render `ticket` to `priority` if that's more urgent;
+    /// a render that has its renderer already is left alone.
+    fn raise(&self, ticket: u64, priority: Priority) {
+        let raised = match self
+            .waiting
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|waiting| waiting.ticket == ticket && waiting.priority < priority)
+        {
+            Some(waiting) => {
+                waiting.priority = priority;
+                true
+            }
+            None => false,
+        };
+        if raised {
+            self.waiting_changed.notify_waiters();
+        }
+    }
+
+    /// A permit for the `queued` render, once no waiting render comes before
+    /// it. Only the render next in line waits on the semaphore; if a permit
+    /// arrives when another has aged past it, the permit goes back for that one.
+    async fn acquire(&self, queued: Queued<'_>) -> Result<SemaphorePermit<'_>, BokehRenderError> {
+        let ticket = queued.ticket;
+        loop {
+            let changed = self.waiting_changed.notified();
+            if self.next_in_line() != Some(ticket) {
+                changed.await;
+                continue;
+            }
+            tokio::select! {
+                permit = self.permits.acquire() => {
+                    let permit = permit.map_err(|_| {
+                        BokehRenderError::Renderer("the pool is closed".to_string())
+                    })?;
+                    if self.next_in_line() == Some(ticket) {
+                        return Ok(permit);
+                    }
+                    drop(permit);
+                    self.waiting_changed.notify_waiters();
+                }
+                _ = changed => (),
+            }
+        }
+    }
+
+    /// The ticket of the waiting render to start next: the most urgent, aged
+    /// priorities included, and of those the first to come.
+    fn next_in_line(&self) -> Option<u64> {
+        let aging = self.priority_aging.as_secs_f64().max(f64::MIN_POSITIVE);
+        let top = Priority::Interactive as u64;
+        let urgency = |waiting: &Waiting| {
+            let aged = (waiting.since.elapsed().as_secs_f64() / aging) as u64;
+            (waiting.priority as u64).saturating_add(aged).min(top)
+        };
+        self.waiting
+            .lock()
+            .unwrap()
+            .iter()
+            .max_by(|a, b| urgency(a).cmp(&urgency(b)).then(b.ticket.cmp(&a.ticket)))
+            .map(|waiting| waiting.ticket)
+    }
+
+    async fn render_with(
+        &self,
+        permit: SemaphorePermit<'_>,
+        json_data: &str,
+        dpi: Dpi,
+        format: RenderFormat,
+    ) -> Result<RenderOutput, BokehRenderError> {
+        let _running = Running::start(&self.activity);
+        let idle = self.idle.lock().unwrap().pop();
+        let renderer = match idle {
+            Some(renderer) => renderer,
+            // Starting waits for the webview to come up.
+            None => {
+                let (resource, options) = (self.resource.clone(), self.options.clone());
+                tokio::task::spawn_blocking(move || BokehRenderer::new(resource, options))
+                    .await
+                    .map_err(|e| BokehRenderError::Renderer(e.to_string()))??
+            }
+        };
+        // Shuts the renderer down off the runtime if the render is cancelled.
+        let mut checked_out = CheckedOut {
+            renderer: Some(renderer),
+            activity: &self.activity,
+        };
+        let renderer = checked_out.renderer.as_ref().unwrap();
+        let result = renderer.render(json_data, dpi, format).await;
+
+        // A renderer whose event loop is gone can't be reused, nor is any
+        // once the pool is shutting down.
+        if !matches!(result, Err(BokehRenderError::Renderer(_))) && !self.permits.is_closed() {
+            let size = self.size();
+            let mut idle = self.idle.lock().unwrap();
+            if idle.len() < size {
+                idle.extend(checked_out.renderer.take());
+            }
+        }
+        drop(checked_out);
+        let retire = self
+            .excess_permits
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |excess| {
+                excess.checked_sub(1)
+            })
+            .is_ok();
+        if retire {
+            permit.forget();
+        }
+        result
+    }
+}
+
+/// A renderer taken out of the pool for a render. One not put back by the
+/// time it's dropped, because the render failed or was cancelled, is shut
+/// down, and counts as running until it has.
+struct CheckedOut<'a> {
+    renderer: Option<BokehRenderer>,
+    activity: &'a Arc<Activity>,
+}
+
+impl Drop for CheckedOut<'_> {
+    fn drop(&mut self) {
+        if let Some(renderer) = self.renderer.take() {
+            drop_renderer(renderer, Some(Running::start(self.activity)));
+        }
+    }
+}
+
+/// Drop `renderer`, which joins its event loop thread, and then `running`, on
+/// a blocking thread when called within a tokio runtime.
+fn drop_renderer(renderer: BokehRenderer, running: Option<Running>) {
+    let shut_down = move || {
+        drop(renderer);
+        drop(running);
+    };
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => drop(runtime.spawn_blocking(shut_down)),
+        Err(_) => shut_down(),
+    }
+}
+
+/// Keeps a render in `RendererPool::waiting` until dropped, with or without a
+/// permit.
+struct Queued<'a> {
+    pool: &'a RendererPool,
+    ticket: u64,
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        let ticket = self.ticket;
+        self.pool
+            .waiting
+            .lock()
+            .unwrap()
+            .retain(|waiting| waiting.ticket != ticket);
+        self.pool.waiting_changed.notify_waiters();
+    }
+}
+
+/// Renders holding a renderer, and a notification whenever one finishes, for
+/// `RendererPool::shutdown`.
+struct Activity {
+    running: AtomicUsize,
+    finished: Notify,
+}
+
+/// Counts a render in `Activity::running` until dropped, cancelled renders
+/// included.
+struct Running(Arc<Activity>);
+
+impl Running {
+    fn start(activity: &Arc<Activity>) -> Self {
+        activity.running.fetch_add(1, Ordering::SeqCst);
+        Running(Arc::clone(activity))
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.running.fetch_sub(1, Ordering::SeqCst);
+        self.0.finished.notify_waiters();
+    }
+}
+
+/// The caller doing a coalesced render, see `RendererPool::render_coalesced`.
+/// Dropping it before `finish` lets the callers waiting on it take over.
+struct Leader<'a> {
+    in_flight: &'a InFlight,
+    key: Option<String>,
+}
+
+impl Leader<'_> {
+    fn finish(mut self, result: &Result<RenderOutput, BokehRenderError>) {
+        let key = self.key.take().unwrap();
+        if let Some(render) = self.in_flight.lock().unwrap().remove(&key) {
+            let _ = render.sender.send(result.clone());
+        }
+    }
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = &self.key {
+            self.in_flight.lock().unwrap().remove(key);
+        }
+    }
+}
+
+/// Where a job submitted with `RendererPool::submit` is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum JobProgress {
+    /// Waiting for a free renderer.
+    Queued,
+    Running,
+    /// Done, successfully or not; the result is ready.
+    Finished,
+    Cancelled,
+}
+
+/// A render submitted to a `RendererPool`. Dropping the handle leaves the
+/// render running to completion; only `cancel` stops it.
+pub struct JobHandle {
+    cancel: Mutex<Option<oneshot::Sender<()>>>,
+    progress: watch::Receiver<JobProgress>,
+    task: tokio::task::JoinHandle<Result<RenderOutput, BokehRenderError>>,
+}
+
+impl JobHandle {
+    /// Cancel the job. A queued job never starts; a running one is abandoned
+    /// and its renderer shut down rather than reused. Finished jobs keep their result.
+    pub fn cancel(&self) {
+        if let Some(cancel) = self.cancel.lock().unwrap().take() {
+            let _ = cancel.send(());
+        }
+    }
+
+    pub fn progress(&self) -> JobProgress {
+        *self.progress.borrow()
+    }
+
+    /// Follow the job's progress, e.g. to show a spinner while it's `Running`.
+    pub fn subscribe(&self) -> watch::Receiver<JobProgress> {
+        self.progress.clone()
+    }
+
+    /// Wait for the job, which fails with `BokehRenderError::Cancelled` if it was cancelled.
+    pub async fn await_result(self) -> Result<RenderOutput, BokehRenderError> {
+        self.task
+            .await
+            .unwrap_or_else(|e| Err(BokehRenderError::Renderer(e.to_string())))
+    }
+}
+
+/// The number of renderers the host can run at once: one per CPU, limited by
+/// how many `memory_per_worker`-sized renderers fit in the available memory.
+pub fn auto_size(memory_per_worker: u64) -> usize {
+    let cpus = std::thread::available_parallelism()
+        .map(|cpus| cpus.get())
+        .unwrap_or(1);
+
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+    let by_memory = match system.available_memory() {
+        // Some platforms and sandboxes don't report memory; trust the CPU count.
+        0 => cpus,
+        available => (available / memory_per_worker.max(1)) as usize,
+    };
+    cpus.min(by_memory).max(1)
+}
+
+/// Process-wide limit on webviews opened by the one-shot render functions.
+static WEBVIEW_LIMIT: Mutex<Option<Arc<Semaphore>>> = Mutex::new(None);
+
+/// Limit how many webviews `render_bokeh_in_webview` and `render_bokeh_report`
+/// may have open at once across the whole process, or lift the limit with `None`.
+/// Renders already running when the limit changes don't count against the new one.
+pub fn set_max_concurrent_webviews(limit: Option<usize>) {
+    *WEBVIEW_LIMIT.lock().unwrap() = limit.map(|limit| Arc::new(Semaphore::new(limit.max(1))));
+}
+
+/// Wait for a slot under the limit set by `set_max_concurrent_webviews`, if any.
+pub(crate) async fn acquire_webview_permit() -> Option<OwnedSemaphorePermit> {
+    let limit = WEBVIEW_LIMIT.lock().unwrap().clone()?;
+    limit.acquire_owned().await.ok()
+}
+
+/// `render_bokeh_in_webview` once a permit of `semaphore` is available, for
+/// callers that share their own concurrency budget between webviews and other work.
+pub async fn render_with_permit(
+    semaphore: &Semaphore,
+    json_data: &str,
+    dpi: impl Into<Dpi>,
+    format: RenderFormat,
+    resource: Option<BokehResource>,
+    options: RenderOptions,
+) -> Result<RenderOutput, BokehRenderError> {
+    let dpi = dpi.into();
+    let _permit = semaphore
+        .acquire()
+        .await
+        .map_err(|_| BokehRenderError::Renderer("the semaphore is closed".to_string()))?;
+    render_bokeh_in_webview(json_data, dpi, format, resource, options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool() -> RendererPool {
+        RendererPool::new(1, None, RenderOptions::default())
+    }
+
+    #[test]
+    fn joining_raises_a_waiting_render_to_the_joiners_priority() {
+        let pool = pool();
+        let batch = pool.enqueue(Priority::Background);
+        let normal = pool.enqueue(Priority::Normal);
+        assert_eq!(pool.next_in_line(), Some(normal.ticket));
+        pool.raise(batch.ticket, Priority::Interactive);
+        assert_eq!(pool.next_in_line(), Some(batch.ticket));
+    }
+
+    #[test]
+    fn joining_never_lowers_a_waiting_render() {
+        let pool = pool();
+        let interactive = pool.enqueue(Priority::Interactive);
+        let normal = pool.enqueue(Priority::Normal);
+        pool.raise(interactive.ticket, Priority::Background);
+        assert_eq!(pool.next_in_line(), Some(interactive.ticket));
+        drop(interactive);
+        assert_eq!(pool.next_in_line(), Some(normal.ticket));
+    }
+
+    /// Backdate the waiting render `ticket` by `age`.
+    fn age(pool: &RendererPool, ticket: u64, age: Duration) {
+        let mut waiting = pool.waiting.lock().unwrap();
+        let waiting = waiting.iter_mut().find(|w| w.ticket == ticket).unwrap();
+        waiting.since = Instant::now() - age;
+    }
+
+    #[test]
+    fn next_in_line_prefers_priority_then_arrival() {
+        let pool = pool();
+        assert_eq!(pool.next_in_line(), None);
+        let batch = pool.enqueue(Priority::Background);
+        let first = pool.enqueue(Priority::Normal);
+        let second = pool.enqueue(Priority::Normal);
+        assert_eq!(pool.next_in_line(), Some(first.ticket));
+        let interactive = pool.enqueue(Priority::Interactive);
+        assert_eq!(pool.next_in_line(), Some(interactive.ticket));
+        drop((interactive, first));
+        assert_eq!(pool.next_in_line(), Some(second.ticket));
+        drop(second);
+        assert_eq!(pool.next_in_line(), Some(batch.ticket));
+    }
+
+    #[test]
+    fn next_in_line_ages_waiting_renders_up_to_interactive() {
+        let pool = pool().with_priority_aging(Duration::from_secs(1));
+        let batch = pool.enqueue(Priority::Background);
+        let normal = pool.enqueue(Priority::Normal);
+        assert_eq!(pool.next_in_line(), Some(normal.ticket));
+        // Aged a level, the batch render ties with the normal one and came first.
+        age(&pool, batch.ticket, Duration::from_secs(1));
+        assert_eq!(pool.next_in_line(), Some(batch.ticket));
+        drop(batch);
+
+        // However long it waits, a render only ties with interactive ones.
+        let interactive = pool.enqueue(Priority::Interactive);
+        let late = pool.enqueue(Priority::Background);
+        age(&pool, late.ticket, Duration::from_secs(10));
+        assert_eq!(pool.next_in_line(), Some(interactive.ticket));
+        drop(interactive);
+        assert_eq!(pool.next_in_line(), Some(late.ticket));
+        drop(late);
+        assert_eq!(pool.next_in_line(), Some(normal.ticket));
+    }
+}
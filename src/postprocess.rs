@@ -0,0 +1,692 @@
+use std::io::Cursor;
+
+use base64::Engine;
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    imageops, DynamicImage, ExtendedColorType, ImageBuffer, ImageEncoder, ImageFormat, Luma, Rgba,
+    RgbaImage,
+};
+use moxcms::{ColorProfile, Layout, TransformOptions};
+use serde::Deserialize;
+use tiff::encoder::{colortype, Compression, Rational, TiffEncoder};
+use tiff::tags::ResolutionUnit;
+
+use crate::options::{Dpi, RenderFormat, RenderOptions};
+use crate::output::{BokehRenderError, RenderOutput};
+
+/// Where a `Watermark` is placed on the exported image.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+/// A PNG overlay, such as a logo or a DRAFT stamp, composited onto every exported image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watermark {
+    pub png: Vec<u8>,
+    pub position: WatermarkPosition,
+    /// Multiplied into the overlay's own alpha, from 0.0 (invisible) to 1.0.
+    pub opacity: f32,
+    /// Distance from the image edges in pixels; ignored for `WatermarkPosition::Center`.
+    pub margin: u32,
+}
+
+impl Watermark {
+    pub fn new(png: impl Into<Vec<u8>>) -> Self {
+        Watermark {
+            png: png.into(),
+            position: WatermarkPosition::default(),
+            opacity: 1.0,
+            margin: 0,
+        }
+    }
+
+    /// A stamp of `text`, such as `DRAFT`, drawn in `color` with a built-in 5x8
+    /// pixel font scaled up `scale` times, so it looks the same on every platform.
+    /// Lines are split at `\n`; characters outside printable ASCII are drawn as `?`.
+    pub fn text(text: &str, scale: u32, color: [u8; 4]) -> Self {
+        Watermark::new(text_png(text, scale.max(1), Rgba(color)))
+    }
+
+    pub fn position(mut self, position: WatermarkPosition) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    pub fn margin(mut self, margin: u32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Top-left corner of an `overlay`-sized watermark on a `width` x `height` image.
+    fn origin(&self, width: u32, height: u32, overlay: &RgbaImage) -> (i64, i64) {
+        let margin = i64::from(self.margin);
+        let left = margin;
+        let top = margin;
+        let right = i64::from(width) - i64::from(overlay.width()) - margin;
+        let bottom = i64::from(height) - i64::from(overlay.height()) - margin;
+        match self.position {
+            WatermarkPosition::TopLeft => (left, top),
+            WatermarkPosition::TopRight => (right, top),
+            WatermarkPosition::BottomLeft => (left, bottom),
+            WatermarkPosition::BottomRight => (right, bottom),
+            WatermarkPosition::Center => (
+                (i64::from(width) - i64::from(overlay.width())) / 2,
+                (i64::from(height) - i64::from(overlay.height())) / 2,
+            ),
+        }
+    }
+}
+
+/// Columns of the glyphs of `FONT`, least significant bit at the top.
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 8;
+
+/// Render `text` with `FONT` and encode it as a PNG with a transparent background.
+fn text_png(text: &str, scale: u32, color: Rgba<u8>) -> Vec<u8> {
+    let lines: Vec<&str> = text.lines().collect();
+    let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) as u32;
+    // One column/row of spacing between characters and lines, none after the last.
+    let width = (columns * (GLYPH_WIDTH + 1)).saturating_sub(1).max(1) * scale;
+    let height = (lines.len() as u32 * (GLYPH_HEIGHT + 1)).saturating_sub(1).max(1) * scale;
+
+    let mut image = RgbaImage::new(width, height);
+    for (row, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let glyph = match c {
+                ' '..='~' => FONT[c as usize - ' ' as usize],
+                _ => FONT['?' as usize - ' ' as usize],
+            };
+            let left = column as u32 * (GLYPH_WIDTH + 1);
+            let top = row as u32 * (GLYPH_HEIGHT + 1);
+            for (x, bits) in (0..).zip(glyph) {
+                for y in (0..GLYPH_HEIGHT).filter(|y| bits >> y & 1 == 1) {
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            let px = (left + x) * scale + dx;
+                            let py = (top + y) * scale + dy;
+                            image.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut png = Vec::new();
+    // Encoding an in-memory RGBA image doesn't fail.
+    PngEncoder::new(&mut png)
+        .write_image(image.as_raw(), width, height, ExtendedColorType::Rgba8)
+        .unwrap();
+    png
+}
+
+/// ICC profile embedded in exported images.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum IccProfile {
+    /// The color space the canvas renders in.
+    Srgb,
+    /// Raw bytes of an RGB ICC profile.
+    Custom(Vec<u8>),
+}
+
+impl IccProfile {
+    fn to_bytes(&self) -> Result<Vec<u8>, BokehRenderError> {
+        match self {
+            IccProfile::Srgb => ColorProfile::new_srgb().encode().map_err(post_process_error),
+            IccProfile::Custom(icc) => Ok(icc.clone()),
+        }
+    }
+}
+
+fn post_process_error(e: impl std::fmt::Display) -> BokehRenderError {
+    BokehRenderError::PostProcess(e.to_string())
+}
+
+/// Apply the post-processing steps requested in `options` to an exported image,
+/// and tag PNGs with their resolution. PDFs are returned untouched.
+pub(crate) fn post_process(
+    mut output: RenderOutput,
+    options: &RenderOptions,
+) -> Result<RenderOutput, BokehRenderError> {
+    let RenderFormat::Image(typ) = &output.format else {
+        return Ok(output);
+    };
+    let format = ImageFormat::from_mime_type(typ);
+
+    if options.watermark.is_some() || options.icc_profile.is_some() || options.trim_margins {
+        let format = format
+            .ok_or_else(|| post_process_error(format!("unsupported image type {}", typ)))?;
+        let mut image = image::load_from_memory_with_format(&output.bytes, format)
+            .map_err(post_process_error)?
+            .into_rgba8();
+        if options.trim_margins {
+            image = trim_margins(image);
+            output.width = image.width();
+            output.height = image.height();
+        }
+        if let Some(watermark) = &options.watermark {
+            apply_watermark(&mut image, watermark)?;
+        }
+        let icc = options
+            .icc_profile
+            .as_ref()
+            .map(IccProfile::to_bytes)
+            .transpose()?;
+        if let (Some(icc), true) = (&icc, options.convert_to_icc_profile) {
+            convert_from_srgb(&mut image, icc)?;
+        }
+        output.bytes = encode(DynamicImage::ImageRgba8(image), format, icc)?;
+    }
+    // Encoders, the page's and ours, don't record a resolution; without one
+    // Word and LaTeX import the image at 72 or 96 DPI instead of the requested size.
+    if format == Some(ImageFormat::Png) {
+        output.bytes = set_png_dpi(&output.bytes, output.dpi)?;
+    }
+    if let Some(max_edge) = options.thumbnail {
+        let format = format
+            .ok_or_else(|| post_process_error(format!("unsupported image type {}", typ)))?;
+        output.thumbnail = Some(thumbnail(&output, format, max_edge, options)?);
+    }
+
+    Ok(output)
+}
+
+/// Crop the border rows and columns that are uniformly the color of the top-left
+/// pixel. Fully transparent pixels match whatever their color channels hold.
+/// Images that are nothing but border are returned as they are.
+fn trim_margins(image: RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return image;
+    }
+    let border = image.get_pixel(0, 0).0;
+    let same = |x: u32, y: u32| {
+        let pixel = image.get_pixel(x, y).0;
+        pixel == border || (pixel[3] == 0 && border[3] == 0)
+    };
+    let blank_row = |y: u32| (0..width).all(|x| same(x, y));
+    let blank_column = |x: u32, top: u32, bottom: u32| (top..bottom).all(|y| same(x, y));
+
+    let Some(top) = (0..height).find(|&y| !blank_row(y)) else {
+        return image;
+    };
+    let bottom = (top..height).rev().find(|&y| !blank_row(y)).unwrap() + 1;
+    let left = (0..width).find(|&x| !blank_column(x, top, bottom)).unwrap();
+    let right = (left..width)
+        .rev()
+        .find(|&x| !blank_column(x, top, bottom))
+        .unwrap()
+        + 1;
+    imageops::crop_imm(&image, left, top, right - left, bottom - top).to_image()
+}
+
+/// `output` downscaled to fit `max_edge` pixels on its longer edge and encoded
+/// the same way. Images already that small are kept as they are.
+fn thumbnail(
+    output: &RenderOutput,
+    format: ImageFormat,
+    max_edge: u32,
+    options: &RenderOptions,
+) -> Result<Vec<u8>, BokehRenderError> {
+    if output.width.max(output.height) <= max_edge {
+        return Ok(output.bytes.clone());
+    }
+    let image = image::load_from_memory_with_format(&output.bytes, format)
+        .map_err(post_process_error)?
+        .resize(max_edge.max(1), max_edge.max(1), imageops::FilterType::Lanczos3);
+    let icc = options
+        .icc_profile
+        .as_ref()
+        .map(IccProfile::to_bytes)
+        .transpose()?;
+    encode(image, format, icc)
+}
+
+fn apply_watermark(image: &mut RgbaImage, watermark: &Watermark) -> Result<(), BokehRenderError> {
+    let mut overlay = image::load_from_memory_with_format(&watermark.png, ImageFormat::Png)
+        .map_err(post_process_error)?
+        .into_rgba8();
+    let opacity = watermark.opacity.clamp(0.0, 1.0);
+    for pixel in overlay.pixels_mut() {
+        pixel[3] = (f32::from(pixel[3]) * opacity).round() as u8;
+    }
+
+    let (x, y) = watermark.origin(image.width(), image.height(), &overlay);
+    imageops::overlay(image, &overlay, x, y);
+    Ok(())
+}
+
+/// Convert the canvas's sRGB pixels into the color space of `icc`.
+fn convert_from_srgb(image: &mut RgbaImage, icc: &[u8]) -> Result<(), BokehRenderError> {
+    let target = ColorProfile::new_from_slice(icc).map_err(post_process_error)?;
+    let transform = ColorProfile::new_srgb()
+        .create_transform_8bit(Layout::Rgba, &target, Layout::Rgba, TransformOptions::default())
+        .map_err(post_process_error)?;
+    let source = image.as_raw().clone();
+    transform
+        .transform(&source, image)
+        .map_err(post_process_error)
+}
+
+/// Re-encode `image` in the format the page originally exported, embedding `icc` if given.
+fn encode(
+    image: DynamicImage,
+    format: ImageFormat,
+    icc: Option<Vec<u8>>,
+) -> Result<Vec<u8>, BokehRenderError> {
+    let mut bytes = Vec::new();
+    match format {
+        // Match `toDataURL(typ, 1.0)`; JPEG has no alpha channel.
+        ImageFormat::Jpeg => write_image(
+            JpegEncoder::new_with_quality(&mut bytes, 100),
+            &DynamicImage::ImageRgb8(image.into_rgb8()),
+            icc,
+        ),
+        ImageFormat::Png => write_image(PngEncoder::new(&mut bytes), &image, icc),
+        ImageFormat::WebP => write_image(WebPEncoder::new_lossless(&mut bytes), &image, icc),
+        format => Err(post_process_error(format!("cannot encode {:?}", format))),
+    }?;
+    Ok(bytes)
+}
+
+fn write_image(
+    mut encoder: impl ImageEncoder,
+    image: &DynamicImage,
+    icc: Option<Vec<u8>>,
+) -> Result<(), BokehRenderError> {
+    if let Some(icc) = icc {
+        encoder.set_icc_profile(icc).map_err(post_process_error)?;
+    }
+    encoder
+        .write_image(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color().into(),
+        )
+        .map_err(post_process_error)
+}
+
+pub(crate) const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Replace any `pHYs` chunk of `png` with one declaring `dpi`. The new chunk
+/// goes right after `IHDR`, since the spec requires it to precede the image data.
+fn set_png_dpi(png: &[u8], dpi: Dpi) -> Result<Vec<u8>, BokehRenderError> {
+    let mut chunks = png
+        .strip_prefix(&PNG_SIGNATURE)
+        .ok_or_else(|| post_process_error("not a PNG image"))?;
+    let pixels_per_meter = ((dpi.get() / 0.0254).round() as u32).to_be_bytes();
+    let mut phys = Vec::with_capacity(9);
+    phys.extend_from_slice(&pixels_per_meter);
+    phys.extend_from_slice(&pixels_per_meter);
+    phys.push(1); // unit: meter
+
+    let mut bytes = Vec::with_capacity(png.len() + 21);
+    bytes.extend_from_slice(&PNG_SIGNATURE);
+    while !chunks.is_empty() {
+        let length = chunks
+            .get(..4)
+            .map(|length| u32::from_be_bytes(length.try_into().unwrap()) as usize)
+            .ok_or_else(|| post_process_error("truncated PNG chunk"))?;
+        let chunk = chunks
+            .get(..length + 12)
+            .ok_or_else(|| post_process_error("truncated PNG chunk"))?;
+        let kind = &chunk[4..8];
+        if kind != b"pHYs" {
+            bytes.extend_from_slice(chunk);
+        }
+        if kind == b"IHDR" {
+            write_png_chunk(&mut bytes, b"pHYs", &phys);
+        }
+        chunks = &chunks[chunk.len()..];
+    }
+    Ok(bytes)
+}
+
+fn write_png_chunk(bytes: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(kind);
+    bytes.extend_from_slice(data);
+    bytes.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+/// Container for `DataImage`s.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum DataImageFormat {
+    /// 16-bit grayscale PNG.
+    Png16,
+    /// 16-bit grayscale TIFF.
+    Tiff16,
+}
+
+/// The values of one `Image` glyph, scaled linearly from `low..=high` onto the
+/// full 16-bit range, without the precision the 8-bit canvas export loses.
+#[derive(Clone, Debug)]
+pub struct DataImage {
+    /// Name of the glyph renderer, if it has one.
+    pub name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    /// The values mapped to 0 and 65535: the color mapper's range if it sets
+    /// one, otherwise the range of the data. NaNs are stored as 0.
+    pub low: f64,
+    pub high: f64,
+    pub format: DataImageFormat,
+    pub bytes: Vec<u8>,
+}
+
+/// An `Image` glyph's values as collected by `imageGlyphData` in `render_bokeh.js`.
+#[derive(Deserialize)]
+pub(crate) struct GlyphData {
+    name: Option<String>,
+    width: u32,
+    height: u32,
+    low: Option<f64>,
+    high: Option<f64>,
+    /// Base64 of the row-major little-endian `f64` values, bottom row first.
+    values: String,
+}
+
+pub(crate) fn encode_data_image(
+    glyph: GlyphData,
+    format: DataImageFormat,
+) -> Result<DataImage, BokehRenderError> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(&glyph.values)
+        .map_err(post_process_error)?;
+    let values: Vec<f64> = bytes
+        .chunks_exact(8)
+        .map(|value| f64::from_le_bytes(value.try_into().unwrap()))
+        .collect();
+    if values.len() != glyph.width as usize * glyph.height as usize {
+        return Err(post_process_error(format!(
+            "image glyph data has {} values, expected {}x{}",
+            values.len(),
+            glyph.width,
+            glyph.height
+        )));
+    }
+
+    let finite = values.iter().copied().filter(|value| value.is_finite());
+    let low = glyph.low.unwrap_or_else(|| finite.clone().fold(f64::INFINITY, f64::min));
+    let high = glyph.high.unwrap_or_else(|| finite.fold(f64::NEG_INFINITY, f64::max));
+    let scale = if high > low { 65535.0 / (high - low) } else { 0.0 };
+
+    // Bokeh's first row is the bottom of the plot; image rows run top to bottom.
+    let image = ImageBuffer::from_fn(glyph.width, glyph.height, |x, y| {
+        let row = (glyph.height - 1 - y) as usize;
+        let value = values[row * glyph.width as usize + x as usize];
+        let scaled = if value.is_nan() {
+            0.0
+        } else {
+            ((value - low) * scale).clamp(0.0, 65535.0)
+        };
+        Luma([scaled.round() as u16])
+    });
+
+    let mut bytes = Vec::new();
+    let container = match format {
+        DataImageFormat::Png16 => ImageFormat::Png,
+        DataImageFormat::Tiff16 => ImageFormat::Tiff,
+    };
+    DynamicImage::ImageLuma16(image)
+        .write_to(&mut Cursor::new(&mut bytes), container)
+        .map_err(post_process_error)?;
+
+    Ok(DataImage {
+        name: glyph.name,
+        width: glyph.width,
+        height: glyph.height,
+        low,
+        high,
+        format,
+        bytes,
+    })
+}
+
+fn tiff_error(e: impl std::fmt::Display) -> BokehRenderError {
+    post_process_error(format!("TIFF encoding failed: {}", e))
+}
+
+/// Combine rendered images into a single TIFF with one page per output.
+pub(crate) fn encode_multipage_tiff(outputs: &[RenderOutput]) -> Result<Vec<u8>, BokehRenderError> {
+    let mut bytes = Vec::new();
+    let mut encoder = TiffEncoder::new(Cursor::new(&mut bytes))
+        .map_err(tiff_error)?
+        .with_compression(Compression::Lzw);
+    for output in outputs {
+        let RenderFormat::Image(typ) = &output.format else {
+            return Err(BokehRenderError::InvalidRequest(
+                "A multi-page TIFF can only hold images, not PDFs".to_string(),
+            ));
+        };
+        let format = ImageFormat::from_mime_type(typ)
+            .ok_or_else(|| post_process_error(format!("unsupported image type {}", typ)))?;
+        let image = image::load_from_memory_with_format(&output.bytes, format)
+            .map_err(post_process_error)?
+            .into_rgb8();
+
+        let mut page = encoder
+            .new_image::<colortype::RGB8>(image.width(), image.height())
+            .map_err(tiff_error)?;
+        page.resolution(
+            ResolutionUnit::Inch,
+            Rational {
+                // Hundredths keep fractional resolutions.
+                n: (output.dpi.get() * 100.0).round() as u32,
+                d: 100,
+            },
+        );
+        page.write_data(image.as_raw()).map_err(tiff_error)?;
+    }
+    Ok(bytes)
+}
+
+/// A 5x8 bitmap font of printable ASCII, from `' '` to `'~'`.
+const FONT: [[u8; GLYPH_WIDTH as usize]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // '!'
+    [0x00, 0x07, 0x00, 0x07, 0x00], // '"'
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // '#'
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // '$'
+    [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+    [0x36, 0x49, 0x56, 0x20, 0x50], // '&'
+    [0x00, 0x08, 0x07, 0x03, 0x00], // '\''
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // '('
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // ')'
+    [0x2A, 0x1C, 0x7F, 0x1C, 0x2A], // '*'
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // '+'
+    [0x00, 0x80, 0x70, 0x30, 0x00], // ','
+    [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+    [0x00, 0x00, 0x60, 0x60, 0x00], // '.'
+    [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // '0'
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // '1'
+    [0x72, 0x49, 0x49, 0x49, 0x46], // '2'
+    [0x21, 0x41, 0x49, 0x4D, 0x33], // '3'
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // '4'
+    [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+    [0x3C, 0x4A, 0x49, 0x49, 0x31], // '6'
+    [0x41, 0x21, 0x11, 0x09, 0x07], // '7'
+    [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+    [0x46, 0x49, 0x49, 0x29, 0x1E], // '9'
+    [0x00, 0x00, 0x14, 0x00, 0x00], // ':'
+    [0x00, 0x40, 0x34, 0x00, 0x00], // ';'
+    [0x00, 0x08, 0x14, 0x22, 0x41], // '<'
+    [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+    [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+    [0x02, 0x01, 0x59, 0x09, 0x06], // '?'
+    [0x3E, 0x41, 0x5D, 0x59, 0x4E], // '@'
+    [0x7C, 0x12, 0x11, 0x12, 0x7C], // 'A'
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // 'B'
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // 'C'
+    [0x7F, 0x41, 0x41, 0x41, 0x3E], // 'D'
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // 'E'
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // 'F'
+    [0x3E, 0x41, 0x41, 0x51, 0x73], // 'G'
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // 'H'
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // 'I'
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // 'J'
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // 'K'
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // 'L'
+    [0x7F, 0x02, 0x1C, 0x02, 0x7F], // 'M'
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // 'N'
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // 'O'
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // 'P'
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // 'Q'
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // 'R'
+    [0x26, 0x49, 0x49, 0x49, 0x32], // 'S'
+    [0x03, 0x01, 0x7F, 0x01, 0x03], // 'T'
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // 'U'
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // 'V'
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // 'W'
+    [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+    [0x03, 0x04, 0x78, 0x04, 0x03], // 'Y'
+    [0x61, 0x59, 0x49, 0x4D, 0x43], // 'Z'
+    [0x00, 0x7F, 0x41, 0x41, 0x41], // '['
+    [0x02, 0x04, 0x08, 0x10, 0x20], // '\\'
+    [0x00, 0x41, 0x41, 0x41, 0x7F], // ']'
+    [0x04, 0x02, 0x01, 0x02, 0x04], // '^'
+    [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+    [0x00, 0x03, 0x07, 0x08, 0x00], // '`'
+    [0x20, 0x54, 0x54, 0x78, 0x40], // 'a'
+    [0x7F, 0x28, 0x44, 0x44, 0x38], // 'b'
+    [0x38, 0x44, 0x44, 0x44, 0x28], // 'c'
+    [0x38, 0x44, 0x44, 0x28, 0x7F], // 'd'
+    [0x38, 0x54, 0x54, 0x54, 0x18], // 'e'
+    [0x00, 0x08, 0x7E, 0x09, 0x02], // 'f'
+    [0x18, 0xA4, 0xA4, 0x9C, 0x78], // 'g'
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // 'h'
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // 'i'
+    [0x20, 0x40, 0x40, 0x3D, 0x00], // 'j'
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // 'k'
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // 'l'
+    [0x7C, 0x04, 0x78, 0x04, 0x78], // 'm'
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // 'n'
+    [0x38, 0x44, 0x44, 0x44, 0x38], // 'o'
+    [0xFC, 0x18, 0x24, 0x24, 0x18], // 'p'
+    [0x18, 0x24, 0x24, 0x18, 0xFC], // 'q'
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // 'r'
+    [0x48, 0x54, 0x54, 0x54, 0x24], // 's'
+    [0x04, 0x04, 0x3F, 0x44, 0x24], // 't'
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // 'u'
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // 'v'
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // 'w'
+    [0x44, 0x28, 0x10, 0x28, 0x44], // 'x'
+    [0x4C, 0x90, 0x90, 0x90, 0x7C], // 'y'
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // 'z'
+    [0x00, 0x08, 0x36, 0x41, 0x00], // '{'
+    [0x00, 0x00, 0x77, 0x00, 0x00], // '|'
+    [0x00, 0x41, 0x36, 0x08, 0x00], // '}'
+    [0x02, 0x01, 0x02, 0x04, 0x02], // '~'
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(image: &RgbaImage) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, ImageFormat::Png).unwrap();
+        bytes.into_inner()
+    }
+
+    /// The kind and data of every chunk of `png`.
+    fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut chunks = &png[PNG_SIGNATURE.len()..];
+        let mut all = Vec::new();
+        while !chunks.is_empty() {
+            let length = u32::from_be_bytes(chunks[..4].try_into().unwrap()) as usize;
+            let kind = String::from_utf8_lossy(&chunks[4..8]).into_owned();
+            all.push((kind, chunks[8..8 + length].to_vec()));
+            chunks = &chunks[length + 12..];
+        }
+        all
+    }
+
+    fn phys(dpi: f64) -> Vec<u8> {
+        let pixels_per_meter = ((dpi / 0.0254).round() as u32).to_be_bytes();
+        [&pixels_per_meter[..], &pixels_per_meter[..], &[1]].concat()
+    }
+
+    #[test]
+    fn set_png_dpi_adds_phys_after_ihdr() {
+        let original = png(&RgbaImage::new(3, 2));
+        let bytes = set_png_dpi(&original, Dpi::new(300.0)).unwrap();
+        let chunks = chunks(&bytes);
+        assert_eq!(chunks[0].0, "IHDR");
+        assert_eq!(chunks[1], ("pHYs".to_string(), phys(300.0)));
+        assert_eq!(chunks.last().unwrap().0, "IEND");
+        let decoded = image::load_from_memory_with_format(&bytes, ImageFormat::Png).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (3, 2));
+    }
+
+    #[test]
+    fn set_png_dpi_replaces_an_existing_phys() {
+        let original = png(&RgbaImage::new(1, 1));
+        let at_96 = set_png_dpi(&original, Dpi::new(96.0)).unwrap();
+        let at_192 = set_png_dpi(&at_96, Dpi::new(192.0)).unwrap();
+        let phys_chunks: Vec<_> = chunks(&at_192)
+            .into_iter()
+            .filter(|(kind, _)| kind == "pHYs")
+            .collect();
+        assert_eq!(phys_chunks, [("pHYs".to_string(), phys(192.0))]);
+        assert_eq!(at_192.len(), at_96.len());
+    }
+
+    #[test]
+    fn set_png_dpi_rejects_other_and_truncated_data() {
+        assert!(set_png_dpi(b"GIF89a", Dpi::new(96.0)).is_err());
+        let original = png(&RgbaImage::new(1, 1));
+        assert!(set_png_dpi(&original[..original.len() - 3], Dpi::new(96.0)).is_err());
+        assert!(set_png_dpi(&original[..PNG_SIGNATURE.len() + 2], Dpi::new(96.0)).is_err());
+    }
+
+    #[test]
+    fn trim_margins_crops_to_the_content() {
+        let mut image = RgbaImage::from_pixel(6, 5, Rgba([255, 255, 255, 255]));
+        image.put_pixel(2, 1, Rgba([255, 0, 0, 255]));
+        image.put_pixel(3, 3, Rgba([0, 0, 255, 255]));
+        let trimmed = trim_margins(image);
+        assert_eq!(trimmed.dimensions(), (2, 3));
+        assert_eq!(trimmed.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(trimmed.get_pixel(1, 2), &Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn trim_margins_treats_every_transparent_pixel_as_border() {
+        let mut image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 0]));
+        image.put_pixel(0, 3, Rgba([255, 255, 255, 0]));
+        image.put_pixel(1, 1, Rgba([0, 128, 0, 255]));
+        assert_eq!(trim_margins(image).dimensions(), (1, 1));
+    }
+
+    #[test]
+    fn trim_margins_keeps_images_without_content() {
+        let blank = RgbaImage::from_pixel(3, 2, Rgba([255, 255, 255, 255]));
+        assert_eq!(trim_margins(blank.clone()), blank);
+        assert_eq!(trim_margins(RgbaImage::new(0, 0)).dimensions(), (0, 0));
+        let mut full = RgbaImage::from_pixel(2, 2, Rgba([0, 0, 0, 255]));
+        full.put_pixel(1, 1, Rgba([255, 255, 255, 255]));
+        full.put_pixel(0, 1, Rgba([255, 255, 255, 255]));
+        full.put_pixel(1, 0, Rgba([255, 255, 255, 255]));
+        assert_eq!(trim_margins(full.clone()), full);
+    }
+}
@@ -0,0 +1,21 @@
+//! The types most renders need, for `use wry_bokeh_helper::prelude::*`.
+
+pub use crate::bokeh_helpers::{
+    render_bokeh_at_dpis, render_bokeh_batch, render_bokeh_in_webview, render_bokeh_report,
+    render_bokeh_server_session, render_bokeh_value, OutputFormat,
+};
+pub use crate::embed::{BokehRenderJob, RenderJobEvent};
+pub use crate::engine::{
+    render_chart_in_webview, ChartEngine, EChartsEngine, PlotlyEngine, VegaLiteEngine,
+};
+pub use crate::options::{
+    Millimeters, Priority, RenderFormat, RenderInput, RenderOptions, RenderRequest, SettleStrategy,
+    WaitUntil,
+};
+pub use crate::output::{BokehRenderError, ConsoleMessage, RenderOutput, RenderTimings};
+pub use crate::pdf::{Margins, PageSize, ReportLayout};
+pub use crate::pool::RendererPool;
+pub use crate::postprocess::{IccProfile, Watermark, WatermarkPosition};
+pub use crate::renderer::{BokehRenderer, MainThreadRenderer, RenderEvent, RenderEvents};
+pub use crate::resources::{BokehResource, BokehResourceError, CssSource, ScriptSource};
+pub use crate::version::BokehVersion;
@@ -0,0 +1,89 @@
+//! Notices when the webview's web content process dies, most often because a
+//! document too large for it ran it out of memory. Without this the renderer
+//! waits on a page that will never post a result.
+
+use wry::WebView;
+
+use crate::protocol::EventSink;
+
+/// Deliver `UserEvent::ProcessFailed` to `events` whenever the web content
+/// process of `webview` terminates.
+pub(crate) fn watch_web_process(webview: &WebView, events: EventSink) {
+    platform::watch_web_process(webview, events);
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use webkit2gtk::{WebProcessTerminationReason, WebViewExt};
+    use wry::{WebView, WebViewExtUnix};
+
+    use crate::protocol::{EventSink, UserEvent};
+
+    pub fn watch_web_process(webview: &WebView, events: EventSink) {
+        webview
+            .webview()
+            .connect_web_process_terminated(move |_, reason| {
+                let out_of_memory =
+                    matches!(reason, WebProcessTerminationReason::ExceededMemoryLimit);
+                events.send(UserEvent::ProcessFailed { out_of_memory });
+            });
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use webview2_com::{
+        Microsoft::Web::WebView2::Win32::{
+            ICoreWebView2ProcessFailedEventArgs2, COREWEBVIEW2_PROCESS_FAILED_KIND,
+            COREWEBVIEW2_PROCESS_FAILED_KIND_RENDER_PROCESS_EXITED,
+            COREWEBVIEW2_PROCESS_FAILED_REASON, COREWEBVIEW2_PROCESS_FAILED_REASON_OUT_OF_MEMORY,
+        },
+        ProcessFailedEventHandler,
+    };
+    use windows::{core::Interface, Win32::System::WinRT::EventRegistrationToken};
+    use wry::{WebView, WebViewExtWindows};
+
+    use crate::protocol::{EventSink, UserEvent};
+
+    /// Only the render process matters: WebView2 restarts its other processes
+    /// itself, and an unresponsive page may still recover.
+    pub fn watch_web_process(webview: &WebView, events: EventSink) {
+        let handler = ProcessFailedEventHandler::create(Box::new(move |_, args| {
+            let Some(args) = args else {
+                return Ok(());
+            };
+            let mut kind = COREWEBVIEW2_PROCESS_FAILED_KIND::default();
+            unsafe { args.ProcessFailedKind(&mut kind)? };
+            if kind != COREWEBVIEW2_PROCESS_FAILED_KIND_RENDER_PROCESS_EXITED {
+                return Ok(());
+            }
+            // The reason needs a WebView2 runtime from 2021 or later.
+            let mut reason = COREWEBVIEW2_PROCESS_FAILED_REASON::default();
+            let out_of_memory = args
+                .cast::<ICoreWebView2ProcessFailedEventArgs2>()
+                .and_then(|args| unsafe { args.Reason(&mut reason) })
+                .is_ok_and(|_| reason == COREWEBVIEW2_PROCESS_FAILED_REASON_OUT_OF_MEMORY);
+            events.send(UserEvent::ProcessFailed { out_of_memory });
+            Ok(())
+        }));
+
+        let mut token = EventRegistrationToken::default();
+        unsafe {
+            // Without the handler a crash hangs the render as before.
+            let _ = webview
+                .controller()
+                .CoreWebView2()
+                .and_then(|core| core.add_ProcessFailed(&handler, &mut token));
+        }
+    }
+}
+
+/// WKWebView reports terminations to its navigation delegate, which wry owns.
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+mod platform {
+    use wry::WebView;
+
+    use crate::protocol::EventSink;
+
+    pub fn watch_web_process(_webview: &WebView, _events: EventSink) {}
+}
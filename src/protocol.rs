@@ -0,0 +1,698 @@
+//! How the render page and the renderer talk: the `wry://` custom protocol
+//! serving the page and its resources, and the messages the page posts over IPC.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use serde::Deserialize;
+use tao::event_loop::EventLoopProxy;
+use tokio::sync::mpsc::UnboundedSender;
+use wry::http::{self, Request};
+
+use crate::diagnostics::is_blank;
+use crate::fonts;
+use crate::options::{Dpi, RenderFormat, RenderOptions};
+use crate::output::{
+    BokehRenderError, ConsoleMessage, RenderOutput, RenderTimings, ResourceFailure,
+};
+use crate::postprocess::{encode_data_image, post_process, GlyphData};
+use crate::proxy;
+use crate::renderer::{RenderEvent, RendererCommand};
+use crate::resources::{sha256_hex, BokehResource, Resource, LOCAL_BUNDLES};
+use crate::tiles::ExportedTile;
+
+/// Where the render page's webview handlers deliver their `UserEvent`s: the
+/// event loop of a render, or a host application's for a `BokehRenderJob`.
+#[derive(Clone)]
+pub struct EventSink(Arc<dyn Fn(UserEvent) + Send + Sync>);
+
+impl EventSink {
+    pub(crate) fn new(send: impl Fn(UserEvent) + Send + Sync + 'static) -> Self {
+        EventSink(Arc::new(send))
+    }
+
+    pub(crate) fn send(&self, event: UserEvent) {
+        (self.0)(event)
+    }
+}
+
+impl From<EventLoopProxy<UserEvent>> for EventSink {
+    fn from(event_loop_proxy: EventLoopProxy<UserEvent>) -> Self {
+        EventSink::new(move |event| {
+            let _ = event_loop_proxy.send_event(event);
+        })
+    }
+}
+
+pub enum UserEvent {
+    PayloadReceived(ExportedImage),
+    /// One tile of a `RenderOptions::tiled` export.
+    Tile(ExportedTile),
+    /// The document is embedded and laid out, ready to be printed.
+    Printable {
+        width: f64,
+        height: f64,
+        timings: RenderTimings,
+    },
+    PdfPrinted(Result<Vec<u8>, String>),
+    /// The page can't complete the render, e.g. because a bundle failed to load.
+    PageError(String),
+    /// A bundle, extra script or stylesheet failed to load: a
+    /// `BokehRenderError::ResourceFailed` if the custom protocol refused it,
+    /// otherwise a `BokehRenderError::ResourceLoad`. Bundles of a release the
+    /// page can't use fail with `BokehRenderError::UnsupportedVersion`.
+    ResourceLoadFailed(BokehRenderError),
+    /// The web content process terminated, see `process::watch_web_process`.
+    ProcessFailed { out_of_memory: bool },
+    /// A session document finished embedding (`BokehRenderer::load`).
+    Loaded,
+    /// A milestone of the render in progress, for `BokehRenderer::render_with_events`.
+    Progress(RenderEvent),
+    PageLoaded,
+    /// `Bokeh.version` of the freshly loaded page, if BokehJS is loaded.
+    BokehVersion(Option<String>),
+    Command(RendererCommand),
+    Console(ConsoleMessage),
+    /// Results of `probeBokehApi`, see `contract::check_bokeh_api`.
+    #[cfg(feature = "contract-checks")]
+    ApiChecks(Vec<crate::contract::ApiCheck>),
+}
+
+/// Messages posted by the render page through `window.ipc`.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum IpcMessage {
+    Image {
+        /// `None` if the image was uploaded to `/capture` instead.
+        data_url: Option<String>,
+        width: u32,
+        height: u32,
+        timings: PageTimings,
+        #[serde(default)]
+        data_images: Vec<GlyphData>,
+        /// The index in `RenderOptions::dpis` of a downscaled export.
+        #[serde(default)]
+        variant: Option<usize>,
+    },
+    Printable {
+        width: f64,
+        height: f64,
+        timings: PageTimings,
+    },
+    Tile {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        data_url: String,
+        timings: Option<PageTimings>,
+    },
+    Preview {
+        data_url: String,
+        width: u32,
+        height: u32,
+        dpi: Dpi,
+        typ: String,
+        timings: PageTimings,
+    },
+    Loaded,
+    Progress {
+        stage: RenderEvent,
+    },
+    Error {
+        message: String,
+    },
+    ResourceLoadFailed {
+        url: Option<String>,
+        reason: String,
+    },
+    UnsupportedVersion {
+        version: String,
+    },
+    Console(ConsoleMessage),
+    #[cfg(feature = "contract-checks")]
+    ApiChecks {
+        checks: Vec<crate::contract::ApiCheck>,
+    },
+}
+
+/// Milliseconds measured by the page with `performance.now()`.
+#[derive(Deserialize)]
+struct PageTimings {
+    bokeh_load: f64,
+    embed: f64,
+    export: f64,
+    /// When the message was posted, in milliseconds since the Unix epoch.
+    sent_at: f64,
+}
+
+impl PageTimings {
+    fn into_render_timings(self) -> RenderTimings {
+        let received_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64() * 1000.0)
+            .unwrap_or_default();
+        RenderTimings {
+            bokeh_load: millis(self.bokeh_load),
+            embed: millis(self.embed),
+            export: millis(self.export),
+            ipc: millis(received_at - self.sent_at),
+        }
+    }
+}
+
+fn millis(ms: f64) -> Duration {
+    Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+}
+
+pub(crate) fn ipc_handler(
+    payload: &Request<String>,
+    events: &EventSink,
+    console_sender: Option<&UnboundedSender<ConsoleMessage>>,
+    preview_sender: Option<&UnboundedSender<RenderOutput>>,
+    capture: &CaptureSlot,
+    failures: &ResourceFailures,
+) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(bytes = payload.body().len(), "received IPC message");
+    let event = match serde_json::from_str(payload.body()) {
+        Ok(IpcMessage::Image {
+            data_url,
+            width,
+            height,
+            timings,
+            data_images,
+            variant,
+        }) => UserEvent::PayloadReceived(ExportedImage {
+            content: match data_url {
+                Some(data_url) => ExportedContent::DataUrl(data_url),
+                None => ExportedContent::Captured(capture.lock().unwrap().take()),
+            },
+            width,
+            height,
+            timings: timings.into_render_timings(),
+            data_images,
+            variant,
+        }),
+        Ok(IpcMessage::Printable {
+            width,
+            height,
+            timings,
+        }) => UserEvent::Printable {
+            width,
+            height,
+            timings: timings.into_render_timings(),
+        },
+        Ok(IpcMessage::Tile {
+            x,
+            y,
+            width,
+            height,
+            data_url,
+            timings,
+        }) => UserEvent::Tile(ExportedTile {
+            x,
+            y,
+            width,
+            height,
+            data_url,
+            timings: timings.map(PageTimings::into_render_timings),
+        }),
+        Ok(IpcMessage::Preview {
+            data_url,
+            width,
+            height,
+            dpi,
+            typ,
+            timings,
+        }) => {
+            // Previews skip the event loop, which only handles the final result.
+            if let (Some(sender), Ok(bytes)) = (preview_sender, decode_data_url(&data_url)) {
+                let _ = sender.send(RenderOutput {
+                    bytes,
+                    width,
+                    height,
+                    dpi,
+                    format: RenderFormat::Image(typ),
+                    timings: timings.into_render_timings(),
+                    data_images: Vec::new(),
+                    console: Vec::new(),
+                    thumbnail: None,
+                });
+            }
+            return;
+        }
+        Ok(IpcMessage::Loaded) => UserEvent::Loaded,
+        Ok(IpcMessage::Progress { stage }) => UserEvent::Progress(stage),
+        Ok(IpcMessage::Error { message }) => UserEvent::PageError(message),
+        Ok(IpcMessage::ResourceLoadFailed { url, reason }) => {
+            let failure = url
+                .as_deref()
+                .and_then(page_path)
+                .and_then(|path| failures.lock().unwrap().remove(path));
+            UserEvent::ResourceLoadFailed(match failure {
+                Some(failure) => BokehRenderError::ResourceFailed(failure),
+                None => BokehRenderError::ResourceLoad { url, reason },
+            })
+        }
+        Ok(IpcMessage::UnsupportedVersion { version }) => {
+            UserEvent::ResourceLoadFailed(match version.parse() {
+                Ok(version) => BokehRenderError::UnsupportedVersion(version),
+                Err(_) => BokehRenderError::Page(format!("BokehJS {} is not supported", version)),
+            })
+        }
+        Ok(IpcMessage::Console(message)) => {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                target: "wry_bokeh_helper::page",
+                level = ?message.level,
+                source = %message.source,
+                "{}",
+                message.message
+            );
+            if let Some(sender) = console_sender {
+                let _ = sender.send(message.clone());
+            }
+            UserEvent::Console(message)
+        }
+        #[cfg(feature = "contract-checks")]
+        Ok(IpcMessage::ApiChecks { checks }) => UserEvent::ApiChecks(checks),
+        Err(_error) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = %_error, "ignored malformed IPC message");
+            return;
+        }
+    };
+    events.send(event);
+}
+
+/// The image last uploaded to `/capture`, until the IPC message announcing it
+/// is handled.
+pub(crate) type CaptureSlot = Arc<Mutex<Option<Vec<u8>>>>;
+
+/// Requests the custom protocol refused, by path, until the page reports the
+/// element that failed to load with one.
+pub(crate) type ResourceFailures = Arc<Mutex<HashMap<String, ResourceFailure>>>;
+
+/// Where the exports of `RenderOptions::dpis` downscaled from the embedded one
+/// are delivered, with their index in `dpis`.
+pub(crate) type VariantSender = UnboundedSender<(usize, Result<RenderOutput, BokehRenderError>)>;
+
+/// The path of `url` if it's served by the custom protocol.
+fn page_path(url: &str) -> Option<&str> {
+    ["wry://render-bokeh", "https://wry.render-bokeh", "http://wry.render-bokeh"]
+        .iter()
+        .find_map(|origin| url.strip_prefix(origin))
+        .filter(|path| path.starts_with('/'))
+}
+
+/// Serve `page_html`, the render page, at `/`, the bundles in `LOCAL_BUNDLES` (and for local
+/// folders any file in them or their subfolders) under `/bokeh-resource-dir/`,
+/// `RenderOptions::extra_scripts` under `/extra-scripts/`,
+/// `RenderOptions::extra_css` under `/extra-css/` and the default and fallback
+/// fonts under `/fonts/`, and take uploads to `/capture` (see
+/// `RenderOptions::binary_transfer`). Anything else is refused without touching
+/// the provider. Served files carry an `ETag` the page revalidates its cached
+/// copy with, and answer `Range` requests.
+pub(crate) fn custom_protocol_handler(
+    request: Request<Vec<u8>>,
+    page_html: &str,
+    resource: &Option<BokehResource>,
+    options: &RenderOptions,
+    capture: &CaptureSlot,
+) -> http::Response<Vec<u8>> {
+    let uri = request.uri().path();
+
+    if uri == "/capture" {
+        // WebKitGTK only passes request bodies on with the `linux-body` feature;
+        // the page falls back to a data URL when the upload is refused.
+        if request.method() != http::Method::POST || request.body().is_empty() {
+            return error_response(
+                http::StatusCode::BAD_REQUEST,
+                "Expected an image in the request body".to_string(),
+            );
+        }
+        *capture.lock().unwrap() = Some(request.into_body());
+        return http::Response::builder()
+            .status(http::StatusCode::NO_CONTENT)
+            .body(Vec::new())
+            .unwrap();
+    }
+
+    if uri == "/" {
+        return http::Response::builder()
+            .header(http::header::CONTENT_TYPE, "text/html")
+            .body(page_html.as_bytes().to_vec())
+            .unwrap();
+    }
+
+    if let Some(file_name) = uri.strip_prefix("/extra-scripts/") {
+        let script = file_name
+            .strip_suffix(".js")
+            .and_then(|index| index.parse::<usize>().ok())
+            .and_then(|index| options.extra_scripts.get(index));
+        return match script {
+            Some(script) => resource_response(&request, None, file_name, script.fetch(options)),
+            None => error_response(
+                http::StatusCode::NOT_FOUND,
+                format!("{} is not an extra script", file_name),
+            ),
+        };
+    }
+
+    if let Some(file_name) = uri.strip_prefix("/extra-css/") {
+        let stylesheet = file_name
+            .strip_suffix(".css")
+            .and_then(|index| index.parse::<usize>().ok())
+            .and_then(|index| options.extra_css.get(index));
+        return match stylesheet {
+            Some(stylesheet) => {
+                resource_response(&request, None, file_name, stylesheet.fetch(options))
+            }
+            None => error_response(
+                http::StatusCode::NOT_FOUND,
+                format!("{} is not an extra stylesheet", file_name),
+            ),
+        };
+    }
+
+    if let Some(file_name) = uri.strip_prefix("/fonts/") {
+        return match fonts::fetch(options, file_name) {
+            Some(font) => resource_response(&request, None, file_name, Ok(font)),
+            None => error_response(
+                http::StatusCode::NOT_FOUND,
+                format!("{} is not a configured font", file_name),
+            ),
+        };
+    }
+
+    if let Some(target) = uri.strip_prefix("/proxy/") {
+        return proxy::forward(&request, target, options);
+    }
+
+    let Some(file_name) = uri.strip_prefix("/bokeh-resource-dir/") else {
+        return error_response(http::StatusCode::NOT_FOUND, format!("Invalid path {}", uri));
+    };
+    // Local folders serve nested files, e.g. `fonts/x.woff2` next to a bundle;
+    // providers resolve them under their folder. Backslashes, escapes and dot
+    // segments are never part of a path the page asks for.
+    if file_name.contains(['\\', '%'])
+        || file_name
+            .split('/')
+            .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        return error_response(http::StatusCode::FORBIDDEN, format!("Invalid path {}", uri));
+    }
+    let serves_assets = resource.as_ref().is_some_and(BokehResource::serves_assets);
+    let bundles = resource.as_ref().map_or(&LOCAL_BUNDLES[..], BokehResource::bundles);
+    if !bundles.contains(&file_name) && !serves_assets {
+        return error_response(
+            http::StatusCode::NOT_FOUND,
+            format!("{} is not a Bokeh bundle", file_name),
+        );
+    }
+    let Some(provider) = resource.as_ref().and_then(BokehResource::provider) else {
+        return error_response(
+            http::StatusCode::NOT_FOUND,
+            "BokehResource is not served over the custom protocol".to_string(),
+        );
+    };
+
+    // A persistent renderer navigates to the page again for every document;
+    // revalidating a bundle it has cached shouldn't read megabytes from disk.
+    // A tag that isn't a valid header value gives way to the content hash.
+    let etag = provider
+        .etag(file_name)
+        .filter(|etag| http::HeaderValue::from_str(etag).is_ok());
+    if let Some(etag) = etag.as_deref().filter(|etag| is_current(&request, etag)) {
+        return not_modified_response(etag);
+    }
+    resource_response(&request, etag, file_name, provider.fetch(file_name))
+}
+
+/// Answer `request` with `resource`, tagged with `etag` or else a hash of its
+/// content: `304 Not Modified` if the page's cached copy carries the tag, and
+/// only the requested bytes for a `Range` request. A MIME type that isn't a
+/// valid header value fails with `500 Internal Server Error`.
+fn resource_response(
+    request: &Request<Vec<u8>>,
+    etag: Option<String>,
+    file_name: &str,
+    resource: std::io::Result<Resource>,
+) -> http::Response<Vec<u8>> {
+    match resource {
+        Ok(Resource { content, mime_type }) => {
+            #[cfg(target_os = "windows")]
+            let cors = "https://wry.render-bokeh".to_string();
+
+            #[cfg(not(target_os = "windows"))]
+            let cors = "wry://render-bokeh".to_string();
+
+            let Ok(mime_type) = http::HeaderValue::from_str(&mime_type) else {
+                return error_response(
+                    http::StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("{}: invalid MIME type {:?}", file_name, mime_type),
+                );
+            };
+            let etag = etag
+                .filter(|etag| http::HeaderValue::from_str(etag).is_ok())
+                .unwrap_or_else(|| format!("\"{}\"", sha256_hex(&content)));
+            if is_current(request, &etag) {
+                return not_modified_response(&etag);
+            }
+            let builder = http::Response::builder()
+                .header(http::header::CONTENT_TYPE, mime_type)
+                .header(http::header::ACCESS_CONTROL_ALLOW_ORIGIN, cors)
+                .header(http::header::CACHE_CONTROL, "no-cache")
+                .header(http::header::ETAG, &etag)
+                .header(http::header::ACCEPT_RANGES, "bytes");
+            let len = content.len();
+            match requested_range(request, len) {
+                None => builder.body(content),
+                Some(Some((start, end))) => builder
+                    .status(http::StatusCode::PARTIAL_CONTENT)
+                    .header(
+                        http::header::CONTENT_RANGE,
+                        format!("bytes {}-{}/{}", start, end, len),
+                    )
+                    .body(content[start..=end].to_vec()),
+                Some(None) => builder
+                    .status(http::StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(http::header::CONTENT_RANGE, format!("bytes */{}", len))
+                    .body(Vec::new()),
+            }
+            .unwrap()
+        }
+        Err(e) => {
+            let status = match e.kind() {
+                std::io::ErrorKind::NotFound => http::StatusCode::NOT_FOUND,
+                std::io::ErrorKind::PermissionDenied => http::StatusCode::FORBIDDEN,
+                _ => http::StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            error_response(status, format!("{}: {}", file_name, e))
+        }
+    }
+}
+
+/// Whether the `If-None-Match` header of `request` names `etag`.
+fn is_current(request: &Request<Vec<u8>>, etag: &str) -> bool {
+    request
+        .headers()
+        .get_all(http::header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+fn not_modified_response(etag: &str) -> http::Response<Vec<u8>> {
+    http::Response::builder()
+        .status(http::StatusCode::NOT_MODIFIED)
+        .header(http::header::CACHE_CONTROL, "no-cache")
+        .header(http::header::ETAG, etag)
+        .body(Vec::new())
+        .unwrap()
+}
+
+/// The inclusive byte range of a `len` bytes file that the `Range` header of
+/// `request` asks for: `None` without a valid one, `Some(None)` if it starts
+/// past the end or is an empty suffix. Only single ranges are served; the page
+/// never asks for several at once, and the whole file answers other headers.
+fn requested_range(request: &Request<Vec<u8>>, len: usize) -> Option<Option<(usize, usize)>> {
+    let (start, end) = request
+        .headers()
+        .get(http::header::RANGE)?
+        .to_str()
+        .ok()?
+        .trim()
+        .strip_prefix("bytes=")
+        .filter(|range| !range.contains(','))?
+        .split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    let last = len.saturating_sub(1);
+    let range = match (start.parse::<usize>(), end.parse::<usize>()) {
+        // `bytes=500-999`, clamped to the file.
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(last)),
+        // `bytes=500-`
+        (Ok(start), Err(_)) if end.is_empty() => (start, last),
+        // `bytes=-500`, the last 500 bytes; `bytes=-0` is unsatisfiable.
+        (Err(_), Ok(0)) if start.is_empty() => (len, last),
+        (Err(_), Ok(suffix)) if start.is_empty() => (len.saturating_sub(suffix), last),
+        _ => return None,
+    };
+    Some(Some(range).filter(|&(start, _)| start < len))
+}
+
+pub(crate) fn error_response(status: http::StatusCode, message: String) -> http::Response<Vec<u8>> {
+    http::Response::builder()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, "text/plain")
+        .body(message.into_bytes())
+        .unwrap()
+}
+
+/// An image posted by the page, see `postImage` in `render_bokeh.js`.
+pub struct ExportedImage {
+    content: ExportedContent,
+    width: u32,
+    height: u32,
+    timings: RenderTimings,
+    data_images: Vec<GlyphData>,
+    variant: Option<usize>,
+}
+
+impl ExportedImage {
+    /// The index in `RenderOptions::dpis` of an export downscaled from the
+    /// embedded one, see `render_bokeh_at_dpis`.
+    pub(crate) fn variant(&self) -> Option<usize> {
+        self.variant
+    }
+}
+
+/// How the page handed an image over.
+enum ExportedContent {
+    DataUrl(String),
+    /// Uploaded to `/capture`; `None` if the upload went missing.
+    Captured(Option<Vec<u8>>),
+}
+
+impl ExportedContent {
+    fn into_bytes(self) -> Result<Vec<u8>, BokehRenderError> {
+        match self {
+            ExportedContent::DataUrl(data_url) => decode_data_url(&data_url),
+            ExportedContent::Captured(bytes) => bytes.ok_or_else(|| {
+                BokehRenderError::Decode("the page's upload of the image is missing".to_string())
+            }),
+        }
+    }
+}
+
+/// The content of a base64 `data:` URL.
+pub(crate) fn decode_data_url(data_url: &str) -> Result<Vec<u8>, BokehRenderError> {
+    let (_, content) = data_url
+        .split_once(',')
+        .ok_or_else(|| BokehRenderError::Decode("not a data URL".to_string()))?;
+    base64::engine::general_purpose::STANDARD
+        .decode(content)
+        .map_err(|e| BokehRenderError::Decode(e.to_string()))
+}
+
+/// Decode an image exported by the page into a post-processed `RenderOutput`.
+pub(crate) fn image_output(
+    image: ExportedImage,
+    dpi: Dpi,
+    format: RenderFormat,
+    options: &RenderOptions,
+) -> Result<RenderOutput, BokehRenderError> {
+    let bytes = image.content.into_bytes()?;
+    if options.blank_retry_delay.is_some() && is_blank(&bytes) {
+        return Err(BokehRenderError::BlankRender);
+    }
+    let data_images = match options.data_image_format {
+        Some(data_image_format) => image
+            .data_images
+            .into_iter()
+            .map(|glyph| encode_data_image(glyph, data_image_format))
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    let output = RenderOutput {
+        bytes,
+        width: image.width,
+        height: image.height,
+        dpi,
+        format,
+        timings: image.timings,
+        data_images,
+        console: Vec::new(),
+        thumbnail: None,
+    };
+    post_process(output, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(range: Option<&str>) -> Request<Vec<u8>> {
+        let mut request = Request::builder().uri("wry://render-bokeh/bokeh.min.js");
+        if let Some(range) = range {
+            request = request.header(http::header::RANGE, range);
+        }
+        request.body(Vec::new()).unwrap()
+    }
+
+    fn script(mime_type: &str) -> std::io::Result<Resource> {
+        Ok(Resource {
+            content: b"console.log(1);".to_vec(),
+            mime_type: mime_type.to_string(),
+        })
+    }
+
+    #[test]
+    fn invalid_etag_falls_back_to_the_content_hash() {
+        let etag = Some("\"tag\"\nX-Injected: 1".to_string());
+        let response = resource_response(&get(None), etag, "a.js", script("text/javascript"));
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let content_hash = format!("\"{}\"", sha256_hex(b"console.log(1);"));
+        assert_eq!(response.headers()[http::header::ETAG], content_hash.as_str());
+    }
+
+    #[test]
+    fn invalid_mime_type_answers_500() {
+        let response = resource_response(&get(None), None, "a.js", script("text/\u{7}js"));
+        assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn requested_range_reads_single_ranges() {
+        let range = |header| requested_range(&get(Some(header)), 1000);
+        assert_eq!(range("bytes=0-499"), Some(Some((0, 499))));
+        assert_eq!(range(" bytes=500 - 999 "), Some(Some((500, 999))));
+        assert_eq!(range("bytes=900-"), Some(Some((900, 999))));
+        assert_eq!(range("bytes=-100"), Some(Some((900, 999))));
+        assert_eq!(range("bytes=500-5000"), Some(Some((500, 999))));
+        assert_eq!(range("bytes=-5000"), Some(Some((0, 999))));
+    }
+
+    #[test]
+    fn requested_range_rejects_unsatisfiable_ranges() {
+        let range = |header, len| requested_range(&get(Some(header)), len);
+        assert_eq!(range("bytes=1000-1100", 1000), Some(None));
+        assert_eq!(range("bytes=1000-", 1000), Some(None));
+        assert_eq!(range("bytes=-0", 1000), Some(None));
+        assert_eq!(range("bytes=0-", 0), Some(None));
+        assert_eq!(range("bytes=-10", 0), Some(None));
+    }
+
+    #[test]
+    fn requested_range_ignores_other_headers() {
+        assert_eq!(requested_range(&get(None), 1000), None);
+        for header in ["bytes=0-1,5-9", "bytes=9-5", "bytes=-", "items=0-9", "bytes=a-9"] {
+            assert_eq!(requested_range(&get(Some(header)), 1000), None, "{}", header);
+        }
+    }
+}
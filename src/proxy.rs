@@ -0,0 +1,208 @@
+//! Requests the page can't make itself: to hosts that need headers such as
+//! `Authorization` (`RenderOptions::request_headers`) or that don't allow the
+//! page's origin (`RenderOptions::proxy_hosts`). The renderer adds the headers
+//! to its own downloads, and the page's `fetch`/`XMLHttpRequest` calls and
+//! bundle `<script>` tags for those hosts go through `/proxy/<host>/<path>`.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use wry::http::{self, Request};
+
+use crate::options::RenderOptions;
+use crate::protocol::error_response;
+use crate::resources::{http_agent, sha256_hex, write_atomically};
+
+/// Request headers of the page passed on to the proxied host.
+const FORWARDED_HEADERS: [http::header::HeaderName; 2] =
+    [http::header::ACCEPT, http::header::CONTENT_TYPE];
+
+/// The configured headers for the host of `url`.
+pub(crate) fn headers_for<'a>(options: &'a RenderOptions, url: &str) -> &'a [(String, String)] {
+    url.parse::<http::Uri>()
+        .ok()
+        .and_then(|uri| {
+            options
+                .request_headers
+                .get(uri.authority()?.as_str())
+                .map(Vec::as_slice)
+        })
+        .unwrap_or_default()
+}
+
+fn is_proxied(options: &RenderOptions, host: &str) -> bool {
+    options.request_headers.contains_key(host) || options.proxy_hosts.iter().any(|h| h == host)
+}
+
+/// `url` as the page should load it: through the proxy for proxied hosts.
+pub(crate) fn page_url(options: &RenderOptions, url: &str) -> String {
+    let Ok(uri) = url.parse::<http::Uri>() else {
+        return url.to_string();
+    };
+    match (uri.scheme_str(), uri.authority(), uri.path_and_query()) {
+        (Some("https"), Some(host), Some(path)) if is_proxied(options, host.as_str()) => {
+            format!("/proxy/{}{}", host, path)
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// Routes the page's `fetch` and `XMLHttpRequest` calls to proxied hosts
+/// through the proxy. Empty when no host is proxied.
+pub(crate) fn proxy_as_head_html(options: &RenderOptions) -> String {
+    let hosts: Vec<&String> = options
+        .request_headers
+        .keys()
+        .chain(&options.proxy_hosts)
+        .collect();
+    if hosts.is_empty() {
+        return String::new();
+    }
+    format!(
+        "
+    <script type='text/javascript'>
+        (() => {{
+            const hosts = {};
+            const proxied = (url) => {{
+                const parsed = new URL(url, document.baseURI);
+                return parsed.protocol === 'https:' && hosts.includes(parsed.host)
+                    ? `/proxy/${{parsed.host}}${{parsed.pathname}}${{parsed.search}}`
+                    : url;
+            }};
+            const fetch = window.fetch;
+            window.fetch = (input, init) => input instanceof Request
+                ? fetch(new Request(proxied(input.url), input), init)
+                : fetch(proxied(String(input)), init);
+            const open = XMLHttpRequest.prototype.open;
+            XMLHttpRequest.prototype.open = function (method, url, ...rest) {{
+                return open.call(this, method, proxied(String(url)), ...rest);
+            }};
+        }})();
+    </script>
+    ",
+        serde_json::to_string(&hosts).unwrap()
+    )
+}
+
+/// A response as the page receives it, and as the cache stores it.
+struct Upstream {
+    status: u16,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+/// Forward `request` for `/proxy/<target>` to `https://<target>` with the
+/// configured headers of its host, passing the upstream status and body back.
+pub(crate) fn forward(
+    request: &Request<Vec<u8>>,
+    target: &str,
+    options: &RenderOptions,
+) -> http::Response<Vec<u8>> {
+    let (host, path) = target.split_once('/').unwrap_or((target, ""));
+    if !is_proxied(options, host) {
+        return error_response(
+            http::StatusCode::FORBIDDEN,
+            format!("{} is not a proxied host", host),
+        );
+    }
+
+    let mut url = format!("https://{}/{}", host, path);
+    if let Some(query) = request.uri().query() {
+        url.push('?');
+        url.push_str(query);
+    }
+    let cache_path = options
+        .proxy_cache_dir
+        .as_deref()
+        .map(|dir| cache_path(dir, request.method().as_str(), &url, request.body()));
+    let cached = cache_path.as_deref().and_then(read_cached);
+
+    let upstream = match cached {
+        Some(upstream) => upstream,
+        None => match fetch(request, &url, options.request_headers.get(host)) {
+            Ok(upstream) => {
+                if let Some(path) = &cache_path {
+                    if (200..300).contains(&upstream.status) {
+                        // A failed write only costs the next render a request.
+                        let _ = write_cached(path, &upstream);
+                    }
+                }
+                upstream
+            }
+            Err(message) => return error_response(http::StatusCode::BAD_GATEWAY, message),
+        },
+    };
+    http::Response::builder()
+        .status(upstream.status)
+        .header(http::header::CONTENT_TYPE, upstream.content_type)
+        .body(upstream.body)
+        .unwrap()
+}
+
+fn fetch(
+    request: &Request<Vec<u8>>,
+    url: &str,
+    headers: Option<&Vec<(String, String)>>,
+) -> Result<Upstream, String> {
+    let mut upstream = http_agent().request(request.method().as_str(), url);
+    for name in FORWARDED_HEADERS {
+        if let Some(value) = request.headers().get(&name).and_then(|v| v.to_str().ok()) {
+            upstream = upstream.set(name.as_str(), value);
+        }
+    }
+    for (name, value) in headers.into_iter().flatten() {
+        upstream = upstream.set(name, value);
+    }
+
+    let result = if request.body().is_empty() {
+        upstream.call()
+    } else {
+        upstream.send_bytes(request.body())
+    };
+    let response = match result {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(e) => return Err(format!("Failed to fetch {}: {}", url, e)),
+    };
+    let status = response.status();
+    let content_type = response.content_type().to_string();
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut body)
+        .map_err(|e| format!("Failed to read {}: {}", url, e))?;
+    Ok(Upstream {
+        status,
+        content_type,
+        body,
+    })
+}
+
+/// Where the response to `method url` with `body` is cached. The headers added
+/// by the proxy aren't part of the key, so rotating a token keeps the cache.
+fn cache_path(dir: &Path, method: &str, url: &str, body: &[u8]) -> PathBuf {
+    let mut key = format!("{} {}\n", method, url).into_bytes();
+    key.extend_from_slice(body);
+    dir.join(sha256_hex(&key))
+}
+
+fn read_cached(path: &Path) -> Option<Upstream> {
+    let content_type = std::fs::read_to_string(path.with_extension("content-type")).ok()?;
+    let body = std::fs::read(path).ok()?;
+    Some(Upstream {
+        status: 200,
+        content_type,
+        body,
+    })
+}
+
+fn write_cached(path: &Path, upstream: &Upstream) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // The body goes last: it's what marks the entry as complete.
+    write_atomically(
+        &path.with_extension("content-type"),
+        upstream.content_type.as_bytes(),
+    )?;
+    write_atomically(path, &upstream.body)
+}
@@ -0,0 +1,209 @@
+//! The Python module, built by maturin with the `python` feature.
+
+// pyo3 0.22's `#[pyfunction]` expansion trips this lint on `PyResult` returns.
+#![allow(clippy::useless_conversion)]
+
+use pyo3::exceptions::{PyOSError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::{bokeh_helpers, pdf};
+
+fn parse_resource(
+    resource: Option<[String; 2]>,
+) -> PyResult<Option<bokeh_helpers::BokehResource>> {
+    let resource = match resource {
+        Some(resource) => {
+            let variant = &resource[0];
+            let value = resource[1].clone();
+
+            if value.is_empty() {
+                return Err(PyValueError::new_err("Resource value cannot be empty"));
+            }
+
+            let resource = match variant.as_str() {
+                "cdn" => bokeh_helpers::BokehResource::cdn(value),
+                "local" => bokeh_helpers::BokehResource::local(value),
+                "archive" => bokeh_helpers::BokehResource::archive(value),
+                _ => {
+                    return Err(PyValueError::new_err(format!(
+                        "Invalid resource variant: {}",
+                        variant
+                    )))
+                }
+            };
+            Some(resource.map_err(|e| PyValueError::new_err(e.to_string()))?)
+        }
+        None => None,
+    };
+
+    Ok(resource)
+}
+
+fn parse_page_size(page_size: Option<(f64, f64)>) -> pdf::PageSize {
+    match page_size {
+        Some((width_mm, height_mm)) => pdf::PageSize::Custom {
+            width_mm,
+            height_mm,
+        },
+        None => pdf::PageSize::Fit,
+    }
+}
+
+fn parse_margins(margins: (f64, f64, f64, f64)) -> pdf::Margins {
+    pdf::Margins {
+        top: margins.0,
+        right: margins.1,
+        bottom: margins.2,
+        left: margins.3,
+    }
+}
+
+fn parse_format(
+    typ: &str,
+    page_size: Option<(f64, f64)>,
+    margins: (f64, f64, f64, f64),
+) -> bokeh_helpers::RenderFormat {
+    match typ {
+        "application/pdf" => bokeh_helpers::RenderFormat::Pdf {
+            page_size: parse_page_size(page_size),
+            margins: parse_margins(margins),
+        },
+        typ => bokeh_helpers::RenderFormat::Image(typ.to_string()),
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (
+    json_data,
+    dpi=300.0,
+    typ="image/png",
+    resource=None,
+    reduced_motion=false,
+    page_size=None,
+    margins=(0.0, 0.0, 0.0, 0.0),
+    debug=false,
+))]
+#[allow(clippy::too_many_arguments)]
+fn render_bokeh(
+    json_data: &str,
+    dpi: f64,
+    typ: &str,
+    resource: Option<[String; 2]>,
+    reduced_motion: bool,
+    page_size: Option<(f64, f64)>,
+    margins: (f64, f64, f64, f64),
+    debug: bool,
+) -> PyResult<String> {
+    let resource = parse_resource(resource)?;
+    let format = parse_format(typ, page_size, margins);
+    let options = bokeh_helpers::RenderOptions::default()
+        .reduced_motion(reduced_motion)
+        .debug(debug);
+
+    tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(bokeh_helpers::render_bokeh_in_webview(
+            json_data, dpi, format, resource, options,
+        ))
+        .map(|output| output.to_data_url())
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+}
+
+/// Like `render_bokeh`, returning the export's bytes rather than a data URL.
+/// Other Python threads keep running while the webview renders.
+#[pyfunction]
+#[pyo3(signature = (
+    json_data,
+    dpi=300.0,
+    typ="image/png",
+    resource=None,
+    reduced_motion=false,
+    page_size=None,
+    margins=(0.0, 0.0, 0.0, 0.0),
+))]
+#[allow(clippy::too_many_arguments)]
+fn render(
+    py: Python<'_>,
+    json_data: &str,
+    dpi: f64,
+    typ: &str,
+    resource: Option<[String; 2]>,
+    reduced_motion: bool,
+    page_size: Option<(f64, f64)>,
+    margins: (f64, f64, f64, f64),
+) -> PyResult<PyObject> {
+    let resource = parse_resource(resource)?;
+    let format = parse_format(typ, page_size, margins);
+    let options = bokeh_helpers::RenderOptions::default().reduced_motion(reduced_motion);
+
+    let output = py
+        .allow_threads(|| {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(bokeh_helpers::render_bokeh_in_webview(
+                    json_data, dpi, format, resource, options,
+                ))
+        })
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    Ok(PyBytes::new_bound(py, &output.bytes).into())
+}
+
+#[pyfunction]
+#[pyo3(signature = (json_data, resource=None))]
+fn export_bokeh_html(json_data: &str, resource: Option<[String; 2]>) -> PyResult<String> {
+    let resource = parse_resource(resource)?;
+
+    bokeh_helpers::export_bokeh_html(json_data, resource)
+        .map_err(|e| PyOSError::new_err(format!("Failed to read Bokeh resources: {}", e)))
+}
+
+#[pyfunction]
+#[pyo3(signature = (
+    json_items,
+    page_size=None,
+    margins=(0.0, 0.0, 0.0, 0.0),
+    columns=1,
+    rows=1,
+    resource=None,
+))]
+fn render_bokeh_report(
+    py: Python<'_>,
+    json_items: Vec<String>,
+    page_size: Option<(f64, f64)>,
+    margins: (f64, f64, f64, f64),
+    columns: u32,
+    rows: u32,
+    resource: Option<[String; 2]>,
+) -> PyResult<PyObject> {
+    let resource = parse_resource(resource)?;
+    let docs = json_items
+        .into_iter()
+        .map(|json_data| bokeh_helpers::RenderRequest {
+            resource: resource.clone(),
+            ..bokeh_helpers::RenderRequest::new(json_data)
+        })
+        .collect();
+    let layout = pdf::ReportLayout {
+        page_size: parse_page_size(page_size),
+        margins: parse_margins(margins),
+        columns,
+        rows,
+    };
+
+    let content = tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(bokeh_helpers::render_bokeh_report(docs, layout))
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    Ok(PyBytes::new_bound(py, &content).into())
+}
+
+/// A Python module implemented in Rust.
+#[pymodule]
+fn wry_bokeh_helper(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(render_bokeh, m)?)?;
+    m.add_function(wrap_pyfunction!(render, m)?)?;
+    m.add_function(wrap_pyfunction!(export_bokeh_html, m)?)?;
+    m.add_function(wrap_pyfunction!(render_bokeh_report, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,994 @@
+// Fail the render instead of leaving it waiting for an export that never comes.
+function reportError(error) {
+    window.ipc.postMessage(JSON.stringify({ type: 'error', message: String(error) }));
+}
+
+// Fail the render as `BokehRenderError::bokeh_missing` does.
+function bokehMissing() {
+    resourceLoadFailed(null, 'window.Bokeh is undefined once the page loaded');
+}
+
+// Fail the render, and return true, unless BokehJS has loaded in a release the
+// page works with, `pageOptions.min_bokeh_version` or later.
+function bokehUnusable() {
+    if (window.Bokeh === undefined) {
+        bokehMissing();
+        return true;
+    }
+    const version = String(window.Bokeh.version);
+    const [major, minor] = version.split('.').map((part) => parseInt(part, 10));
+    const [minMajor, minMinor] = pageOptions.min_bokeh_version;
+    if (major < minMajor || (major === minMajor && minor < minMinor)) {
+        window.ipc.postMessage(JSON.stringify({ type: 'unsupported_version', version }));
+        return true;
+    }
+    return false;
+}
+
+// Run the `pageOptions[name]` hook, if set, as a function of `args`. Async
+// hooks are waited for; console calls made meanwhile are attributed to the
+// hook (see `CONSOLE_SCRIPT`).
+function runHook(name, args) {
+    const source = pageOptions[name];
+    if (source === null) {
+        return Promise.resolve();
+    }
+    runningHooks.push(name);
+    return Promise.resolve()
+        .then(() => new Function(...Object.keys(args), source)(...Object.values(args)))
+        .finally(() => runningHooks.splice(runningHooks.indexOf(name), 1));
+}
+
+// Resolves once the default and fallback fonts in `pageOptions.fonts` have
+// loaded. Canvas text drawn while a font is still loading silently falls back to
+// another font and is never redrawn, so this runs before embedding;
+// `@font-face` fonts otherwise only load on first use.
+function fontsLoaded() {
+    const loads = pageOptions.fonts.map((font) => document.fonts.load(font));
+    return Promise.all(loads).then(() => document.fonts.ready);
+}
+
+// How long to wait for Google Maps tiles before exporting without them, so a
+// bad API key or a blocked network produces a capture instead of a hang.
+const GMAP_TILES_TIMEOUT = 15000;
+
+function* allViews(view) {
+    yield view;
+    for (const child of view.children?.() ?? view.child_views ?? []) {
+        yield* allViews(child);
+    }
+}
+
+// Google Maps loads asynchronously and draws its tiles into its own DOM under
+// the plot: resolve once every GMap plot under `view` has fired `tilesloaded`.
+function gmapTilesLoaded(view) {
+    const mapViews = [...allViews(view)].filter((v) => ['GMapPlot', 'GMap'].includes(v.model.type));
+    return Promise.all(mapViews.map((mapView) => new Promise((resolve) => {
+        const timeout = setTimeout(() => {
+            console.warn('Google Maps tiles did not load in time');
+            resolve();
+        }, GMAP_TILES_TIMEOUT);
+        const listen = () => {
+            if (mapView.map === undefined || window.google?.maps === undefined) {
+                setTimeout(listen, 50);
+                return;
+            }
+            window.google.maps.event.addListenerOnce(mapView.map, 'tilesloaded', () => {
+                clearTimeout(timeout);
+                resolve();
+            });
+        };
+        listen();
+    })));
+}
+
+// Whether the document has math text: `TeX`, `MathML` or `Ascii` models, or
+// strings with the `$$ ... $$`, `\[ ... \]` or `\( ... \)` delimiters Bokeh detects.
+function usesMath(doc) {
+    for (const model of doc.all_models) {
+        if (['TeX', 'MathML', 'Ascii'].includes(model.type)) {
+            return true;
+        }
+        for (const property of Object.values(model.properties ?? {})) {
+            try {
+                const value = property.get_value();
+                if (typeof value === 'string' && /\$\$|\\\[|\\\(/.test(value)) {
+                    return true;
+                }
+            } catch (e) {
+                // Unset properties throw; they hold no text.
+            }
+        }
+    }
+    return false;
+}
+
+// The document can report idle before MathJax has loaded and typeset its labels.
+// If it has math, wait until the MathJax provider has settled and every view has
+// finished, or export as is after `pageOptions.mathjax_timeout` ms.
+function mathTypeset(view) {
+    const doc = view.model.document;
+    if (doc === null || pageOptions.mathjax_timeout === 0 || !usesMath(doc)) {
+        return Promise.resolve();
+    }
+    let provider = null;
+    try {
+        provider = window.Bokeh.require('models/text/providers').default_provider;
+    } catch (e) {
+        console.warn('Could not find the BokehJS MathJax provider', e);
+    }
+    const deadline = performance.now() + pageOptions.mathjax_timeout;
+    return new Promise((resolve) => {
+        const poll = () => {
+            const loaded = provider === null || ['loaded', 'failed'].includes(provider.status);
+            const finished = [...allViews(view)].every((v) => v.has_finished?.() !== false);
+            if (loaded && finished) {
+                requestAnimationFrame(() => resolve());
+            } else if (performance.now() > deadline) {
+                console.warn('MathJax did not finish typesetting in time');
+                resolve();
+            } else {
+                setTimeout(poll, 50);
+            }
+        };
+        poll();
+    });
+}
+
+// Replace the API key of every GMap plot with `pageOptions.gmap_api_key`, so
+// documents can be serialized without one.
+function useGmapApiKey(node) {
+    if (pageOptions.gmap_api_key === null) {
+        return;
+    }
+    if (Array.isArray(node)) {
+        node.forEach(useGmapApiKey);
+    } else if (node !== null && typeof node === 'object') {
+        if (node.attributes !== undefined && ['GMapPlot', 'GMap'].includes(modelName(node))) {
+            const bytes = new TextEncoder().encode(pageOptions.gmap_api_key);
+            const data = bytesToBase64(bytes.buffer);
+            // Bokeh 2 serializes the key as a plain base64 string.
+            node.attributes.api_key = node.type === 'object' ? { type: 'bytes', data } : data;
+        }
+        Object.values(node).forEach(useGmapApiKey);
+    }
+}
+
+// How long a `selector` or `js_predicate` condition, or an `AjaxDataSource`,
+// may stay unmet before the render fails.
+const WAIT_UNTIL_TIMEOUT = 30000;
+
+// Completed `XMLHttpRequest`s by absolute URL. `AjaxDataSource` sends its first
+// request while the document is embedded, so counting starts with the page.
+const completedRequests = new Map();
+{
+    const open = XMLHttpRequest.prototype.open;
+    XMLHttpRequest.prototype.open = function (method, url, ...rest) {
+        const href = new URL(url, document.baseURI).href;
+        this.addEventListener('loadend', () => {
+            completedRequests.set(href, (completedRequests.get(href) ?? 0) + 1);
+        });
+        return open.call(this, method, url, ...rest);
+    };
+}
+
+// Resolve once every `AjaxDataSource` of the document has had
+// `pageOptions.ajax_polls` responses, failed ones included, and the document
+// has drawn the data of the last one.
+function ajaxDataLoaded(view) {
+    const sources = [...view.model.document.all_models].filter((model) => model.type === 'AjaxDataSource');
+    if (sources.length === 0 || pageOptions.ajax_polls === 0) {
+        return Promise.resolve();
+    }
+    const loaded = () => sources.every((source) => {
+        const href = new URL(source.data_url, document.baseURI).href;
+        return (completedRequests.get(href) ?? 0) >= pageOptions.ajax_polls;
+    });
+    return pollUntil(loaded, 'every AjaxDataSource has loaded')
+        .then(() => new Promise((resolve) => requestAnimationFrame(() => resolve())));
+}
+
+// Resolve once `test` returns (or resolves to) a truthy value, polling every 50ms.
+function pollUntil(test, description) {
+    const deadline = performance.now() + WAIT_UNTIL_TIMEOUT;
+    return new Promise((resolve, reject) => {
+        const poll = () => Promise.resolve(test()).then((done) => {
+            if (done) {
+                resolve();
+            } else if (performance.now() > deadline) {
+                reject(new Error(`Timed out waiting until ${description}`));
+            } else {
+                setTimeout(poll, 50);
+            }
+        }).catch(reject);
+        poll();
+    });
+}
+
+// The extra readiness condition of `pageOptions.wait_until`, for documents whose
+// `CustomJS` keeps changing glyphs after the document reports idle.
+function waitUntil(view) {
+    const condition = pageOptions.wait_until;
+    switch (condition?.type) {
+        case 'idle':
+            return new Promise((resolve) => setTimeout(resolve, condition.ms));
+        case 'selector':
+            return pollUntil(() => document.querySelector(condition.selector) !== null,
+                `${condition.selector} matches`);
+        case 'js_predicate': {
+            const predicate = new Function('view', `return (${condition.predicate});`);
+            return pollUntil(() => predicate(view), `${condition.predicate} holds`);
+        }
+        case 'double_animation_frame':
+            return new Promise((resolve) => requestAnimationFrame(() => requestAnimationFrame(() => resolve())));
+        default:
+            return Promise.resolve();
+    }
+}
+
+// Resolves after `count` animation frames, see `SettleStrategy`.
+function animationFrames(count) {
+    if (count <= 0) {
+        return Promise.resolve();
+    }
+    return new Promise((resolve) => requestAnimationFrame(() => resolve()))
+        .then(() => animationFrames(count - 1));
+}
+
+// Resolves once the document is idle and the browser has painted the result.
+// The page dispatches `bokeh-render-settled` on `window` at that point; exports
+// wait on this instead of capturing as soon as `embed_item` resolves.
+function renderSettled(view) {
+    const doc = view.model.document;
+    const idle = new Promise((resolve) => {
+        if (doc === null || doc.is_idle) {
+            resolve();
+        } else {
+            doc.idle.connect(() => resolve());
+        }
+    });
+    return idle
+        .then(() => ajaxDataLoaded(view))
+        .then(() => mathTypeset(view))
+        .then(() => gmapTilesLoaded(view))
+        .then(() => document.fonts.ready)
+        .then(() => waitUntil(view))
+        .then(() => animationFrames(pageOptions.settle.frames))
+        .then(() => new Promise((resolve) => setTimeout(resolve, pageOptions.settle.delay)))
+        .then(() => {
+            window.dispatchEvent(new CustomEvent('bokeh-render-settled', { detail: { view } }));
+        });
+}
+
+// The device pixel ratio `base` scaled to `dpi`, which may be fractional such as
+// 144.5. Rounding off the floating-point error keeps canvases whose size is a
+// whole number of pixels at this ratio, e.g. 1.4999999999999998 for 1.5, from
+// coming out a pixel short.
+function scaledPixelRatio(base, dpi) {
+    return Math.round(base * dpi / 96 * 1e9) / 1e9;
+}
+
+// The pixel size exports come out at: `pageOptions.output_size`, or
+// `pageOptions.physical_size` at the DPI of the last embed. Set by `useOutputSize`.
+let outputSize = null;
+
+// The class name of the serialized model `node`: Bokeh 3 writes models as
+// `{type: 'object', name, ...}`, Bokeh 2 as `{type: name, ...}`.
+function modelName(node) {
+    return node.type === 'object' ? node.name : node.type;
+}
+
+// The root of the `json_item` `data`: among `doc.roots` in Bokeh 3 documents,
+// among `doc.roots.references` in Bokeh 2 ones.
+function rootModel(data) {
+    const roots = data['doc']['roots'];
+    const models = Array.isArray(roots) ? roots : roots?.references ?? [];
+    return models.find((model) => model.id === data['root_id']);
+}
+
+// Lay the root out at the output size divided by the device pixel ratio, so
+// its export comes out at that many pixels (see `fitOutputSize`).
+function useOutputSize(data, dpi) {
+    outputSize = pageOptions.output_size ?? physicalSizeAt(dpi);
+    if (outputSize === null) {
+        return;
+    }
+    const root = rootModel(data);
+    if (root?.attributes === undefined) {
+        return;
+    }
+    const [width, height] = outputSize;
+    root.attributes.width = Math.round(width / window.devicePixelRatio);
+    root.attributes.height = Math.round(height / window.devicePixelRatio);
+    root.attributes.sizing_mode = 'fixed';
+    if (pageOptions.native_resolution && root.attributes.width > 0) {
+        // Draw the rounded layout at exactly the output width, rather than
+        // scaling the export to it in `fitOutputSize`.
+        window.devicePixelRatio = width / root.attributes.width;
+    }
+}
+
+// `value` of a font size or line width property times `scale`: numbers, sizes
+// in `px` or `pt`, and `{ value }` specs. Anything else, such as relative sizes
+// or sizes taken from a data field, is left as it is by returning `undefined`.
+function scaledValue(value, scale) {
+    if (typeof value === 'number') {
+        return value * scale;
+    }
+    if (typeof value === 'string') {
+        const size = /^(\d*\.?\d+)(px|pt)$/.exec(value);
+        return size === null ? undefined : `${parseFloat(size[1]) * scale}${size[2]}`;
+    }
+    if (value?.value !== undefined) {
+        const scaled = scaledValue(value.value, scale);
+        return scaled === undefined ? undefined : { ...value, value: scaled };
+    }
+    return undefined;
+}
+
+// The pixel size of `pageOptions.physical_size` at `dpi`, or null without one.
+function physicalSizeAt(dpi) {
+    return pageOptions.physical_size?.map((mm) => Math.max(1, Math.round(mm / 25.4 * dpi)))
+        ?? null;
+}
+
+// Scale every font size and line width in `doc` by `pageOptions.presentation_scale`,
+// defaults included, so text stays readable in small exports and doesn't dwarf
+// the plot in large ones. The document lays itself out again before settling.
+function usePresentationScale(doc) {
+    const scale = pageOptions.presentation_scale;
+    if (scale === null) {
+        return;
+    }
+    for (const model of doc.all_models) {
+        const changes = {};
+        for (const property of Object.values(model.properties)) {
+            if (/(font_size|line_width)$/.test(property.attr)) {
+                const scaled = scaledValue(property.get_value(), scale);
+                if (scaled !== undefined) {
+                    changes[property.attr] = scaled;
+                }
+            }
+        }
+        model.setv(changes);
+    }
+}
+
+// Scale `canvas` to exactly the output size, making up for the layout rounding
+// to whole CSS pixels.
+function fitOutputSize(canvas) {
+    if (outputSize === null) {
+        return canvas;
+    }
+    return resizedCanvas(canvas, outputSize);
+}
+
+// `canvas` drawn at `size`, or `canvas` itself if it has that size already.
+function resizedCanvas(canvas, [width, height]) {
+    if (canvas.width === width && canvas.height === height) {
+        return canvas;
+    }
+    const resized = document.createElement('canvas');
+    resized.width = width;
+    resized.height = height;
+    const ctx = resized.getContext('2d');
+    ctx.imageSmoothingQuality = 'high';
+    ctx.drawImage(canvas, 0, 0, width, height);
+    return resized;
+}
+
+// `canvas` encoded as `typ`: as a data URL, or with `binary` as a `Blob` for
+// `postImage` to upload, in which case the result is a promise.
+function encodeCanvas(canvas, typ, binary) {
+    const size = { width: canvas.width, height: canvas.height };
+    if (!binary) {
+        return { dataURL: canvas.toDataURL(typ, 1.0), ...size };
+    }
+    return new Promise((resolve) => canvas.toBlob(resolve, typ, 1.0))
+        .then((blob) => ({ blob, ...size }));
+}
+
+function blobToDataURL(blob) {
+    return new Promise((resolve, reject) => {
+        const reader = new FileReader();
+        reader.onload = () => resolve(reader.result);
+        reader.onerror = () => reject(reader.error);
+        reader.readAsDataURL(blob);
+    });
+}
+
+// `canvas` flattened onto a white background.
+function whiteBackground(canvas) {
+    const ctx = canvas.getContext('2d');
+    ctx.globalCompositeOperation = 'destination-over';
+    ctx.fillStyle = '#ffffff';
+    ctx.fillRect(0, 0, canvas.width, canvas.height);
+    return canvas;
+}
+
+// The view's canvas at the output size, flattened onto a white background.
+function flattenedCanvas(view) {
+    const canvas = whiteBackground(fitOutputSize(view.export().canvas));
+    // A Bokeh server page has no `#root`, see `snapshotServerSession`.
+    const container = document.getElementById('root');
+    if (container !== null) {
+        container.style.width = canvas.width + 'px';
+        container.style.height = canvas.height + 'px';
+    }
+    return canvas;
+}
+
+// Flatten the view and encode it, see `encodeCanvas`; exports are binary with
+// `pageOptions.binary_transfer` unless `binary` is false.
+function exportView(view, typ, binary = pageOptions.binary_transfer) {
+    return encodeCanvas(flattenedCanvas(view), typ, binary);
+}
+
+// Durations in milliseconds reported with every export. `sent_at` is wall-clock
+// time so the event loop can measure how long the message took to arrive.
+function pageTimings(bokehLoad, embed, exported) {
+    return {
+        bokeh_load: bokehLoad,
+        embed: embed,
+        export: exported,
+        sent_at: performance.timeOrigin + performance.now(),
+    };
+}
+
+function bytesToBase64(buffer) {
+    const bytes = new Uint8Array(buffer);
+    let binary = '';
+    for (let i = 0; i < bytes.length; i += 0x8000) {
+        binary += String.fromCharCode.apply(null, bytes.subarray(i, i + 0x8000));
+    }
+    return btoa(binary);
+}
+
+// Full-precision values of every `Image` glyph in the view's document, since
+// the exported canvas only keeps their 8-bit colormapped rendering.
+function imageGlyphData(view) {
+    const images = [];
+    for (const model of view.model.document.all_models) {
+        if (model.type !== 'GlyphRenderer' || model.glyph.type !== 'Image') {
+            continue;
+        }
+        const field = model.glyph.image.field;
+        if (field === undefined) {
+            continue;
+        }
+        const mapper = model.glyph.color_mapper;
+        for (const image of model.data_source.data[field]) {
+            const [height, width] = image.shape ?? [image.length, image[0].length];
+            const values = Float64Array.from(ArrayBuffer.isView(image) ? image : image.flat());
+            images.push({
+                name: model.name,
+                width,
+                height,
+                low: mapper?.low ?? null,
+                high: mapper?.high ?? null,
+                values: bytesToBase64(values.buffer),
+            });
+        }
+    }
+    return images;
+}
+
+// Report a milestone of the render in progress, see `renderer::RenderEvent`.
+function postProgress(stage) {
+    window.ipc.postMessage(JSON.stringify({ type: 'progress', stage }));
+}
+
+// Post an export over IPC. IPC only takes strings, so a binary export is
+// uploaded to `/capture` first; if the webview refuses the upload it is posted
+// as a data URL after all.
+// `variant` is the index in `RenderOptions::dpis` of a downscaled export.
+function postImage(view, image, timings, variant = null) {
+    const post = (dataURL) => window.ipc.postMessage(JSON.stringify({
+        type: 'image',
+        data_url: dataURL,
+        width: image.width,
+        height: image.height,
+        timings,
+        data_images: pageOptions.data_images ? imageGlyphData(view) : [],
+        variant,
+    }));
+    if (variant === null) {
+        postProgress('transfer_started');
+    }
+    if (image.blob === undefined) {
+        return post(image.dataURL);
+    }
+    if (image.blob === null) {
+        return reportError(new Error('The export could not be encoded'));
+    }
+    return fetch('/capture', { method: 'POST', body: image.blob })
+        .then((response) => response.ok ? post(null) : blobToDataURL(image.blob).then(post));
+}
+
+// Deterministic mode: reseed `Math.random` and the BokehJS generator used by
+// `Jitter` from `pageOptions.random_seed` and the figure's root id before each
+// embed, so the same figure always draws the same jitter whatever else is rendered.
+function seedRandom(key) {
+    if (pageOptions.random_seed === null) {
+        return;
+    }
+    let state = 0x811c9dc5;
+    for (const char of `${pageOptions.random_seed}:${key}`) {
+        state = Math.imul(state ^ char.charCodeAt(0), 0x01000193) >>> 0;
+    }
+    // mulberry32
+    Math.random = () => {
+        state = (state + 0x6d2b79f5) >>> 0;
+        let t = state;
+        t = Math.imul(t ^ (t >>> 15), t | 1);
+        t ^= t + Math.imul(t ^ (t >>> 7), t | 61);
+        return ((t ^ (t >>> 14)) >>> 0) / 4294967296;
+    };
+    try {
+        // Park-Miller generator seeded from `Date.now()` when BokehJS loads.
+        window.Bokeh.require('core/util/random').random._seed = (state % 2147483646) + 1;
+    } catch (e) {
+        console.warn('Could not seed the BokehJS random generator', e);
+    }
+}
+
+// The SVG backend's export of the view, loaded as an image at its CSS size.
+function svgImage(view) {
+    const svg = view.export('svg').get_serialized_svg(true);
+    return new Promise((resolve, reject) => {
+        const image = new Image();
+        image.onload = () => resolve(image);
+        image.onerror = () => reject(new Error('The SVG export could not be rasterized'));
+        image.src = URL.createObjectURL(new Blob([svg], { type: 'image/svg+xml' }));
+    });
+}
+
+// Rasterize what the SVG backend draws, at the same scale as `exportView`, so
+// the two backends can be compared.
+function exportSvgView(view, typ) {
+    return svgImage(view).then((image) => {
+        const canvas = document.createElement('canvas');
+        canvas.width = Math.round(image.width * window.devicePixelRatio);
+        canvas.height = Math.round(image.height * window.devicePixelRatio);
+        const ctx = canvas.getContext('2d');
+        ctx.fillStyle = '#ffffff';
+        ctx.fillRect(0, 0, canvas.width, canvas.height);
+        ctx.drawImage(image, 0, 0, canvas.width, canvas.height);
+        const fitted = fitOutputSize(canvas);
+        return encodeCanvas(fitted, typ, pageOptions.binary_transfer);
+    });
+}
+
+// Post the SVG backend's export in `pageOptions.tile_size` square tiles for the
+// renderer to stitch (see `tiles.rs`), since one canvas for the whole view at
+// poster resolution exceeds the browser's size limit. Only the last tile carries
+// timings, from `timings()`.
+function postTiles(view, timings) {
+    return svgImage(view).then((image) => {
+        const ratio = window.devicePixelRatio;
+        const width = Math.round(image.width * ratio);
+        const height = Math.round(image.height * ratio);
+        const size = pageOptions.tile_size;
+        const canvas = document.createElement('canvas');
+        for (let y = 0; y < height; y += size) {
+            for (let x = 0; x < width; x += size) {
+                canvas.width = Math.min(size, width - x);
+                canvas.height = Math.min(size, height - y);
+                const ctx = canvas.getContext('2d');
+                ctx.fillStyle = '#ffffff';
+                ctx.fillRect(0, 0, canvas.width, canvas.height);
+                ctx.drawImage(image, x / ratio, y / ratio, canvas.width / ratio, canvas.height / ratio,
+                    0, 0, canvas.width, canvas.height);
+                const last = x + size >= width && y + size >= height;
+                window.ipc.postMessage(JSON.stringify({
+                    type: 'tile',
+                    x,
+                    y,
+                    width,
+                    height,
+                    data_url: canvas.toDataURL('image/png'),
+                    timings: last ? timings() : null,
+                }));
+            }
+        }
+    });
+}
+
+// Post `canvas`, exported at `dpi`, downscaled to each of `variants`, the
+// `[index, dpi]` of the other `RenderOptions::dpis`, and then itself, one after
+// the other so each upload to `/capture` is taken before the next.
+function postVariants(view, canvas, dpi, variants, typ, timings) {
+    const binary = pageOptions.binary_transfer;
+    const posted = variants.reduce((previous, [index, variantDpi]) => previous.then(() => {
+        const size = physicalSizeAt(variantDpi) ?? [canvas.width, canvas.height]
+            .map((px) => Math.max(1, Math.round(px * variantDpi / dpi)));
+        return Promise.resolve(encodeCanvas(resizedCanvas(canvas, size), typ, binary))
+            .then((image) => postImage(view, image, timings(), index));
+    }), Promise.resolve());
+    return posted
+        .then(() => encodeCanvas(canvas, typ, binary))
+        .then((image) => postImage(view, image, timings()));
+}
+
+function postPreview(image, dpi, typ, timings) {
+    window.ipc.postMessage(JSON.stringify({
+        type: 'preview',
+        data_url: image.dataURL,
+        width: image.width,
+        height: image.height,
+        dpi,
+        typ,
+        timings,
+    }));
+}
+
+// Root views embedded since the page was loaded or last reset, with the
+// `ViewManager` of each, so a `BokehRenderer` can render one document after
+// another on the same page (see `resetPage`).
+const embeddedViews = [];
+
+// When the current document's page was ready: the navigation, or the last
+// `resetPage`. Exports report the time until their embed began as `bokeh_load`.
+let pageStart = 0;
+
+const initialDevicePixelRatio = window.devicePixelRatio;
+
+function trackView(viewManager, view) {
+    embeddedViews.push({ viewManager, view });
+    return view;
+}
+
+function removeView(view) {
+    embeddedViews.splice(embeddedViews.findIndex((embedded) => embedded.view === view), 1);
+    view.remove();
+}
+
+// Remove the view of a document embedded for an intermediate export, before
+// the document is embedded again.
+function discardView(view) {
+    removeView(view);
+    const container = document.getElementById('root');
+    container.replaceChildren();
+    container.style.width = '';
+    container.style.height = '';
+}
+
+// Clear the page for the next document without reloading Bokeh: destroy the
+// views of the previous documents, let BokehJS forget them and undo what
+// embedding them changed on the page.
+function resetPage() {
+    for (const { viewManager, view } of embeddedViews.splice(0)) {
+        // `ViewManager.destroy` is missing from older BokehJS releases.
+        if (typeof viewManager?.destroy === 'function') {
+            viewManager.destroy();
+        } else {
+            view.remove();
+        }
+    }
+    window.Bokeh?.documents?.splice(0);
+    const container = document.getElementById('root');
+    container.replaceChildren();
+    container.style.width = '';
+    container.style.height = '';
+    window.devicePixelRatio = initialDevicePixelRatio;
+    completedRequests.clear();
+    outputSize = null;
+    sessionView = null;
+    pageStart = performance.now();
+}
+
+// `backend` is 'canvas', 'svg' to draw plots with the SVG backend and rasterize
+// the result (see `diagnostics::compare_backends`), or 'tiled' to draw them with
+// the SVG backend and post the result in tiles (see `postTiles`). Canvas exports
+// are also posted downscaled to the `[index, dpi]` of each of `variants`, see
+// `render_bokeh_at_dpis`.
+function renderBokeh(json, dpi, typ, backend = 'canvas', variants = []) {
+    const rootId = JSON.parse(json)['root_id'];
+    if (bokehUnusable()) {
+        return;
+    }
+    postProgress('resources_loaded');
+    let devicePixelRatioBase = window.devicePixelRatio;
+    const container = document.getElementById('root');
+    const embedStart = performance.now();
+    const bokehLoad = embedStart - pageStart;
+    // Embed a fresh copy of the document at `dpi`, resolving to its settled root
+    // view. Hooks may modify `item`, so every embed parses its own.
+    const embed = (dpi) => {
+        const data = JSON.parse(json);
+        window.devicePixelRatio = scaledPixelRatio(devicePixelRatioBase, dpi);
+        return fontsLoaded().then(() => runHook('before_render_js', { item: data })).then(() => {
+            seedRandom(rootId);
+            useGmapApiKey(data['doc']['roots']);
+            useHidpi(data['doc']['roots']);
+            useOutputSize(data, dpi);
+            if (backend === 'svg' || backend === 'tiled') {
+                useSvgBackend(data['doc']['roots']);
+            }
+            return window.Bokeh.embed.embed_item(data, container);
+        }).then((viewManager) => {
+            const view = trackView(viewManager, viewManager.get_by_id(rootId));
+            usePresentationScale(view.model.document);
+            return runHook('after_render_js', { view, doc: view.model.document })
+                .then(() => renderSettled(view))
+                .then(() => view);
+        });
+    };
+    // A quick low-resolution export first, see `RenderOptions::preview_dpi`.
+    const preview = pageOptions.preview_dpi !== null && backend === 'canvas'
+        && pageOptions.output_size === null;
+    // With `pageOptions.native_resolution` each of `variants` is embedded and
+    // drawn at its own DPI first, instead of downscaled from the export at `dpi`.
+    const native = pageOptions.native_resolution;
+    const variantsPosted = (native ? variants : []).reduce((previous, [index, variantDpi]) =>
+        previous.then(() => embed(variantDpi)).then((view) => {
+            const exportStart = performance.now();
+            return Promise.resolve(exportView(view, typ)).then((image) => {
+                const exportEnd = performance.now();
+                const timings =
+                    pageTimings(bokehLoad, exportStart - embedStart, exportEnd - exportStart);
+                return postImage(view, image, timings, index);
+            }).then(() => discardView(view));
+        }), Promise.resolve());
+    const embedded = variantsPosted.then(() => !preview ? embed(dpi)
+        : embed(pageOptions.preview_dpi).then((view) => {
+            const exportStart = performance.now();
+            const image = exportView(view, typ, false);
+            const exportEnd = performance.now();
+            postPreview(image, pageOptions.preview_dpi, typ,
+                pageTimings(bokehLoad, exportStart - embedStart, exportEnd - exportStart));
+            discardView(view);
+            return embed(dpi);
+        }));
+    embedded.then((view) => {
+        postProgress('embedded');
+        const exportStart = performance.now();
+        if (backend === 'tiled') {
+            return postTiles(view, () => {
+                window.devicePixelRatio = devicePixelRatioBase;
+                const exportEnd = performance.now();
+                return pageTimings(bokehLoad, exportStart - embedStart, exportEnd - exportStart);
+            });
+        }
+        if (variants.length > 0 && !native) {
+            const canvas = flattenedCanvas(view);
+            window.devicePixelRatio = devicePixelRatioBase;
+            return postVariants(view, canvas, dpi, variants, typ, () => {
+                const exportEnd = performance.now();
+                return pageTimings(bokehLoad, exportStart - embedStart, exportEnd - exportStart);
+            });
+        }
+        const exported = backend === 'svg' ? exportSvgView(view, typ) : exportView(view, typ);
+        return Promise.resolve(exported).then((image) => {
+            const exportEnd = performance.now();
+            window.devicePixelRatio = devicePixelRatioBase;
+            postProgress('exported');
+            const timings = pageTimings(bokehLoad, exportStart - embedStart, exportEnd - exportStart);
+            return postImage(view, image, timings);
+        });
+    }).catch(reportError);
+}
+
+// Root view of the document embedded by `loadBokeh`, for scripts that drive
+// an interactive session before taking snapshots.
+let sessionView = null;
+
+function loadBokeh(json, dpi) {
+    const data = JSON.parse(json);
+    const rootId = data['root_id'];
+    if (bokehUnusable()) {
+        return;
+    }
+    window.devicePixelRatio = scaledPixelRatio(window.devicePixelRatio, dpi);
+    const container = document.getElementById('root');
+    fontsLoaded().then(() => runHook('before_render_js', { item: data })).then(() => {
+        seedRandom(rootId);
+        useGmapApiKey(data['doc']['roots']);
+        useHidpi(data['doc']['roots']);
+        useOutputSize(data, dpi);
+        return window.Bokeh.embed.embed_item(data, container);
+    }).then((viewManager) => {
+        sessionView = trackView(viewManager, viewManager.get_by_id(rootId));
+        usePresentationScale(sessionView.model.document);
+        return runHook('after_render_js', { view: sessionView, doc: sessionView.model.document });
+    }).then(() => {
+        window.ipc.postMessage(JSON.stringify({ type: 'loaded' }));
+    }).catch(reportError);
+}
+
+function snapshotBokeh(typ) {
+    const settleStart = performance.now();
+    renderSettled(sessionView).then(() => {
+        const exportStart = performance.now();
+        return Promise.resolve(exportView(sessionView, typ)).then((image) => {
+            const exportEnd = performance.now();
+            const timings = pageTimings(0, exportStart - settleStart, exportEnd - exportStart);
+            return postImage(sessionView, image, timings);
+        });
+    }).catch(reportError);
+}
+
+// The view of the first root of the document a Bokeh server session renders,
+// once BokehJS has built it. `Bokeh.index` is a `ViewManager` in Bokeh 3 and a
+// plain object of root views by id in Bokeh 2.
+function serverRootView() {
+    const root = window.Bokeh?.documents?.[0]?.roots()[0];
+    const index = window.Bokeh?.index;
+    if (root === undefined || index === undefined) {
+        return undefined;
+    }
+    return typeof index.get_by_id === 'function' ? index.get_by_id(root.id) : index[root.id];
+}
+
+// Export the first root of the Bokeh server session the page connects to, once
+// it has rendered and settled (see `render_bokeh_server_session`). Runs ahead
+// of the page's own scripts, so the BokehJS the server sends draws at `dpi`.
+function snapshotServerSession(dpi, typ) {
+    window.devicePixelRatio = scaledPixelRatio(window.devicePixelRatio, dpi);
+    window.addEventListener('load', () => {
+        if (bokehUnusable()) {
+            return;
+        }
+        const sessionStart = performance.now();
+        pollUntil(() => serverRootView() !== undefined, 'the server session has rendered')
+            .then(() => renderSettled(serverRootView()))
+            .then(() => {
+                const view = serverRootView();
+                const exportStart = performance.now();
+                return Promise.resolve(exportView(view, typ)).then((image) => {
+                    const exportEnd = performance.now();
+                    const timings = pageTimings(
+                        sessionStart, exportStart - sessionStart, exportEnd - exportStart);
+                    return postImage(view, image, timings);
+                });
+            }).catch(reportError);
+    });
+}
+
+// Set `attribute` of every plot in the document `node`, `doc.roots` of either
+// Bokeh 3 or Bokeh 2 documents.
+function setPlotAttribute(node, attribute, value) {
+    if (Array.isArray(node)) {
+        node.forEach((item) => setPlotAttribute(item, attribute, value));
+    } else if (node !== null && typeof node === 'object') {
+        if (node.attributes !== undefined
+            && ['Plot', 'Figure', 'GMapPlot', 'GMap'].includes(modelName(node))) {
+            node.attributes[attribute] = value;
+        }
+        Object.values(node).forEach((item) => setPlotAttribute(item, attribute, value));
+    }
+}
+
+// Printing keeps the DOM vector only if plots draw to SVG instead of a canvas.
+function useSvgBackend(node) {
+    setPlotAttribute(node, 'output_backend', 'svg');
+}
+
+// With `pageOptions.native_resolution`, plots saved with `hidpi=False` still
+// draw their canvas at the device pixel ratio rather than at one pixel per CSS
+// pixel, which the export would then be scaled up from.
+function useHidpi(node) {
+    if (pageOptions.native_resolution) {
+        setPlotAttribute(node, 'hidpi', true);
+    }
+}
+
+// Lay the documents out in a `columns` x `rows` grid per printed page. Cells are
+// sized to `pageContentSize` (CSS px) or, when it is null, to the largest plot;
+// plots that don't fit their cell are scaled down, never up.
+function printReport(jsons, columns, rows, pageContentSize, padding) {
+    if (bokehUnusable()) {
+        return;
+    }
+    postProgress('resources_loaded');
+    document.body.style.padding = padding;
+    const container = document.getElementById('root');
+    const embedStart = performance.now();
+    const perPage = columns * rows;
+    const pages = [];
+    fontsLoaded().then(() => Promise.all(jsons.map((json, i) => {
+        if (i % perPage === 0) {
+            const page = document.createElement('div');
+            page.style.display = 'grid';
+            page.style.gridTemplateColumns = `repeat(${columns}, max-content)`;
+            page.style.breakAfter = 'page';
+            container.appendChild(page);
+            pages.push(page);
+        }
+        const cell = document.createElement('div');
+        cell.style.overflow = 'hidden';
+        const inner = document.createElement('div');
+        inner.style.transformOrigin = 'top left';
+        cell.appendChild(inner);
+        pages[pages.length - 1].appendChild(cell);
+
+        const data = JSON.parse(json);
+        return runHook('before_render_js', { item: data }).then(() => {
+            useSvgBackend(data['doc']['roots']);
+            seedRandom(data['root_id']);
+            useGmapApiKey(data['doc']['roots']);
+            return window.Bokeh.embed.embed_item(data, inner);
+        }).then((viewManager) => {
+            const view = viewManager.get_by_id(data['root_id']);
+            return runHook('after_render_js', { view, doc: view.model.document })
+                .then(() => renderSettled(view))
+                .then(() => ({ cell, inner, view }));
+        });
+    }))).then((items) => {
+        postProgress('embedded');
+        const rects = items.map(({ view }) => view.el.getBoundingClientRect());
+        const cellWidth = pageContentSize === null
+            ? Math.max(...rects.map((rect) => rect.width))
+            : pageContentSize[0] / columns;
+        const cellHeight = pageContentSize === null
+            ? Math.max(...rects.map((rect) => rect.height))
+            : pageContentSize[1] / rows;
+        items.forEach(({ cell, inner }, i) => {
+            const scale = Math.min(1, cellWidth / rects[i].width, cellHeight / rects[i].height);
+            cell.style.width = cellWidth + 'px';
+            cell.style.height = cellHeight + 'px';
+            inner.style.transform = `scale(${scale})`;
+        });
+        pages[pages.length - 1].style.breakAfter = 'auto';
+        window.ipc.postMessage(JSON.stringify({
+            type: 'printable',
+            width: cellWidth * columns,
+            height: cellHeight * rows,
+            timings: pageTimings(embedStart, performance.now() - embedStart, 0),
+        }));
+    }).catch(reportError);
+}
+
+function printBokeh(json, padding) {
+    printReport([json], 1, 1, null, padding);
+}
+
+// Exercise each BokehJS API the functions above rely on and report every one
+// separately, so a new BokehJS release shows exactly which of them it broke.
+async function probeBokehApi(json) {
+    const checks = [];
+    const check = async (name, probe) => {
+        try {
+            const result = await probe();
+            checks.push({ name, ok: result === true, error: result === true ? null : `returned ${result}` });
+        } catch (e) {
+            checks.push({ name, ok: false, error: String(e) });
+        }
+    };
+    const data = JSON.parse(json);
+    let view = null;
+    await check('Bokeh.embed.embed_item', async () => {
+        const viewManager = await window.Bokeh.embed.embed_item(data, document.getElementById('root'));
+        view = viewManager.get_by_id(data['root_id']);
+        return view !== undefined;
+    });
+    await check('Document.idle', () => typeof view.model.document.idle.connect === 'function');
+    await check('Document.is_idle', () => typeof view.model.document.is_idle === 'boolean');
+    await check('Document.all_models', () => Symbol.iterator in Object(view.model.document.all_models));
+    await check('View.export', () => view.export().canvas instanceof HTMLCanvasElement);
+    await check('View.el', () => view.el instanceof Element);
+    await check('core/util/random', () => '_seed' in window.Bokeh.require('core/util/random').random);
+    window.ipc.postMessage(JSON.stringify({ type: 'api_checks', checks }));
+}
+
+// Draw `json`, a chart of the page's `ChartEngine`, with the engine's
+// `renderChart(container, spec, pixelRatio)` at `dpi` and post its export,
+// flattened like a Bokeh export (see `engine::render_chart_in_webview`).
+function exportChart(json, dpi, typ) {
+    const container = document.getElementById('root');
+    const renderStart = performance.now();
+    const pixelRatio = scaledPixelRatio(window.devicePixelRatio, dpi);
+    outputSize = pageOptions.output_size ?? physicalSizeAt(dpi);
+    const rendered = fontsLoaded().then(() => renderChart(container, JSON.parse(json), pixelRatio));
+    rendered.then((canvas) => {
+        const exportStart = performance.now();
+        const flattened = whiteBackground(fitOutputSize(canvas));
+        return Promise.resolve(encodeCanvas(flattened, typ, pageOptions.binary_transfer))
+            .then((image) => {
+                const exportEnd = performance.now();
+                const timings = pageTimings(
+                    renderStart - pageStart, exportStart - renderStart, exportEnd - exportStart);
+                return postImage(null, image, timings);
+            });
+    }).catch(reportError);
+}
@@ -0,0 +1,985 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use futures_core::Stream;
+use serde::Deserialize;
+use tao::{
+    dpi::LogicalSize,
+    event::Event,
+    event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy},
+    platform::run_return::EventLoopExtRunReturn,
+    window::Window,
+};
+use tokio::sync::{mpsc, oneshot};
+use wry::{WebView, WebViewBuilder};
+
+#[cfg(target_os = "linux")]
+use tao::platform::unix::EventLoopBuilderExtUnix;
+#[cfg(target_os = "windows")]
+use tao::platform::windows::EventLoopBuilderExtWindows;
+#[cfg(target_os = "windows")]
+use wry::WebContext;
+
+use crate::bokeh_helpers::{
+    apply_browser_args, js_string, render_call, render_webview_builder, render_window_builder,
+    PendingPdf,
+};
+use crate::cache::{render_key, MemoryCache};
+use crate::diagnostics::DocumentStats;
+use crate::document::embeddable_json;
+use crate::offline::{check_offline_document, check_offline_resources};
+use crate::options::{document_viewport, Dpi, RenderFormat, RenderOptions};
+use crate::output::{BokehRenderError, ConsoleMessage, RenderOutput, RenderTimings};
+use crate::process::watch_web_process;
+use crate::protocol::{image_output, ExportedImage, UserEvent};
+use crate::resources::BokehResource;
+use crate::runtime::webview_error;
+use crate::version::BokehVersion;
+
+/// The page URL the renderer navigates to when the current page can't be reused.
+const RENDER_PAGE_URL: &str = "wry://render-bokeh";
+
+/// An image exported by `BokehRenderer::snapshot`.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub name: String,
+    pub output: RenderOutput,
+}
+
+/// A milestone of a render, from `BokehRenderer::render_with_events`. PDF
+/// renders skip `TransferStarted`, and renders answered from the memory cache
+/// only report `Done`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderEvent {
+    /// The page has its Bokeh bundles and starts embedding the document.
+    ResourcesLoaded,
+    /// The document is embedded and has settled.
+    Embedded,
+    /// The export is drawn and encoded, or the PDF printed.
+    Exported,
+    /// The export is on its way from the page to the renderer.
+    TransferStarted,
+    /// The render finished, successfully or not. Always the last event.
+    Done,
+}
+
+/// The events of one render, a `Stream` that ends after `RenderEvent::Done`.
+pub struct RenderEvents {
+    receiver: mpsc::UnboundedReceiver<RenderEvent>,
+}
+
+impl RenderEvents {
+    /// The next event, or `None` after `RenderEvent::Done`.
+    pub async fn next(&mut self) -> Option<RenderEvent> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for RenderEvents {
+    type Item = RenderEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<RenderEvent>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// What a `BokehRenderer` is doing, from `BokehRenderer::status`.
+#[derive(Clone, Debug)]
+pub struct RendererStatus {
+    /// Whether a render, load or snapshot is in progress.
+    pub busy: bool,
+    /// Commands waiting behind the one in progress.
+    pub queue_len: usize,
+    /// When the last render or snapshot finished successfully.
+    pub last_render_at: Option<SystemTime>,
+    /// Whether the renderer's event loop still answers. Without it every other
+    /// field is at its default.
+    pub webview_alive: bool,
+    /// `Bokeh.version` of the page as last loaded.
+    pub loaded_bokeh_version: Option<BokehVersion>,
+}
+
+/// Requests sent to a `BokehRenderer`'s event loop thread.
+pub enum RendererCommand {
+    Render {
+        call: String,
+        dpi: Dpi,
+        format: RenderFormat,
+        /// The document's own window size, unless `RenderOptions::viewport` is set.
+        viewport: Option<(u32, u32)>,
+        stats: DocumentStats,
+        /// Where `RenderEvent`s of the render go, see `render_with_events`.
+        events: Option<mpsc::UnboundedSender<RenderEvent>>,
+        reply: oneshot::Sender<Result<RenderOutput, BokehRenderError>>,
+    },
+    Load {
+        json_data: String,
+        dpi: Dpi,
+        reply: oneshot::Sender<Result<(), BokehRenderError>>,
+    },
+    Eval {
+        script: String,
+        reply: oneshot::Sender<Result<(), BokehRenderError>>,
+    },
+    Snapshot {
+        name: String,
+        typ: String,
+        reply: oneshot::Sender<Result<(), BokehRenderError>>,
+    },
+    Collect {
+        reply: oneshot::Sender<Vec<Snapshot>>,
+    },
+    Status {
+        reply: oneshot::Sender<RendererStatus>,
+    },
+    WarmUp {
+        reply: oneshot::Sender<Result<(), BokehRenderError>>,
+    },
+    HealthCheck {
+        reply: oneshot::Sender<()>,
+    },
+    /// Replace the webview right away, failing the command in progress.
+    Recycle,
+    Shutdown,
+}
+
+/// A long-lived hidden webview that renders documents one after another
+/// without paying for a new window and event loop each time. The page loads
+/// Bokeh once and clears itself between documents; it is only reloaded after a
+/// page error or a crash.
+///
+/// Besides one-off `render`s it supports interactive sessions: `load` a
+/// document, drive it with `eval`, `snapshot` intermediate states by name and
+/// `collect` them at the end.
+///
+/// The event loop runs on a dedicated thread, which tao doesn't allow on macOS;
+/// see `MainThreadRenderer` there.
+pub struct BokehRenderer {
+    event_loop_proxy: EventLoopProxy<UserEvent>,
+    thread: Option<JoinHandle<()>>,
+    memory_cache: Option<Mutex<MemoryCache>>,
+    /// `RenderOptions::offline`, checked against every document.
+    offline: bool,
+    /// `RenderOptions::dpi`, overriding the resolution of every document.
+    dpi: Option<Dpi>,
+}
+
+impl BokehRenderer {
+    pub fn new(
+        resource: Option<BokehResource>,
+        options: RenderOptions,
+    ) -> Result<Self, BokehRenderError> {
+        if cfg!(target_os = "macos") {
+            return Err(BokehRenderError::Renderer(
+                "macOS requires the event loop to run on the main thread".to_string(),
+            ));
+        }
+
+        let offline = options.offline;
+        let dpi = options.dpi;
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let thread = std::thread::spawn(move || run_renderer(resource, options, ready_tx));
+        let event_loop_proxy = ready_rx
+            .recv()
+            .map_err(|_| BokehRenderError::Renderer("renderer thread exited".to_string()))??;
+
+        Ok(BokehRenderer {
+            event_loop_proxy,
+            thread: Some(thread),
+            memory_cache: None,
+            offline,
+            dpi,
+        })
+    }
+
+    /// Keep the last `capacity` exports of `render` in memory and return them
+    /// again, console messages and timings included, when the same document is
+    /// rendered at the same DPI and format.
+    pub fn with_memory_cache(mut self, capacity: usize) -> Self {
+        self.memory_cache = Some(Mutex::new(MemoryCache::new(capacity)));
+        self
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> RendererCommand,
+    ) -> Result<T, BokehRenderError> {
+        let (tx, rx) = oneshot::channel();
+        self.event_loop_proxy
+            .send_event(UserEvent::Command(command(tx)))
+            .map_err(|_| renderer_gone())?;
+        rx.await.map_err(|_| renderer_gone())
+    }
+
+    /// Render a standalone document. This discards any loaded session document,
+    /// unless the export comes from the memory cache.
+    pub async fn render(
+        &self,
+        json_data: &str,
+        dpi: impl Into<Dpi>,
+        format: RenderFormat,
+    ) -> Result<RenderOutput, BokehRenderError> {
+        self.render_reporting(json_data, dpi.into(), format, None)
+            .await
+    }
+
+    /// Like `render`, with the stream of the render's milestones as IPC from
+    /// the page reports them, so a UI can show the progress of a long export.
+    /// Like every future, the render only starts once it's polled.
+    pub fn render_with_events<'a>(
+        &'a self,
+        json_data: &'a str,
+        dpi: impl Into<Dpi>,
+        format: RenderFormat,
+    ) -> (
+        impl Future<Output = Result<RenderOutput, BokehRenderError>> + 'a,
+        RenderEvents,
+    ) {
+        let (events, receiver) = mpsc::unbounded_channel();
+        let dpi = dpi.into();
+        let render = async move {
+            let result = self
+                .render_reporting(json_data, dpi, format, Some(events.clone()))
+                .await;
+            let _ = events.send(RenderEvent::Done);
+            result
+        };
+        (render, RenderEvents { receiver })
+    }
+
+    async fn render_reporting(
+        &self,
+        json_data: &str,
+        dpi: Dpi,
+        format: RenderFormat,
+        events: Option<mpsc::UnboundedSender<RenderEvent>>,
+    ) -> Result<RenderOutput, BokehRenderError> {
+        let dpi = self.dpi.unwrap_or(dpi).validate()?;
+        let key = self
+            .memory_cache
+            .as_ref()
+            .map(|_| render_key(json_data, dpi, &format));
+        if let Some((cache, key)) = self.memory_cache.as_ref().zip(key.as_deref()) {
+            if let Some(output) = cache.lock().unwrap().get(key) {
+                return Ok(output);
+            }
+        }
+
+        let json_data = embeddable_json(json_data)?;
+        let json_data = json_data.as_ref();
+        if self.offline {
+            check_offline_document(json_data)?;
+        }
+        let call = render_call(json_data, dpi, &format);
+        let viewport = document_viewport(json_data);
+        let stats = DocumentStats::of(json_data);
+        let output = self
+            .request(|reply| RendererCommand::Render {
+                call,
+                dpi,
+                format,
+                viewport,
+                stats,
+                events,
+                reply,
+            })
+            .await??;
+        if let Some((cache, key)) = self.memory_cache.as_ref().zip(key) {
+            cache.lock().unwrap().insert(key, output.clone());
+        }
+        Ok(output)
+    }
+
+    /// Embed a document for an interactive session, replacing the previous one.
+    pub async fn load(
+        &self,
+        json_data: &str,
+        dpi: impl Into<Dpi>,
+    ) -> Result<(), BokehRenderError> {
+        let dpi = self.dpi.unwrap_or(dpi.into()).validate()?;
+        let json_data = embeddable_json(json_data)?.into_owned();
+        if self.offline {
+            check_offline_document(&json_data)?;
+        }
+        self.request(|reply| RendererCommand::Load {
+            json_data,
+            dpi,
+            reply,
+        })
+        .await?
+    }
+
+    /// Run `script` in the page, e.g. to change ranges or patch data sources of
+    /// the loaded document. The root view is available as `sessionView`.
+    pub async fn eval(&self, script: &str) -> Result<(), BokehRenderError> {
+        let script = script.to_string();
+        self.request(|reply| RendererCommand::Eval { script, reply })
+            .await?
+    }
+
+    /// Export the current state of the loaded document and keep it under `name`,
+    /// replacing an earlier snapshot with the same name.
+    pub async fn snapshot(&self, name: &str, typ: &str) -> Result<(), BokehRenderError> {
+        let name = name.to_string();
+        let typ = typ.to_string();
+        self.request(|reply| RendererCommand::Snapshot { name, typ, reply })
+            .await?
+    }
+
+    /// Take all snapshots stored so far, in the order they were first taken.
+    pub async fn collect(&self) -> Result<Vec<Snapshot>, BokehRenderError> {
+        self.request(|reply| RendererCommand::Collect { reply })
+            .await
+    }
+
+    /// Load the render page and its Bokeh bundles ahead of the first render, so
+    /// that it doesn't pay for them. Resolves once `window.Bokeh` is defined.
+    pub async fn warm_up(&self) -> Result<(), BokehRenderError> {
+        self.request(|reply| RendererCommand::WarmUp { reply })
+            .await?
+    }
+
+    /// Check that the webview still runs scripts, answering within `deadline`
+    /// even while it's busy. One that doesn't is replaced with a fresh webview,
+    /// failing the command in progress, and the check fails.
+    pub async fn health_check(&self, deadline: Duration) -> Result<(), BokehRenderError> {
+        let check = self.request(|reply| RendererCommand::HealthCheck { reply });
+        match tokio::time::timeout(deadline, check).await {
+            Ok(result) => result,
+            Err(_) => {
+                self.recycle();
+                Err(BokehRenderError::Renderer(format!(
+                    "the webview didn't answer a health check within {:?}",
+                    deadline
+                )))
+            }
+        }
+    }
+
+    /// Replace the webview with a fresh one right away, failing the command in
+    /// progress, e.g. a render given up on after a timeout that would otherwise
+    /// hold up every command behind it.
+    pub fn recycle(&self) {
+        let _ = self
+            .event_loop_proxy
+            .send_event(UserEvent::Command(RendererCommand::Recycle));
+    }
+
+    /// What the renderer is doing. Answered right away, even while it's busy.
+    pub async fn status(&self) -> RendererStatus {
+        self.request(|reply| RendererCommand::Status { reply })
+            .await
+            .unwrap_or(RendererStatus {
+                busy: false,
+                queue_len: 0,
+                last_render_at: None,
+                webview_alive: false,
+                loaded_bokeh_version: None,
+            })
+    }
+}
+
+impl Drop for BokehRenderer {
+    fn drop(&mut self) {
+        let _ = self
+            .event_loop_proxy
+            .send_event(UserEvent::Command(RendererCommand::Shutdown));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Runs a renderer's event loop on the calling thread and the application on
+/// another, for macOS, where tao event loops must run on the main thread. The
+/// application gets a `BokehRenderer` as usual, which can be shared between
+/// threads and tasks; the event loop shuts down once it's dropped.
+///
+/// Works the same on Windows and Linux, so applications can start the same way
+/// on every platform.
+pub struct MainThreadRenderer {
+    resource: Option<BokehResource>,
+    options: RenderOptions,
+}
+
+impl MainThreadRenderer {
+    pub fn new(resource: Option<BokehResource>, options: RenderOptions) -> Self {
+        MainThreadRenderer { resource, options }
+    }
+
+    /// Start the renderer's event loop on this thread, which must be the main
+    /// thread on macOS, and call `app` on a new thread with the renderer. Returns
+    /// what `app` returns once it has returned and the event loop has shut down.
+    /// A panic in `app` is resumed on this thread.
+    pub fn run<T: Send + 'static>(
+        self,
+        app: impl FnOnce(BokehRenderer) -> T + Send + 'static,
+    ) -> Result<T, BokehRenderError> {
+        let offline = self.options.offline;
+        let dpi = self.options.dpi;
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let app_thread = std::thread::spawn(move || -> Result<T, BokehRenderError> {
+            let event_loop_proxy = ready_rx
+                .recv()
+                .map_err(|_| BokehRenderError::Renderer("renderer exited".to_string()))??;
+            Ok(app(BokehRenderer {
+                event_loop_proxy,
+                thread: None,
+                memory_cache: None,
+                offline,
+                dpi,
+            }))
+        });
+        run_renderer(self.resource, self.options, ready_tx);
+        app_thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+fn renderer_gone() -> BokehRenderError {
+    BokehRenderError::Renderer("the renderer has shut down".to_string())
+}
+
+fn not_loaded() -> BokehRenderError {
+    BokehRenderError::InvalidRequest("no document is loaded in the renderer".to_string())
+}
+
+/// The command currently being carried out by the page.
+enum ActiveJob {
+    Render {
+        call: String,
+        dpi: Dpi,
+        format: RenderFormat,
+        events: Option<mpsc::UnboundedSender<RenderEvent>>,
+        reply: oneshot::Sender<Result<RenderOutput, BokehRenderError>>,
+        navigating: bool,
+        pending_pdf: Option<PendingPdf>,
+    },
+    Load {
+        call: String,
+        reply: oneshot::Sender<Result<(), BokehRenderError>>,
+        navigating: bool,
+    },
+    Snapshot {
+        name: String,
+        typ: String,
+        reply: oneshot::Sender<Result<(), BokehRenderError>>,
+    },
+    /// Waits for the page's `Bokeh.version`, see `UserEvent::BokehVersion`.
+    WarmUp {
+        reply: oneshot::Sender<Result<(), BokehRenderError>>,
+    },
+}
+
+struct RendererState {
+    webview: WebView,
+    window: Window,
+    /// Kept to build a fresh webview when recycling.
+    resource: Option<BokehResource>,
+    #[cfg(target_os = "windows")]
+    web_context: WebContext,
+    options: RenderOptions,
+    event_loop_proxy: EventLoopProxy<UserEvent>,
+    /// Renders by the current webview, for `RenderOptions::recycle_after`.
+    renders: u32,
+    /// Replace the webview before the next command, see `recycle`.
+    recycle_pending: bool,
+    queue: VecDeque<RendererCommand>,
+    active: Option<ActiveJob>,
+    /// Whether the page has loaded and not failed since, so the next document
+    /// can reuse it instead of navigating.
+    page_ready: bool,
+    session_loaded: bool,
+    session_dpi: Dpi,
+    /// The document rendered or loaded last, for `BokehRenderError::PageOutOfMemory`.
+    document_stats: DocumentStats,
+    snapshots: Vec<Snapshot>,
+    /// Console output since the last render or load, attached to the next result.
+    console: Vec<ConsoleMessage>,
+    last_render_at: Option<SystemTime>,
+    bokeh_version: Option<BokehVersion>,
+}
+
+impl RendererState {
+    /// Start queued commands until one has to wait on the page.
+    fn pump(&mut self) {
+        while self.active.is_none() {
+            if self.recycle_pending {
+                self.recycle();
+            }
+            let Some(command) = self.queue.pop_front() else {
+                return;
+            };
+            match command {
+                RendererCommand::Render {
+                    call,
+                    dpi,
+                    format,
+                    viewport,
+                    stats,
+                    events,
+                    reply,
+                } => {
+                    self.session_loaded = false;
+                    self.document_stats = stats;
+                    self.renders += 1;
+                    if self.options.recycle_after.is_some_and(|limit| self.renders >= limit) {
+                        self.recycle_pending = true;
+                    }
+                    self.console.clear();
+                    self.resize(viewport);
+                    let navigating = self.start_document(&call);
+                    self.active = Some(ActiveJob::Render {
+                        call,
+                        dpi: dpi.on_window(&self.window),
+                        format,
+                        events,
+                        reply,
+                        navigating,
+                        pending_pdf: None,
+                    });
+                }
+                RendererCommand::Load {
+                    json_data,
+                    dpi,
+                    reply,
+                } => {
+                    self.session_loaded = false;
+                    self.session_dpi = dpi.on_window(&self.window);
+                    self.document_stats = DocumentStats::of(&json_data);
+                    self.console.clear();
+                    self.resize(document_viewport(&json_data));
+                    let call = format!("loadBokeh({}, {})", js_string(&json_data), dpi);
+                    let navigating = self.start_document(&call);
+                    self.active = Some(ActiveJob::Load {
+                        call,
+                        reply,
+                        navigating,
+                    });
+                }
+                RendererCommand::Eval { script, reply } => {
+                    let result = if self.session_loaded {
+                        self.webview
+                            .evaluate_script(&script)
+                            .map_err(|e| BokehRenderError::Renderer(e.to_string()))
+                    } else {
+                        Err(not_loaded())
+                    };
+                    let _ = reply.send(result);
+                }
+                RendererCommand::Snapshot { name, typ, reply } => {
+                    if !self.session_loaded {
+                        let _ = reply.send(Err(not_loaded()));
+                        continue;
+                    }
+                    self.evaluate(&format!("snapshotBokeh({})", js_string(&typ)));
+                    self.active = Some(ActiveJob::Snapshot { name, typ, reply });
+                }
+                RendererCommand::Collect { reply } => {
+                    let _ = reply.send(std::mem::take(&mut self.snapshots));
+                }
+                RendererCommand::Status { reply } => {
+                    let _ = reply.send(self.status());
+                }
+                RendererCommand::HealthCheck { reply } => self.health_check(reply),
+                RendererCommand::Recycle => self.recycle(),
+                RendererCommand::WarmUp { reply } => {
+                    match self.page_ready {
+                        true => self.query_bokeh_version(),
+                        false => self.navigate(),
+                    }
+                    self.active = Some(ActiveJob::WarmUp { reply });
+                }
+                RendererCommand::Shutdown => (),
+            }
+        }
+    }
+
+    fn health_check(&self, reply: oneshot::Sender<()>) {
+        let reply = Mutex::new(Some(reply));
+        let _ = self.webview.evaluate_script_with_callback("true", move |_| {
+            if let Some(reply) = reply.lock().unwrap().take() {
+                let _ = reply.send(());
+            }
+        });
+    }
+
+    /// Replace the webview with a fresh one. If that fails, the current one is
+    /// kept.
+    fn recycle(&mut self) {
+        self.recycle_pending = false;
+        self.renders = 0;
+        let webview = build_webview(
+            &self.window,
+            self.resource.clone(),
+            &self.options,
+            &self.event_loop_proxy,
+            #[cfg(target_os = "windows")]
+            &mut self.web_context,
+        );
+        if let Ok(webview) = webview {
+            self.webview = webview;
+            self.page_ready = false;
+            self.session_loaded = false;
+            self.bokeh_version = None;
+        }
+    }
+
+    /// A failed health check: fail the command in progress, which will never
+    /// finish, and replace the webview before the next one.
+    fn on_unresponsive(&mut self) {
+        self.recycle_pending = true;
+        self.fail_active(BokehRenderError::Renderer(
+            "the webview stopped responding".to_string(),
+        ));
+    }
+
+    fn status(&self) -> RendererStatus {
+        RendererStatus {
+            busy: self.active.is_some(),
+            queue_len: self.queue.len(),
+            last_render_at: self.last_render_at,
+            webview_alive: true,
+            loaded_bokeh_version: self.bokeh_version.clone(),
+        }
+    }
+
+    /// Size the window for the next document, `RenderOptions::viewport` taking
+    /// precedence over the document's `viewport`.
+    fn resize(&self, viewport: Option<(u32, u32)>) {
+        if let Some((width, height)) = self.options.viewport.or(viewport) {
+            self.window.set_inner_size(LogicalSize::new(width, height));
+        }
+    }
+
+    /// Make `call` for a new document on the current page, cleared by
+    /// `resetPage`, or once a fresh page has loaded if the current one can't be
+    /// trusted. Returns whether the call waits for the navigation.
+    fn start_document(&mut self, call: &str) -> bool {
+        if self.page_ready {
+            self.evaluate(&format!("resetPage(); {}", call));
+            return false;
+        }
+        self.navigate();
+        true
+    }
+
+    fn navigate(&self) {
+        let _ = self.webview.load_url(RENDER_PAGE_URL);
+    }
+
+    fn evaluate(&self, script: &str) {
+        let _ = self.webview.evaluate_script(script);
+    }
+
+    /// Ask the page for `Bokeh.version`, answered as `UserEvent::BokehVersion`.
+    fn query_bokeh_version(&self) {
+        let event_loop_proxy = self.event_loop_proxy.clone();
+        let _ = self.webview.evaluate_script_with_callback(
+            "window.Bokeh?.version ?? null",
+            move |version| {
+                let version = serde_json::from_str(&version).unwrap_or_default();
+                let _ = event_loop_proxy.send_event(UserEvent::BokehVersion(version));
+            },
+        );
+    }
+
+    fn on_page_loaded(&mut self) {
+        self.page_ready = true;
+        self.query_bokeh_version();
+        match &mut self.active {
+            Some(ActiveJob::Render {
+                call, navigating, ..
+            })
+            | Some(ActiveJob::Load {
+                call, navigating, ..
+            }) if *navigating => {
+                *navigating = false;
+                let _ = self.webview.evaluate_script(call);
+            }
+            _ => (),
+        }
+    }
+
+    fn on_bokeh_version(&mut self, version: Option<String>) {
+        let loaded = version.is_some();
+        self.bokeh_version = version.and_then(|version| version.parse().ok());
+        match self.active.take() {
+            Some(ActiveJob::WarmUp { reply }) => {
+                let result = match loaded {
+                    true => Ok(()),
+                    false => Err(BokehRenderError::bokeh_missing()),
+                };
+                let _ = reply.send(result);
+            }
+            active => self.active = active,
+        }
+        self.pump();
+    }
+
+    fn on_image(&mut self, image: ExportedImage) {
+        match self.active.take() {
+            Some(ActiveJob::Render {
+                dpi, format, reply, ..
+            }) => {
+                let console = std::mem::take(&mut self.console);
+                let output = image_output(image, dpi, format, &self.options)
+                    .map(|output| output.with_console(console));
+                if output.is_ok() {
+                    self.last_render_at = Some(SystemTime::now());
+                }
+                let _ = reply.send(output);
+            }
+            Some(ActiveJob::Snapshot { name, typ, reply }) => {
+                let format = RenderFormat::Image(typ);
+                let console = std::mem::take(&mut self.console);
+                let result =
+                    image_output(image, self.session_dpi, format, &self.options).map(|output| {
+                        let output = output.with_console(console);
+                        self.snapshots.retain(|snapshot| snapshot.name != name);
+                        self.snapshots.push(Snapshot { name, output });
+                        self.last_render_at = Some(SystemTime::now());
+                    });
+                let _ = reply.send(result);
+            }
+            active => self.active = active,
+        }
+        self.pump();
+    }
+
+    fn on_page_error(&mut self, error: BokehRenderError) {
+        self.page_ready = false;
+        self.fail_active(error);
+    }
+
+    /// Fail the command in progress with the crash; the next document's
+    /// navigation starts a fresh web content process.
+    fn on_process_failed(&mut self, out_of_memory: bool) {
+        self.session_loaded = false;
+        self.page_ready = false;
+        self.fail_active(BokehRenderError::process_failed(out_of_memory, self.document_stats));
+    }
+
+    fn fail_active(&mut self, error: BokehRenderError) {
+        match self.active.take() {
+            Some(ActiveJob::Render { reply, .. }) => {
+                let _ = reply.send(Err(error));
+            }
+            Some(ActiveJob::Load { reply, .. })
+            | Some(ActiveJob::Snapshot { reply, .. })
+            | Some(ActiveJob::WarmUp { reply }) => {
+                let _ = reply.send(Err(error));
+            }
+            None => (),
+        }
+        self.pump();
+    }
+
+    fn on_loaded(&mut self) {
+        match self.active.take() {
+            Some(ActiveJob::Load { reply, .. }) => {
+                self.session_loaded = true;
+                let _ = reply.send(Ok(()));
+            }
+            active => self.active = active,
+        }
+        self.pump();
+    }
+
+    fn on_printable(&mut self, width: f64, height: f64, timings: RenderTimings) {
+        if let Some(ActiveJob::Render {
+            format,
+            pending_pdf,
+            ..
+        }) = &mut self.active
+        {
+            *pending_pdf = PendingPdf::start(
+                &self.webview,
+                format,
+                width,
+                height,
+                timings,
+                self.event_loop_proxy.clone().into(),
+            );
+        }
+    }
+
+    /// Pass a milestone on to the render in progress, if it asked for them.
+    fn on_progress(&self, event: RenderEvent) {
+        if let Some(ActiveJob::Render {
+            events: Some(events),
+            ..
+        }) = &self.active
+        {
+            let _ = events.send(event);
+        }
+    }
+
+    fn on_pdf_printed(&mut self, result: Result<Vec<u8>, String>) {
+        self.on_progress(RenderEvent::Exported);
+        match self.active.take() {
+            Some(ActiveJob::Render {
+                dpi,
+                format,
+                reply,
+                pending_pdf: Some(pending),
+                ..
+            }) => {
+                let console = std::mem::take(&mut self.console);
+                let output = pending
+                    .finish(result, dpi, format)
+                    .map(|output| output.with_console(console));
+                if output.is_ok() {
+                    self.last_render_at = Some(SystemTime::now());
+                }
+                let _ = reply.send(output);
+            }
+            active => self.active = active,
+        }
+        self.pump();
+    }
+}
+
+/// A renderer webview on `window`, watched for crashes of its web content process.
+fn build_webview(
+    window: &Window,
+    resource: Option<BokehResource>,
+    options: &RenderOptions,
+    event_loop_proxy: &EventLoopProxy<UserEvent>,
+    #[cfg(target_os = "windows")] web_context: &mut WebContext,
+) -> Result<WebView, BokehRenderError> {
+    #[cfg(target_os = "windows")]
+    let webview_builder = WebViewBuilder::with_web_context(web_context);
+
+    #[cfg(not(target_os = "windows"))]
+    let webview_builder = WebViewBuilder::new();
+
+    let webview = render_webview_builder(
+        webview_builder,
+        resource,
+        options.clone(),
+        event_loop_proxy.clone().into(),
+    )
+    .build(window)
+    .map_err(webview_error)?;
+    apply_browser_args(&webview, options);
+    watch_web_process(&webview, event_loop_proxy.clone().into());
+    Ok(webview)
+}
+
+fn run_renderer(
+    resource: Option<BokehResource>,
+    options: RenderOptions,
+    ready: std::sync::mpsc::Sender<Result<EventLoopProxy<UserEvent>, BokehRenderError>>,
+) {
+    if options.offline {
+        if let Err(e) = check_offline_resources(resource.as_ref(), &options) {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    }
+    let mut event_loop_builder = EventLoopBuilder::<UserEvent>::with_user_event();
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    event_loop_builder.with_any_thread(true);
+    let mut event_loop = event_loop_builder.build();
+    let event_loop_proxy = event_loop.create_proxy();
+
+    let window = match render_window_builder(&options).build(&event_loop) {
+        Ok(window) => window,
+        Err(e) => {
+            let _ = ready.send(Err(BokehRenderError::Renderer(e.to_string())));
+            return;
+        }
+    };
+
+    #[cfg(target_os = "windows")]
+    let mut web_context = crate::bokeh_helpers::new_web_context(&options);
+
+    let webview = match build_webview(
+        &window,
+        resource.clone(),
+        &options,
+        &event_loop_proxy,
+        #[cfg(target_os = "windows")]
+        &mut web_context,
+    ) {
+        Ok(webview) => webview,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    let mut state = RendererState {
+        webview,
+        window,
+        resource,
+        #[cfg(target_os = "windows")]
+        web_context,
+        options,
+        event_loop_proxy: event_loop_proxy.clone(),
+        renders: 0,
+        recycle_pending: false,
+        queue: VecDeque::new(),
+        active: None,
+        page_ready: false,
+        session_loaded: false,
+        session_dpi: Dpi::CSS,
+        document_stats: DocumentStats::default(),
+        snapshots: Vec::new(),
+        console: Vec::new(),
+        last_render_at: None,
+        bokeh_version: None,
+    };
+    let _ = ready.send(Ok(event_loop_proxy));
+
+    let _ = event_loop.run_return(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+
+        match event {
+            Event::UserEvent(UserEvent::Command(RendererCommand::Shutdown)) => {
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::UserEvent(UserEvent::Command(RendererCommand::Status { reply })) => {
+                let _ = reply.send(state.status());
+            }
+            Event::UserEvent(UserEvent::Command(RendererCommand::HealthCheck { reply })) => {
+                state.health_check(reply)
+            }
+            Event::UserEvent(UserEvent::Command(RendererCommand::Recycle)) => {
+                state.on_unresponsive()
+            }
+            Event::UserEvent(UserEvent::Command(command)) => {
+                state.queue.push_back(command);
+                state.pump();
+            }
+            Event::UserEvent(UserEvent::PageLoaded) => state.on_page_loaded(),
+            Event::UserEvent(UserEvent::BokehVersion(version)) => state.on_bokeh_version(version),
+            Event::UserEvent(UserEvent::PayloadReceived(image)) => state.on_image(image),
+            Event::UserEvent(UserEvent::Loaded) => state.on_loaded(),
+            Event::UserEvent(UserEvent::Progress(event)) => state.on_progress(event),
+            Event::UserEvent(UserEvent::Console(message)) => state.console.push(message),
+            Event::UserEvent(UserEvent::PageError(message)) => {
+                state.on_page_error(BokehRenderError::Page(message))
+            }
+            Event::UserEvent(UserEvent::ResourceLoadFailed(error)) => state.on_page_error(error),
+            Event::UserEvent(UserEvent::ProcessFailed { out_of_memory }) => {
+                state.on_process_failed(out_of_memory)
+            }
+            Event::UserEvent(UserEvent::Printable {
+                width,
+                height,
+                timings,
+            }) => state.on_printable(width, height, timings),
+            Event::UserEvent(UserEvent::PdfPrinted(result)) => state.on_pdf_printed(result),
+            _ => (),
+        }
+    });
+}
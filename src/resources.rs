@@ -0,0 +1,489 @@
+//! Where the render page gets the BokehJS bundles from, and the extra scripts
+//! and stylesheets it loads alongside them.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use crate::archive;
+use crate::options::RenderOptions;
+use crate::proxy;
+use crate::version::{BokehVersion, MIN_SUPPORTED_VERSION};
+
+/// Why a `BokehResource` constructor rejected its arguments.
+#[derive(Clone, Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum BokehResourceError {
+    #[error("Invalid Bokeh version {0:?}, expected a release such as \"3.5.2\"")]
+    InvalidVersion(String),
+    /// The render page can't embed documents with this release, see
+    /// `version::MIN_SUPPORTED_VERSION`.
+    #[error(
+        "Bokeh {0} is not supported, the render page needs Bokeh {min} or later",
+        min = MIN_SUPPORTED_VERSION
+    )]
+    UnsupportedVersion(BokehVersion),
+    #[error("The Bokeh resource folder {} does not exist", .0.display())]
+    MissingFolder(PathBuf),
+    #[error("The Bokeh resource folder {} is missing {}", .folder.display(), .missing.join(", "))]
+    MissingBundles {
+        folder: PathBuf,
+        missing: Vec<&'static str>,
+    },
+    #[error("The Bokeh resource archive {} can't be served: {reason}", .path.display())]
+    InvalidArchive { path: PathBuf, reason: String },
+}
+
+/// Bundle files expected in a `BokehLocalResource` folder, in load order, and
+/// loaded for Bokeh 3 releases (see `BokehVersion::bundles`).
+pub(crate) const LOCAL_BUNDLES: [&str; 3] =
+    ["bokeh.min.js", "bokeh-mathjax.min.js", "bokeh-api.min.js"];
+
+/// Prefer `BokehResource::cdn`, which validates the version.
+#[derive(Clone)]
+pub struct BokehCDNResource {
+    pub version: BokehVersion,
+    /// Pinned Subresource Integrity hashes (`sha384-<base64>`) keyed by bundle
+    /// file name as in `LOCAL_BUNDLES`. A bundle that doesn't match fails the render.
+    pub integrity: HashMap<String, String>,
+}
+
+/// Prefer `BokehResource::local`, which checks the folder holds the bundles.
+#[derive(Clone)]
+pub struct BokehLocalResource {
+    pub folder_uri: String,
+}
+
+/// Bundles and other files served straight out of a zip archive or gzipped
+/// tarball, a single sealed artifact rather than a folder whose files can be
+/// changed. Prefer `BokehResource::archive`, which checks it holds the bundles.
+#[derive(Clone)]
+pub struct BokehArchiveResource {
+    pub path: PathBuf,
+    /// The folder of the bundles within the archive, such as `bokeh-3.5.2/`,
+    /// or empty if they are at its top.
+    pub root: String,
+}
+
+/// CDN bundles downloaded on first use into `cache_dir/<version>/` and served
+/// from there afterwards, so renders keep working offline after the first run.
+/// Prefer `BokehResource::auto_cache`, which validates the version.
+#[derive(Clone)]
+pub struct BokehAutoCacheResource {
+    pub version: BokehVersion,
+    pub cache_dir: PathBuf,
+}
+
+#[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
+#[non_exhaustive]
+pub enum BokehResource {
+    CDN(BokehCDNResource),
+    Local(BokehLocalResource),
+    /// Bundles held in memory, keyed by file name (see `LOCAL_BUNDLES`), for
+    /// applications that embed them in their binary or download them at startup.
+    Memory(HashMap<String, Vec<u8>>),
+    AutoCache(BokehAutoCacheResource),
+    Archive(BokehArchiveResource),
+    /// Bundles served by a user-supplied backend, such as an archive or an HTTP cache.
+    Provider(Arc<dyn ResourceProvider>),
+}
+
+impl BokehResource {
+    /// Bundles of Bokeh `version` loaded from the CDN.
+    pub fn cdn(version: impl AsRef<str>) -> Result<Self, BokehResourceError> {
+        Ok(BokehResource::CDN(BokehCDNResource {
+            version: version.as_ref().parse::<BokehVersion>()?.supported()?,
+            integrity: HashMap::new(),
+        }))
+    }
+
+    /// Bundles served from `folder`, which must contain every file in `LOCAL_BUNDLES`.
+    pub fn local(folder: impl AsRef<Path>) -> Result<Self, BokehResourceError> {
+        let folder = folder.as_ref();
+        if !folder.is_dir() {
+            return Err(BokehResourceError::MissingFolder(folder.to_path_buf()));
+        }
+        let missing: Vec<&'static str> = LOCAL_BUNDLES
+            .into_iter()
+            .filter(|bundle| !folder.join(bundle).is_file())
+            .collect();
+        if !missing.is_empty() {
+            return Err(BokehResourceError::MissingBundles {
+                folder: folder.to_path_buf(),
+                missing,
+            });
+        }
+        Ok(BokehResource::Local(BokehLocalResource {
+            folder_uri: folder.to_string_lossy().into_owned(),
+        }))
+    }
+
+    /// CDN bundles of Bokeh `version`, cached in `cache_dir` on first use.
+    pub fn auto_cache(
+        version: impl AsRef<str>,
+        cache_dir: impl Into<PathBuf>,
+    ) -> Result<Self, BokehResourceError> {
+        Ok(BokehResource::AutoCache(BokehAutoCacheResource {
+            version: version.as_ref().parse::<BokehVersion>()?.supported()?,
+            cache_dir: cache_dir.into(),
+        }))
+    }
+
+    /// Bundles served out of the zip archive or gzipped tarball (`.tar.gz` or
+    /// `.tgz`) at `path`, which must contain every file in `LOCAL_BUNDLES`,
+    /// at its top or all in one folder.
+    pub fn archive(path: impl Into<PathBuf>) -> Result<Self, BokehResourceError> {
+        let path = path.into();
+        let invalid = |reason: String| BokehResourceError::InvalidArchive {
+            path: path.clone(),
+            reason,
+        };
+        let names = archive::file_names(&path).map_err(|e| invalid(e.to_string()))?;
+        let root = names
+            .iter()
+            .filter_map(|name| name.strip_suffix(LOCAL_BUNDLES[0]))
+            .find(|root| root.is_empty() || root.ends_with('/'))
+            .ok_or_else(|| invalid(format!("it holds no {}", LOCAL_BUNDLES[0])))?
+            .to_string();
+        let missing: Vec<&'static str> = LOCAL_BUNDLES
+            .into_iter()
+            .filter(|bundle| !names.contains(&format!("{}{}", root, bundle)))
+            .collect();
+        if !missing.is_empty() {
+            return Err(invalid(format!("{}is missing {}", root, missing.join(", "))));
+        }
+        Ok(BokehResource::Archive(BokehArchiveResource { path, root }))
+    }
+
+    /// The provider serving bundles over the custom protocol, or `None` for the CDN.
+    pub(crate) fn provider(&self) -> Option<&dyn ResourceProvider> {
+        match self {
+            BokehResource::CDN(_) => None,
+            BokehResource::Local(local) => Some(local),
+            BokehResource::Memory(files) => Some(files),
+            BokehResource::AutoCache(auto_cache) => Some(auto_cache),
+            BokehResource::Archive(archive) => Some(archive),
+            BokehResource::Provider(provider) => Some(provider.as_ref()),
+        }
+    }
+
+    /// Whether the page may load files besides `LOCAL_BUNDLES` from the
+    /// resource directory, such as the fonts MathJax requests as it typesets.
+    pub(crate) fn serves_assets(&self) -> bool {
+        matches!(self, BokehResource::Local(_) | BokehResource::Archive(_))
+    }
+
+    /// The bundles the page loads: those of the release for downloaded ones,
+    /// otherwise `LOCAL_BUNDLES`.
+    pub(crate) fn bundles(&self) -> &'static [&'static str] {
+        match self {
+            BokehResource::CDN(cdn) => cdn.version.bundles(),
+            BokehResource::AutoCache(auto_cache) => auto_cache.version.bundles(),
+            _ => &LOCAL_BUNDLES,
+        }
+    }
+}
+
+/// A file served to the render page under `/bokeh-resource-dir/`.
+pub struct Resource {
+    pub content: Vec<u8>,
+    pub mime_type: String,
+}
+
+impl Resource {
+    /// A resource with the MIME type guessed from the extension of `path`.
+    pub fn guess(path: &str, content: Vec<u8>) -> Self {
+        Resource {
+            content,
+            mime_type: mime_guess::from_path(path)
+                .first()
+                .map(|mime| mime.to_string())
+                .unwrap_or("application/octet-stream".to_string()),
+        }
+    }
+}
+
+/// A backend for the files the page loads from `/bokeh-resource-dir/`: the
+/// bundles in `LOCAL_BUNDLES`, requested by their bare file name. Local folders
+/// also serve the other files in them, see `BokehResource::serves_assets`.
+pub trait ResourceProvider: Send + Sync {
+    /// Fetch the file at `path`, relative to the resource directory.
+    fn fetch(&self, path: &str) -> std::io::Result<Resource>;
+
+    /// An HTTP entity tag of the file at `path` that changes with its content,
+    /// found without reading it, e.g. from its size and modification time. The
+    /// page's cached copy is then revalidated without fetching the file again;
+    /// `None` makes the tag a hash of the fetched content.
+    fn etag(&self, _path: &str) -> Option<String> {
+        None
+    }
+}
+
+impl ResourceProvider for HashMap<String, Vec<u8>> {
+    fn fetch(&self, path: &str) -> std::io::Result<Resource> {
+        match self.get(path) {
+            Some(content) => Ok(Resource::guess(path, content.clone())),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} is not among the in-memory resources", path),
+            )),
+        }
+    }
+}
+
+impl BokehLocalResource {
+    fn resolve(&self, path: &str) -> std::io::Result<PathBuf> {
+        // Resolve symlinks and dot segments so nothing outside the folder is served.
+        let folder = std::fs::canonicalize(&self.folder_uri)?;
+        let file = std::fs::canonicalize(folder.join(path))?;
+        if !file.starts_with(&folder) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("{} is outside {}", path, self.folder_uri),
+            ));
+        }
+        Ok(file)
+    }
+}
+
+impl ResourceProvider for BokehLocalResource {
+    fn fetch(&self, path: &str) -> std::io::Result<Resource> {
+        let content = std::fs::read(self.resolve(path)?)?;
+        Ok(Resource::guess(path, content))
+    }
+
+    fn etag(&self, path: &str) -> Option<String> {
+        file_etag(&self.resolve(path).ok()?)
+    }
+}
+
+impl ResourceProvider for BokehArchiveResource {
+    fn fetch(&self, path: &str) -> std::io::Result<Resource> {
+        let content = archive::read_file(&self.path, &format!("{}{}", self.root, path))?;
+        Ok(Resource::guess(path, content))
+    }
+
+    /// The tag of the whole archive: its files only change with it.
+    fn etag(&self, _path: &str) -> Option<String> {
+        file_etag(&self.path)
+    }
+}
+
+/// An entity tag of the file at `path` from its size and modification time.
+fn file_etag(path: &Path) -> Option<String> {
+    let meta = std::fs::metadata(path).ok()?;
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("\"{:x}-{:x}\"", meta.len(), modified.as_nanos()))
+}
+
+/// SHA-256 of every bundle in a version directory of a `BokehAutoCacheResource`,
+/// recorded at download time so truncated or tampered files are fetched again.
+const AUTO_CACHE_MANIFEST: &str = "checksums.json";
+
+/// Serializes updates of auto cache manifests within the process, so that
+/// concurrent fetches don't drop each other's checksums.
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+fn read_manifest(manifest_path: &Path) -> HashMap<String, String> {
+    std::fs::read(manifest_path)
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Record `checksum` for `path`, merged into the manifest as it is on disk now.
+fn record_checksum(manifest_path: &Path, path: &str, checksum: String) -> std::io::Result<()> {
+    let _lock = MANIFEST_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut manifest = read_manifest(manifest_path);
+    manifest.insert(path.to_string(), checksum);
+    write_atomically(manifest_path, &serde_json::to_vec_pretty(&manifest)?)
+}
+
+impl ResourceProvider for BokehAutoCacheResource {
+    fn fetch(&self, path: &str) -> std::io::Result<Resource> {
+        if !self.version.bundles().contains(&path) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} is not a Bokeh {} bundle", path, self.version),
+            ));
+        }
+
+        let dir = self.cache_dir.join(self.version.to_string());
+        let manifest_path = dir.join(AUTO_CACHE_MANIFEST);
+        let manifest = read_manifest(&manifest_path);
+
+        let file_path = dir.join(path);
+        if let (Some(checksum), Ok(content)) = (manifest.get(path), std::fs::read(&file_path)) {
+            if sha256_hex(&content) == *checksum {
+                return Ok(Resource::guess(path, content));
+            }
+        }
+
+        let content = download_cdn_bundle(&self.version, path)?;
+        std::fs::create_dir_all(&dir)?;
+        write_atomically(&file_path, &content)?;
+        record_checksum(&manifest_path, path, sha256_hex(&content))?;
+        Ok(Resource::guess(path, content))
+    }
+
+    /// The checksum recorded for the cached bundle; `fetch` verifies the file
+    /// against it once the page asks for a fresh copy.
+    fn etag(&self, path: &str) -> Option<String> {
+        let dir = self.cache_dir.join(self.version.to_string());
+        if !self.version.bundles().contains(&path) || !dir.join(path).is_file() {
+            return None;
+        }
+        let manifest: HashMap<String, String> =
+            serde_json::from_slice(&std::fs::read(dir.join(AUTO_CACHE_MANIFEST)).ok()?).ok()?;
+        manifest.get(path).map(|checksum| format!("\"{}\"", checksum))
+    }
+}
+
+pub(crate) fn sha256_hex(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Write through a temporary file so concurrent renders never read a partial bundle.
+/// Every write gets a temporary file of its own, also within one process.
+pub(crate) fn write_atomically(path: &std::path::Path, content: &[u8]) -> std::io::Result<()> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = path.with_file_name(temp_name);
+    if let Err(e) = std::fs::write(&temp_path, content) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    std::fs::rename(&temp_path, path)
+}
+
+/// The CDN URL of `bundle`, one of `version`'s bundles.
+pub(crate) fn cdn_bundle_url(version: &BokehVersion, bundle: &str) -> String {
+    format!(
+        "https://cdn.bokeh.org/bokeh/release/{}-{}.min.js",
+        bundle.trim_end_matches(".min.js"),
+        version
+    )
+}
+
+fn download_cdn_bundle(version: &BokehVersion, bundle: &str) -> std::io::Result<Vec<u8>> {
+    download_script(&cdn_bundle_url(version, bundle), &[])
+}
+
+/// How long a download may take to connect.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a download may go without receiving data.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The agent every download goes through. Pages fetch their resources on the
+/// event loop thread, so a stalled host must fail the fetch rather than block
+/// the loop, and with it `RenderOptions::bokeh_load_timeout`.
+pub(crate) fn http_agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(|| {
+        ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout_read(READ_TIMEOUT)
+            .timeout_write(READ_TIMEOUT)
+            .build()
+    })
+}
+
+fn download_script(url: &str, headers: &[(String, String)]) -> std::io::Result<Vec<u8>> {
+    download(url, headers, "javascript", "script")
+}
+
+/// Download `url` with `headers`, failing unless the response is a non-empty
+/// `kind` whose content type contains `content_type`.
+fn download(
+    url: &str,
+    headers: &[(String, String)],
+    content_type: &str,
+    kind: &str,
+) -> std::io::Result<Vec<u8>> {
+    let mut request = http_agent().get(url);
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    let response = request
+        .call()
+        .map_err(|e| std::io::Error::other(format!("Failed to download {}: {}", url, e)))?;
+    if !response.content_type().contains(content_type) {
+        return Err(std::io::Error::other(format!(
+            "{} returned {} instead of a {}",
+            url,
+            response.content_type(),
+            kind
+        )));
+    }
+
+    let mut content = Vec::new();
+    response.into_reader().read_to_end(&mut content)?;
+    if content.is_empty() {
+        return Err(std::io::Error::other(format!("{} returned an empty {}", url, kind)));
+    }
+    Ok(content)
+}
+
+/// A script the page loads after the Bokeh bundles and before embedding the
+/// document, such as the compiled JS of custom extension models.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum ScriptSource {
+    /// Downloaded by the renderer when the page requests it.
+    Url(String),
+    File(PathBuf),
+    Inline(String),
+}
+
+impl ScriptSource {
+    pub(crate) fn fetch(&self, options: &RenderOptions) -> std::io::Result<Resource> {
+        let content = match self {
+            ScriptSource::Url(url) => download_script(url, proxy::headers_for(options, url))?,
+            ScriptSource::File(path) => std::fs::read(path)?,
+            ScriptSource::Inline(script) => script.clone().into_bytes(),
+        };
+        Ok(Resource {
+            content,
+            mime_type: "text/javascript".to_string(),
+        })
+    }
+}
+
+/// A stylesheet the page loads in `<head>`, for custom fonts, `@font-face`
+/// rules or overrides of Bokeh widget styling.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum CssSource {
+    /// Downloaded by the renderer when the page requests it.
+    Url(String),
+    File(PathBuf),
+    Inline(String),
+}
+
+impl CssSource {
+    pub(crate) fn fetch(&self, options: &RenderOptions) -> std::io::Result<Resource> {
+        let content = match self {
+            CssSource::Url(url) => {
+                download(url, proxy::headers_for(options, url), "css", "stylesheet")?
+            }
+            CssSource::File(path) => std::fs::read(path)?,
+            CssSource::Inline(css) => css.clone().into_bytes(),
+        };
+        Ok(Resource {
+            content,
+            mime_type: "text/css".to_string(),
+        })
+    }
+}
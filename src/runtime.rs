@@ -0,0 +1,92 @@
+//! Whether the platform webview can be created at all. WebKitGTK and WKWebView
+//! come with the system, but the WebView2 runtime may be missing from older
+//! Windows installs and server images.
+
+use crate::output::BokehRenderError;
+
+/// Download of Microsoft's Evergreen WebView2 runtime bootstrapper.
+pub const WEBVIEW2_BOOTSTRAPPER_URL: &str = "https://go.microsoft.com/fwlink/p/?LinkId=2124703";
+
+/// What `check_webview2_runtime` found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuntimeStatus {
+    /// The version of the platform webview, e.g. `120.0.2210.91` for WebView2.
+    Installed { version: String },
+    /// No webview runtime is installed; renders fail with
+    /// `BokehRenderError::RuntimeMissing`.
+    Missing,
+}
+
+/// Look up the WebView2 runtime on Windows, or the system webview elsewhere.
+pub fn check_webview2_runtime() -> RuntimeStatus {
+    match wry::webview_version() {
+        Ok(version) => RuntimeStatus::Installed { version },
+        Err(_) => RuntimeStatus::Missing,
+    }
+}
+
+/// Why creating a webview failed, telling a missing WebView2 runtime apart
+/// from other failures.
+pub(crate) fn webview_error(error: wry::Error) -> BokehRenderError {
+    if cfg!(target_os = "windows") && check_webview2_runtime() == RuntimeStatus::Missing {
+        return runtime_missing();
+    }
+    BokehRenderError::Renderer(error.to_string())
+}
+
+fn runtime_missing() -> BokehRenderError {
+    BokehRenderError::RuntimeMissing(format!(
+        "install the Evergreen WebView2 runtime from {}{}",
+        WEBVIEW2_BOOTSTRAPPER_URL,
+        if cfg!(feature = "webview2-bootstrap") {
+            ", or with `runtime::install_webview2_runtime`"
+        } else {
+            ""
+        }
+    ))
+}
+
+/// Download Microsoft's bootstrapper and install the Evergreen WebView2 runtime
+/// with it, unless it's installed already. Installing for all users needs an
+/// elevated process; otherwise the runtime is installed for the current user.
+#[cfg(all(target_os = "windows", feature = "webview2-bootstrap"))]
+pub fn install_webview2_runtime() -> Result<(), BokehRenderError> {
+    if let RuntimeStatus::Installed { .. } = check_webview2_runtime() {
+        return Ok(());
+    }
+
+    let install_failed = |reason: String| {
+        BokehRenderError::Renderer(format!("Installing the WebView2 runtime failed: {}", reason))
+    };
+    let path = std::env::temp_dir().join(format!(
+        "MicrosoftEdgeWebview2Setup-{}.exe",
+        std::process::id()
+    ));
+    let response = crate::resources::http_agent()
+        .get(WEBVIEW2_BOOTSTRAPPER_URL)
+        .call()
+        .map_err(|e| install_failed(e.to_string()))?;
+    let mut file = std::fs::File::create(&path).map_err(|e| install_failed(e.to_string()))?;
+    std::io::copy(&mut response.into_reader(), &mut file)
+        .map_err(|e| install_failed(e.to_string()))?;
+    drop(file);
+
+    let status = std::process::Command::new(&path)
+        .args(["/silent", "/install"])
+        .status();
+    let _ = std::fs::remove_file(&path);
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(status) => {
+            return Err(install_failed(format!(
+                "the bootstrapper exited with {}",
+                status
+            )))
+        }
+        Err(e) => return Err(install_failed(e.to_string())),
+    }
+    match check_webview2_runtime() {
+        RuntimeStatus::Installed { .. } => Ok(()),
+        RuntimeStatus::Missing => Err(runtime_missing()),
+    }
+}
@@ -0,0 +1,117 @@
+//! Exports too large for one canvas, which browsers cap at around 16k pixels
+//! per side (and fewer in total): the page cuts the view into tiles and the
+//! renderer stitches them into one PNG.
+
+use image::{codecs::png::PngEncoder, GenericImage, ImageEncoder, RgbaImage};
+
+use crate::options::{Dpi, RenderFormat, RenderOptions};
+use crate::output::{BokehRenderError, RenderOutput, RenderTimings};
+use crate::postprocess::post_process;
+use crate::protocol::decode_data_url;
+
+/// Settings of `RenderOptions::tiled`.
+#[derive(Clone, Copy, Debug)]
+pub struct TiledExport {
+    /// Side of the square tiles in pixels, within every webview's canvas limit.
+    pub tile_size: u32,
+    /// Fail exports whose stitched image would take more memory than this,
+    /// at 4 bytes per pixel, instead of stitching them.
+    pub max_memory_bytes: Option<u64>,
+}
+
+impl Default for TiledExport {
+    fn default() -> Self {
+        TiledExport {
+            tile_size: 4096,
+            max_memory_bytes: None,
+        }
+    }
+}
+
+/// A tile posted by the page, see `postTiles` in `render_bokeh.js`. `width` and
+/// `height` are the size of the whole export; only the last tile carries timings.
+pub struct ExportedTile {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) data_url: String,
+    pub(crate) timings: Option<RenderTimings>,
+}
+
+/// Collects the tiles of one export.
+#[derive(Default)]
+pub(crate) struct Stitcher {
+    image: Option<RgbaImage>,
+}
+
+impl Stitcher {
+    /// Add `tile`, returning the stitched export once the last one is in.
+    pub(crate) fn add(
+        &mut self,
+        tile: ExportedTile,
+        dpi: Dpi,
+        options: &RenderOptions,
+    ) -> Option<Result<RenderOutput, BokehRenderError>> {
+        match self.try_add(tile, dpi, options) {
+            Ok(output) => output.map(Ok),
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    fn try_add(
+        &mut self,
+        tile: ExportedTile,
+        dpi: Dpi,
+        options: &RenderOptions,
+    ) -> Result<Option<RenderOutput>, BokehRenderError> {
+        let image = match &mut self.image {
+            Some(image) => image,
+            None => {
+                let bytes = u64::from(tile.width) * u64::from(tile.height) * 4;
+                let limit = options.tiled.and_then(|tiled| tiled.max_memory_bytes);
+                if let Some(limit) = limit.filter(|limit| bytes > *limit) {
+                    return Err(BokehRenderError::InvalidRequest(format!(
+                        "A {}x{} export needs {} bytes, more than the {} allowed",
+                        tile.width, tile.height, bytes, limit
+                    )));
+                }
+                self.image.insert(RgbaImage::new(tile.width, tile.height))
+            }
+        };
+
+        let content = decode_data_url(&tile.data_url)?;
+        let decoded = image::load_from_memory(&content)
+            .map_err(|e| BokehRenderError::Decode(e.to_string()))?
+            .to_rgba8();
+        image
+            .copy_from(&decoded, tile.x, tile.y)
+            .map_err(|e| BokehRenderError::Decode(e.to_string()))?;
+
+        let Some(timings) = tile.timings else {
+            return Ok(None);
+        };
+        let image = self.image.take().unwrap();
+        let mut bytes = Vec::new();
+        PngEncoder::new(&mut bytes)
+            .write_image(
+                image.as_raw(),
+                image.width(),
+                image.height(),
+                image::ExtendedColorType::Rgba8,
+            )
+            .map_err(|e| BokehRenderError::PostProcess(e.to_string()))?;
+        let output = RenderOutput {
+            bytes,
+            width: image.width(),
+            height: image.height(),
+            dpi,
+            format: RenderFormat::Image("image/png".to_string()),
+            timings,
+            data_images: Vec::new(),
+            console: Vec::new(),
+            thumbnail: None,
+        };
+        post_process(output, options).map(Some)
+    }
+}
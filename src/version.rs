@@ -0,0 +1,197 @@
+//! Bokeh releases, and what the render page needs of each: the bundles to load
+//! and whether its embed script works with the release at all.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::resources::{BokehResourceError, LOCAL_BUNDLES};
+
+/// The oldest release the render page works with. BokehJS before 2.4 resolves
+/// `embed_item` to a list of views rather than the `ViewManager` the page reads
+/// the root view from, so its renders never export.
+pub const MIN_SUPPORTED_VERSION: BokehVersion = BokehVersion::new(2, 4, 0);
+
+/// Bundles of Bokeh 2, which ships WebGL support in its own bundle; Bokeh 3
+/// merged it into `bokeh.min.js`.
+const BOKEH_2_BUNDLES: [&str; 4] = [
+    "bokeh.min.js",
+    "bokeh-gl.min.js",
+    "bokeh-mathjax.min.js",
+    "bokeh-api.min.js",
+];
+
+/// A Bokeh release such as `3.5.2`, or a pre-release such as `3.6.0.dev1` or
+/// `3.6.0rc1`. It becomes part of the CDN URLs and cache paths of its bundles.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BokehVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// The pre-release suffix as written, e.g. `.dev1` or `rc1`.
+    pub pre: Option<String>,
+}
+
+impl BokehVersion {
+    /// The release the render page loads from the CDN without a `BokehResource`.
+    pub const DEFAULT: BokehVersion = BokehVersion::new(3, 5, 2);
+
+    pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        BokehVersion {
+            major,
+            minor,
+            patch,
+            pre: None,
+        }
+    }
+
+    /// The bundles the page loads for this release, in load order, named as in
+    /// `LOCAL_BUNDLES`.
+    pub fn bundles(&self) -> &'static [&'static str] {
+        match self.major {
+            ..=2 => &BOKEH_2_BUNDLES,
+            _ => &LOCAL_BUNDLES,
+        }
+    }
+
+    /// Whether the render page works with this release, see
+    /// `MIN_SUPPORTED_VERSION`. Pre-releases of 2.4.0 count as 2.4.
+    pub fn is_supported(&self) -> bool {
+        (self.major, self.minor) >= (MIN_SUPPORTED_VERSION.major, MIN_SUPPORTED_VERSION.minor)
+    }
+
+    /// This release, or `BokehResourceError::UnsupportedVersion` if the page
+    /// would only render it blank.
+    pub(crate) fn supported(self) -> Result<Self, BokehResourceError> {
+        match self.is_supported() {
+            true => Ok(self),
+            false => Err(BokehResourceError::UnsupportedVersion(self)),
+        }
+    }
+}
+
+impl FromStr for BokehVersion {
+    type Err = BokehResourceError;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let invalid = || BokehResourceError::InvalidVersion(version.to_string());
+        let number = |part: &str| {
+            part.bytes()
+                .all(|b| b.is_ascii_digit())
+                .then(|| part.parse::<u64>().ok())
+                .flatten()
+                .ok_or_else(invalid)
+        };
+        let mut parts = version.splitn(3, '.');
+        let major = number(parts.next().unwrap_or_default())?;
+        let minor = number(parts.next().ok_or_else(invalid)?)?;
+        let rest = parts.next().ok_or_else(invalid)?;
+        // The patch number runs up to a pre-release such as `rc1` or `.dev1`.
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let patch = number(&rest[..digits])?;
+        let pre = &rest[digits..];
+        let pre_valid = pre.strip_prefix('.').unwrap_or(pre).split('.').all(|part| {
+            !part.is_empty() && part.bytes().all(|b| b.is_ascii_alphanumeric())
+        });
+        if !pre.is_empty() && !pre_valid {
+            return Err(invalid());
+        }
+        Ok(BokehVersion {
+            major,
+            minor,
+            patch,
+            pre: (!pre.is_empty()).then(|| pre.to_string()),
+        })
+    }
+}
+
+/// As Bokeh writes it, e.g. `3.5.2` or `3.6.0.dev1`.
+impl fmt::Display for BokehVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        match &self.pre {
+            Some(pre) => write!(f, "{}", pre),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The pre-release suffix `pre` as runs of non-digits, each with the number
+/// after it, so that `rc10` is compared as 10 and sorts after `rc2`.
+fn pre_release_key(pre: &str) -> Vec<(&str, u64)> {
+    let mut key = Vec::new();
+    let mut rest = pre;
+    while !rest.is_empty() {
+        let label = rest.bytes().take_while(|b| !b.is_ascii_digit()).count();
+        let digits = rest[label..].bytes().take_while(u8::is_ascii_digit).count();
+        let number = rest[label..label + digits].parse().unwrap_or(u64::MAX);
+        key.push((&rest[..label], number));
+        rest = &rest[label + digits..];
+    }
+    key
+}
+
+/// Releases in order, each after its pre-releases.
+impl Ord for BokehVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(pre), Some(other)) => pre_release_key(pre).cmp(&pre_release_key(other)),
+            })
+    }
+}
+
+impl PartialOrd for BokehVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> BokehVersion {
+        version.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_releases_and_pre_releases() {
+        assert_eq!(version("3.5.2"), BokehVersion::new(3, 5, 2));
+        for pre in [".dev1", "rc1", "a2", ".dev1.post3"] {
+            let parsed = version(&format!("3.6.0{}", pre));
+            assert_eq!(parsed.pre.as_deref(), Some(pre));
+            assert_eq!(parsed.to_string(), format!("3.6.0{}", pre));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_versions() {
+        let invalid = ["", "3", "3.5", "3.5.", "v3.5.2", "3.5.x", "3.-1.0", "3.5.2.", "3.5.2-rc1"];
+        for invalid in invalid {
+            assert!(invalid.parse::<BokehVersion>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn orders_pre_releases_before_their_release() {
+        let ordered = [
+            "2.4.3", "3.5.2", "3.6.0.dev1", "3.6.0.dev2", "3.6.0rc1", "3.6.0rc2", "3.6.0rc10",
+            "3.6.0", "3.10.0",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(version(pair[0]) < version(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn supports_2_4_and_its_pre_releases() {
+        assert!(version("2.4.0.dev1").is_supported());
+        assert!(version("2.4.3").is_supported());
+        assert!(!version("2.3.3").is_supported());
+    }
+}
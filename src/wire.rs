@@ -0,0 +1,178 @@
+//! The render request the crate's servers take as JSON: `http_server`'s
+//! `POST /render` body, an `ipc_server` request frame and the params of the
+//! CLI's JSON-RPC `render`.
+//!
+//! It's `{"document": ..., "dpi": 300, "format": "png", "options": {...}}`,
+//! where `document` is a `json_item`, as an object or as JSON text, and the
+//! rest is optional: `dpi` defaults to the server's, `format` is `png`,
+//! `jpeg`, `webp`, `pdf` or a MIME type, and `options` may set
+//!
+//! - `priority`: `background`, `normal` or `interactive`, see `Priority`;
+//! - `page_size`: `fit`, `a4`, `letter` or `{"width_mm", "height_mm"}`, for PDF;
+//! - `margins`: millimeters on every side or `{"top", "right", "bottom",
+//!   "left"}`, for PDF.
+//!
+//! Everything else about a render comes from the server's `RenderOptions`.
+
+use serde::Deserialize;
+use serde_json::Value;
+#[cfg(any(feature = "coordinator", feature = "ipc-server"))]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::options::{Dpi, Priority, RenderFormat, RenderOptions, RenderRequest};
+use crate::output::BokehRenderError;
+use crate::pdf::{Margins, PageSize};
+
+/// Frames longer than this close the connection.
+#[cfg(any(feature = "coordinator", feature = "ipc-server"))]
+pub const MAX_FRAME_BYTES: usize = 64 * 1024 * 1024;
+
+/// A render request as a server receives it.
+#[derive(Deserialize)]
+pub struct WireRequest {
+    document: Value,
+    #[serde(default)]
+    dpi: Option<f64>,
+    #[serde(default)]
+    format: Option<String>,
+    #[serde(default)]
+    options: WireOptions,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WireOptions {
+    #[serde(default)]
+    priority: Priority,
+    page_size: Option<WirePageSize>,
+    margins: Option<WireMargins>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WirePageSize {
+    Named(String),
+    Custom { width_mm: f64, height_mm: f64 },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WireMargins {
+    Uniform(f64),
+    Sides {
+        #[serde(default)]
+        top: f64,
+        #[serde(default)]
+        right: f64,
+        #[serde(default)]
+        bottom: f64,
+        #[serde(default)]
+        left: f64,
+    },
+}
+
+impl WireRequest {
+    /// The render the request asks for, at `default_dpi` unless it names one.
+    pub fn into_request(self, default_dpi: f64) -> Result<RenderRequest, BokehRenderError> {
+        let invalid = |message: String| Err(BokehRenderError::InvalidRequest(message));
+        let name = self.format.as_deref().unwrap_or("png");
+        let Some(mut format) = RenderFormat::from_name(name) else {
+            return invalid(format!("unsupported format {}", name));
+        };
+        let WireOptions {
+            priority,
+            page_size,
+            margins,
+        } = self.options;
+        match &mut format {
+            RenderFormat::Pdf {
+                page_size: pdf_page_size,
+                margins: pdf_margins,
+            } => {
+                match page_size {
+                    Some(WirePageSize::Named(name)) => match name.to_lowercase().as_str() {
+                        "fit" => *pdf_page_size = PageSize::Fit,
+                        "a4" => *pdf_page_size = PageSize::A4,
+                        "letter" => *pdf_page_size = PageSize::Letter,
+                        _ => return invalid(format!("unknown page size {}", name)),
+                    },
+                    Some(WirePageSize::Custom {
+                        width_mm,
+                        height_mm,
+                    }) => {
+                        *pdf_page_size = PageSize::Custom {
+                            width_mm,
+                            height_mm,
+                        }
+                    }
+                    None => (),
+                }
+                match margins {
+                    Some(WireMargins::Uniform(mm)) => *pdf_margins = Margins::uniform(mm),
+                    Some(WireMargins::Sides {
+                        top,
+                        right,
+                        bottom,
+                        left,
+                    }) => {
+                        *pdf_margins = Margins {
+                            top,
+                            right,
+                            bottom,
+                            left,
+                        }
+                    }
+                    None => (),
+                }
+            }
+            RenderFormat::Image(_) if page_size.is_some() || margins.is_some() => {
+                return invalid("page_size and margins only apply to PDF".to_string());
+            }
+            RenderFormat::Image(_) => (),
+        }
+        let json_data = match self.document {
+            Value::String(json_data) => json_data,
+            document => document.to_string(),
+        };
+        Ok(RenderRequest {
+            json_data,
+            dpi: Dpi::new(self.dpi.unwrap_or(default_dpi)),
+            format,
+            resource: None,
+            options: RenderOptions::default(),
+            priority,
+        })
+    }
+}
+
+/// The next frame, or `None` once the peer has closed the connection.
+#[cfg(any(feature = "coordinator", feature = "ipc-server"))]
+pub(crate) async fn read_frame(
+    stream: &mut (impl AsyncRead + Unpin),
+) -> std::io::Result<Option<Vec<u8>>> {
+    let mut length = [0; 4];
+    match stream.read_exact(&mut length).await {
+        Ok(_) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME_BYTES {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("a frame of {} bytes is over the limit", length),
+        ));
+    }
+    let mut frame = vec![0; length];
+    stream.read_exact(&mut frame).await?;
+    Ok(Some(frame))
+}
+
+#[cfg(any(feature = "coordinator", feature = "ipc-server"))]
+pub(crate) async fn write_frame(
+    stream: &mut (impl AsyncWrite + Unpin),
+    frame: &[u8],
+) -> std::io::Result<()> {
+    stream.write_all(&(frame.len() as u32).to_be_bytes()).await?;
+    stream.write_all(frame).await
+}
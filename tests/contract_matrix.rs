@@ -0,0 +1,64 @@
+//! Check the render page against several BokehJS releases and report which
+//! APIs or fixtures broke. Each argument is a CDN version (`3.4.3`) or a local
+//! bundle folder (`local:/path/to/bokeh/dist`), defaulting to the supported releases.
+//! It needs a display and, for CDN versions, the network, so it only runs with
+//! its feature:
+//!
+//!     cargo test --features contract-checks --test contract_matrix -- 3.5.2 3.6.0.dev1
+
+use std::process::ExitCode;
+
+use wry_bokeh_helper::contract::check_bokeh_version;
+use wry_bokeh_helper::resources::{BokehResource, BokehResourceError};
+
+const SUPPORTED_VERSIONS: [&str; 3] = ["3.3.4", "3.4.3", "3.5.2"];
+
+fn resource(arg: &str) -> Result<BokehResource, BokehResourceError> {
+    match arg.strip_prefix("local:") {
+        Some(folder) => BokehResource::local(folder),
+        None => BokehResource::cdn(arg),
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        args = SUPPORTED_VERSIONS.iter().map(|version| version.to_string()).collect();
+    }
+
+    let mut passed = true;
+    for arg in &args {
+        let resource = match resource(arg) {
+            Ok(resource) => resource,
+            Err(e) => {
+                println!("{}: {}", arg, e);
+                passed = false;
+                continue;
+            }
+        };
+        let report = check_bokeh_version(resource).await;
+        println!("{}: {}", arg, if report.passed() { "ok" } else { "FAILED" });
+        match &report.api {
+            Ok(checks) => {
+                for check in checks.iter().filter(|check| !check.ok) {
+                    let error = check.error.as_deref().unwrap_or("");
+                    println!("    api {}: {}", check.name, error);
+                }
+            }
+            Err(e) => println!("    api probe: {}", e),
+        }
+        for (name, result) in &report.fixtures {
+            if let Err(e) = result {
+                println!("    fixture {}: {}", name, e);
+            }
+        }
+        passed &= report.passed();
+    }
+
+    if passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
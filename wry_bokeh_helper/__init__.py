@@ -1,5 +1,8 @@
-from .bokeh_io import bokeh_to_image
+from .bokeh_io import bokeh_to_html, bokeh_to_image, bokeh_to_pdf, bokeh_to_pdf_report
 
 __all__ = [
+    "bokeh_to_html",
     "bokeh_to_image",
+    "bokeh_to_pdf",
+    "bokeh_to_pdf_report",
 ]
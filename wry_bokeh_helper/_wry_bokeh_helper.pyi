@@ -1,12 +1,53 @@
 from typing import Literal
 
-ResourceType = Literal["cdn", "local"]
+ResourceType = Literal["cdn", "local", "archive"]
 
 def render_bokeh(
     json_data: str,
-    dpi: int = 300,
+    dpi: float = 300,
     typ: str = "image/png",
     resource: tuple[ResourceType, str] | None = None,
+    reduced_motion: bool = False,
+    page_size: tuple[float, float] | None = None,
+    margins: tuple[float, float, float, float] = (0.0, 0.0, 0.0, 0.0),
+    debug: bool = False,
 ) -> str:
-    """Render Bokeh JSON to a image URL."""
+    """Render Bokeh JSON to a image URL, or a PDF URL for `typ="application/pdf"`.
+
+    With `debug`, the render window is shown with DevTools and the call only
+    returns once it is closed.
+    """
+    ...
+
+def render(
+    json_data: str,
+    dpi: float = 300,
+    typ: str = "image/png",
+    resource: tuple[ResourceType, str] | None = None,
+    reduced_motion: bool = False,
+    page_size: tuple[float, float] | None = None,
+    margins: tuple[float, float, float, float] = (0.0, 0.0, 0.0, 0.0),
+) -> bytes:
+    """Render Bokeh JSON like `render_bokeh`, returning the image or PDF bytes.
+
+    The GIL is released while rendering, so other threads keep running.
+    """
+    ...
+
+def export_bokeh_html(
+    json_data: str,
+    resource: tuple[ResourceType, str] | None = None,
+) -> str:
+    """Build a self-contained interactive HTML page from Bokeh JSON."""
+    ...
+
+def render_bokeh_report(
+    json_items: list[str],
+    page_size: tuple[float, float] | None = None,
+    margins: tuple[float, float, float, float] = (0.0, 0.0, 0.0, 0.0),
+    columns: int = 1,
+    rows: int = 1,
+    resource: tuple[ResourceType, str] | None = None,
+) -> bytes:
+    """Print several Bokeh JSON documents into one multi-page PDF."""
     ...
@@ -6,11 +6,17 @@ import os
 import pathlib
 import sys
 import urllib.request
-from typing import TYPE_CHECKING, Any, overload
+from typing import TYPE_CHECKING, Any, Callable, TypeVar, overload
 
 from PIL import Image
 
-from wry_bokeh_helper._wry_bokeh_helper import render_bokeh
+from wry_bokeh_helper._wry_bokeh_helper import (
+    export_bokeh_html,
+    render_bokeh,
+    render_bokeh_report,
+)
+
+T = TypeVar("T")
 
 if TYPE_CHECKING:
     from multiprocessing import Queue
@@ -27,12 +33,34 @@ if TYPE_CHECKING:
 
     BokehFigureOrStandaloneJson = Model | StandaloneEmbedJson
 
+PAGE_SIZES_MM: dict[str, tuple[float, float]] = {
+    "a4": (210.0, 297.0),
+    "letter": (215.9, 279.4),
+}
+
+
+def _to_bokeh_json_item(
+    bokeh_figure_or_bokeh_standalone_json: BokehFigureOrStandaloneJson,
+) -> dict[str, Any]:
+    if isinstance(bokeh_figure_or_bokeh_standalone_json, dict):
+        return bokeh_figure_or_bokeh_standalone_json
+
+    try:
+        from bokeh.embed.standalone import json_item
+        from bokeh.models import Model
+    except ImportError:
+        raise ImportError("bokeh is not installed.")
+    if not isinstance(bokeh_figure_or_bokeh_standalone_json, Model):
+        raise TypeError("bokeh_figure_or_bokeh_standalone_json must be a Bokeh Model.")
+    return dict(json_item(bokeh_figure_or_bokeh_standalone_json))
+
 
 def _render_bokeh(
     bokeh_json_item: dict[str, Any],
-    dpi: int,
+    dpi: float,
     typ: str,
     resource: tuple[ResourceType, str] | None,
+    options: dict[str, Any],
 ) -> str:
     try:
         return render_bokeh(
@@ -40,53 +68,28 @@ def _render_bokeh(
             dpi=dpi,
             typ=typ,
             resource=resource,
+            **options,
         )
     except BaseException as e:
         raise e
 
 
-def _run_in_process(
-    queue: Queue,
-    bokeh_json_item: dict[str, Any],
-    dpi: int,
-    typ: str,
-    resource: tuple[ResourceType, str] | None,
-):
+def _run_in_process(queue: Queue, target: Callable[..., Any], *args: Any):
     try:
-        data_url = _render_bokeh(
-            bokeh_json_item,
-            dpi,
-            typ,
-            resource,
-        )
-        queue.put(data_url)
+        queue.put(target(*args))
     except BaseException as e:
         queue.put(e)
 
 
-def _get_img_data_url_in_subprocess(
-    bokeh_json_item: dict[str, Any],
-    dpi: int,
-    typ: str,
-    resource: tuple[ResourceType, str] | None,
-) -> str:
+def _call_in_subprocess(target: Callable[..., T], *args: Any) -> T:
     from multiprocessing import Process, Queue, freeze_support
     from multiprocessing.process import current_process
     from queue import Empty
 
     if getattr(current_process(), "_inheriting", False):
         freeze_support()
-    queue: Queue[str | BaseException] = Queue()
-    process = Process(
-        target=_run_in_process,
-        args=(
-            queue,
-            bokeh_json_item,
-            dpi,
-            typ,
-            resource,
-        ),
-    )
+    queue: Queue[T | BaseException] = Queue()
+    process = Process(target=_run_in_process, args=(queue, target, *args))
     process.start()
 
     try:
@@ -98,13 +101,21 @@ def _get_img_data_url_in_subprocess(
     return result
 
 
+def _call_webview(target: Callable[..., T], *args: Any) -> T:
+    # tao event loops must run on the main thread on macOS, so render in a fresh process.
+    if sys.platform == "darwin":
+        return _call_in_subprocess(target, *args)
+    return target(*args)
+
+
 @overload
 def bokeh_to_image(
     bokeh_figure_or_bokeh_standalone_json: BokehFigureOrStandaloneJson,
     *,
-    dpi: int = 300,
+    dpi: float = 300,
     typ: str = "image/png",
     resource: tuple[ResourceType, str] | None = None,
+    reduced_motion: bool = False,
 ) -> Image.Image:
     """
     Converts a Bokeh figure or standalone JSON to an image.
@@ -112,10 +123,13 @@ def bokeh_to_image(
     Args:
         bokeh_figure_or_bokeh_standalone_json (BokehFigureOrStandaloneJson):
             The Bokeh figure or standalone JSON to convert.
-        dpi (int, optional):
+        dpi (float, optional):
             The resolution of the image in dots per inch. Default is 300.
         resource (tuple[ResourceType, str] | None, optional):
             Additional resources required for the conversion. Defaults to None.
+        reduced_motion (bool, optional):
+            Disable animations and transitions in the page before exporting.
+            Defaults to False.
     Returns:
         Image.Image: The resulting image.
     """
@@ -127,9 +141,10 @@ def bokeh_to_image(
     bokeh_figure_or_bokeh_standalone_json: BokehFigureOrStandaloneJson,
     filepath: os.PathLike[str] | str,
     *,
-    dpi: int = 300,
+    dpi: float = 300,
     typ: str = "image/png",
     resource: tuple[ResourceType, str] | None = None,
+    reduced_motion: bool = False,
 ) -> None:
     """
     Save a Bokeh plot to a specified file path.
@@ -139,10 +154,13 @@ def bokeh_to_image(
             The Bokeh figure or standalone JSON to be saved as an image.
         filepath (os.PathLike[str] | str):
             The file path where the image will be saved.
-        dpi (int, optional):
+        dpi (float, optional):
             The resolution of the saved image in dots per inch. Default is 300.
         resource (tuple[ResourceType, str] | None, optional):
             Additional resources required for saving the image. Default is None.
+        reduced_motion (bool, optional):
+            Disable animations and transitions in the page before exporting.
+            Default is False.
 
     Returns:
         None
@@ -154,35 +172,20 @@ def bokeh_to_image(
     bokeh_figure_or_bokeh_standalone_json: BokehFigureOrStandaloneJson,
     filepath: os.PathLike[str] | str | None = None,
     *,
-    dpi: int = 300,
+    dpi: float = 300,
     typ: str = "image/png",
     resource: tuple[ResourceType, str] | None = None,
+    reduced_motion: bool = False,
 ) -> Image.Image | None:
     if typ not in ["image/jpeg", "image/png", "image/webp"]:
         raise ValueError(
             "Invalid `toDataURL` type value. See https://developer.mozilla.org/en-US/docs/Web/API/HTMLCanvasElement/toDataURL for more details."
         )
-    if isinstance(bokeh_figure_or_bokeh_standalone_json, dict):
-        bokeh_json_item = bokeh_figure_or_bokeh_standalone_json
-
-    else:
-        try:
-            from bokeh.embed.standalone import json_item
-            from bokeh.models import Model
-        except ImportError:
-            raise ImportError("bokeh is not installed.")
-        if not isinstance(bokeh_figure_or_bokeh_standalone_json, Model):
-            raise TypeError(
-                "bokeh_figure_or_bokeh_standalone_json must be a Bokeh Model."
-            )
-        bokeh_json_item = json_item(bokeh_figure_or_bokeh_standalone_json)
-    is_MacOS = sys.platform == "darwin"
-    if is_MacOS:
-        img_data_url = _get_img_data_url_in_subprocess(
-            {**bokeh_json_item}, dpi, typ, resource
-        )
-    else:
-        img_data_url = _render_bokeh({**bokeh_json_item}, dpi, typ, resource)
+    bokeh_json_item = _to_bokeh_json_item(bokeh_figure_or_bokeh_standalone_json)
+    options: dict[str, Any] = {"reduced_motion": reduced_motion}
+    img_data_url = _call_webview(
+        _render_bokeh, {**bokeh_json_item}, dpi, typ, resource, options
+    )
     response = urllib.request.urlopen(img_data_url)
     img = Image.open(io.BytesIO(response.read()))
 
@@ -195,3 +198,155 @@ def bokeh_to_image(
             img = img.convert("RGB")
         return img.save(filepath, dpi=(dpi, dpi))
     return img
+
+
+def bokeh_to_html(
+    bokeh_figure_or_bokeh_standalone_json: BokehFigureOrStandaloneJson,
+    filepath: os.PathLike[str] | str | None = None,
+    *,
+    resource: tuple[ResourceType, str] | None = None,
+) -> str:
+    """
+    Export a Bokeh figure or standalone JSON to a self-contained HTML page.
+
+    Local resources are inlined into the page, so the result can be opened
+    without access to the resource folder.
+
+    Parameters:
+        bokeh_figure_or_bokeh_standalone_json (BokehFigureOrStandaloneJson):
+            The Bokeh figure or standalone JSON to export.
+        filepath (os.PathLike[str] | str | None, optional):
+            If given, the HTML is also written to this path. Default is None.
+        resource (tuple[ResourceType, str] | None, optional):
+            The Bokeh resources to embed in the page. Default is None.
+
+    Returns:
+        str: The HTML document.
+    """
+    bokeh_json_item = _to_bokeh_json_item(bokeh_figure_or_bokeh_standalone_json)
+    html = export_bokeh_html(json_data=json.dumps(bokeh_json_item), resource=resource)
+    if filepath:
+        pathlib.Path(filepath).write_text(html, encoding="utf-8")
+    return html
+
+
+def _parse_page_layout(
+    page_size: str | tuple[float, float],
+    margins: float | tuple[float, float, float, float],
+) -> tuple[tuple[float, float] | None, tuple[float, float, float, float]]:
+    if isinstance(page_size, str):
+        if page_size != "fit" and page_size not in PAGE_SIZES_MM:
+            raise ValueError(f"Invalid page size: {page_size}")
+        page_size_mm = PAGE_SIZES_MM.get(page_size)
+    else:
+        page_size_mm = page_size
+    if isinstance(margins, (int, float)):
+        margins = (margins, margins, margins, margins)
+    return page_size_mm, margins
+
+
+def bokeh_to_pdf(
+    bokeh_figure_or_bokeh_standalone_json: BokehFigureOrStandaloneJson,
+    filepath: os.PathLike[str] | str | None = None,
+    *,
+    page_size: str | tuple[float, float] = "fit",
+    margins: float | tuple[float, float, float, float] = 0.0,
+    resource: tuple[ResourceType, str] | None = None,
+) -> bytes:
+    """
+    Print a Bokeh figure or standalone JSON to a vector PDF.
+
+    Plots are switched to the SVG output backend before printing, so lines and
+    text stay vector in the resulting document.
+
+    Parameters:
+        bokeh_figure_or_bokeh_standalone_json (BokehFigureOrStandaloneJson):
+            The Bokeh figure or standalone JSON to print.
+        filepath (os.PathLike[str] | str | None, optional):
+            If given, the PDF is also written to this path. Default is None.
+        page_size (str | tuple[float, float], optional):
+            "fit" to size the page to the plot, "a4", "letter", or a
+            (width, height) tuple in millimeters. Default is "fit".
+        margins (float | tuple[float, float, float, float], optional):
+            Page margins in millimeters, either uniform or as
+            (top, right, bottom, left). Default is 0.
+        resource (tuple[ResourceType, str] | None, optional):
+            Additional resources required for the conversion. Default is None.
+
+    Returns:
+        bytes: The PDF document.
+    """
+    page_size_mm, margins = _parse_page_layout(page_size, margins)
+    bokeh_json_item = _to_bokeh_json_item(bokeh_figure_or_bokeh_standalone_json)
+    options: dict[str, Any] = {"page_size": page_size_mm, "margins": margins}
+    pdf_data_url = _call_webview(
+        _render_bokeh, {**bokeh_json_item}, 96, "application/pdf", resource, options
+    )
+    content = urllib.request.urlopen(pdf_data_url).read()
+
+    if filepath:
+        pathlib.Path(filepath).write_bytes(content)
+    return content
+
+
+def _render_bokeh_report(
+    bokeh_json_items: list[dict[str, Any]],
+    resource: tuple[ResourceType, str] | None,
+    options: dict[str, Any],
+) -> bytes:
+    return render_bokeh_report(
+        json_items=[json.dumps(item) for item in bokeh_json_items],
+        resource=resource,
+        **options,
+    )
+
+
+def bokeh_to_pdf_report(
+    bokeh_figures_or_bokeh_standalone_jsons: list[BokehFigureOrStandaloneJson],
+    filepath: os.PathLike[str] | str | None = None,
+    *,
+    page_size: str | tuple[float, float] = "fit",
+    margins: float | tuple[float, float, float, float] = 0.0,
+    columns: int = 1,
+    rows: int = 1,
+    resource: tuple[ResourceType, str] | None = None,
+) -> bytes:
+    """
+    Print several Bokeh figures or standalone JSONs into one multi-page PDF.
+
+    Parameters:
+        bokeh_figures_or_bokeh_standalone_jsons (list[BokehFigureOrStandaloneJson]):
+            The Bokeh figures or standalone JSONs, in page order.
+        filepath (os.PathLike[str] | str | None, optional):
+            If given, the PDF is also written to this path. Default is None.
+        page_size (str | tuple[float, float], optional):
+            "fit" to size pages to the largest plot, "a4", "letter", or a
+            (width, height) tuple in millimeters. Default is "fit".
+        margins (float | tuple[float, float, float, float], optional):
+            Page margins in millimeters, either uniform or as
+            (top, right, bottom, left). Default is 0.
+        columns (int, optional):
+            Number of grid columns per page. Default is 1.
+        rows (int, optional):
+            Number of grid rows per page. Default is 1.
+        resource (tuple[ResourceType, str] | None, optional):
+            Additional resources required for the conversion. Default is None.
+
+    Returns:
+        bytes: The PDF document.
+    """
+    page_size_mm, margins = _parse_page_layout(page_size, margins)
+    bokeh_json_items = [
+        _to_bokeh_json_item(item) for item in bokeh_figures_or_bokeh_standalone_jsons
+    ]
+    options: dict[str, Any] = {
+        "page_size": page_size_mm,
+        "margins": margins,
+        "columns": columns,
+        "rows": rows,
+    }
+    content = _call_webview(_render_bokeh_report, bokeh_json_items, resource, options)
+
+    if filepath:
+        pathlib.Path(filepath).write_bytes(content)
+    return content